@@ -1,4 +1,5 @@
 "use client";
+import CodeEditor, { EditorService } from "@/components/CodeEditor";
 import Navbar from "@/components/Navbar";
 import { ToggleGroup, ToggleGroupItem } from "@/components/ui/toggle-group";
 import dynamic from "next/dynamic";
@@ -7,9 +8,11 @@ import { useState } from "react";
 
 type HomeProps = {
   wasmFn: (input: string) => string;
+  formatFn: (input: string) => string;
+  editorService: EditorService;
 };
 
-function Home({ wasmFn }: HomeProps) {
+function Home({ wasmFn, formatFn, editorService }: HomeProps) {
   const [inputCode, setInputCode] = useState("");
   const [evaluatedCode, setEvaluatedCode] = useState("");
   const [showTerminal, setShowTerminal] = useState(false);
@@ -24,6 +27,15 @@ function Home({ wasmFn }: HomeProps) {
     setShowTerminal(true);
   };
 
+  const format = () => {
+    try {
+      setInputCode(formatFn(inputCode));
+    } catch (e) {
+      setEvaluatedCode(String(e));
+      setShowTerminal(true);
+    }
+  };
+
   return (
     <div className="bg-primary w-screen min-h-screen text-primary">
       <div className="container max-w-screen-lg flex flex-col items-center px-4">
@@ -74,11 +86,11 @@ function Home({ wasmFn }: HomeProps) {
             className={`${showTerminal ? "hidden" : "flex"} md:flex flex-col flex-grow w-full`}
           >
             <p className="hidden md:block p-4 border-b border-primary">Input</p>
-            <textarea
-              className="bg-secondary dark:bg-primary p-4 border-b border-primary flex-grow w-full focus:outline-none"
+            <CodeEditor
               value={inputCode}
-              onChange={(e) => setInputCode(e.target.value)}
-            ></textarea>
+              onChange={setInputCode}
+              service={editorService}
+            />
             <div className="hidden md:flex items-center justify-between p-4">
               <p className="text-placeholder">
                 Made with ♥️ by{" "}
@@ -90,21 +102,29 @@ function Home({ wasmFn }: HomeProps) {
                   Mehul
                 </Link>
               </p>
-              <button
-                className="flex gap-1 bg-primary-accent px-4 py-2 rounded justify-center items-center text-white dark:text-cta-text"
-                onClick={evaluate}
-              >
-                Run
-                <svg
-                  xmlns="http://www.w3.org/2000/svg"
-                  width="16"
-                  height="16"
-                  className="fill-current"
-                  viewBox="0 0 256 256"
+              <div className="flex gap-2">
+                <button
+                  className="px-4 py-2 rounded border border-primary"
+                  onClick={format}
+                >
+                  Format
+                </button>
+                <button
+                  className="flex gap-1 bg-primary-accent px-4 py-2 rounded justify-center items-center text-white dark:text-cta-text"
+                  onClick={evaluate}
                 >
-                  <path d="M234.49,111.07,90.41,22.94A20,20,0,0,0,60,39.87V216.13a20,20,0,0,0,30.41,16.93l144.08-88.13a19.82,19.82,0,0,0,0-33.86ZM84,208.85V47.15L216.16,128Z"></path>
-                </svg>
-              </button>
+                  Run
+                  <svg
+                    xmlns="http://www.w3.org/2000/svg"
+                    width="16"
+                    height="16"
+                    className="fill-current"
+                    viewBox="0 0 256 256"
+                  >
+                    <path d="M234.49,111.07,90.41,22.94A20,20,0,0,0,60,39.87V216.13a20,20,0,0,0,30.41,16.93l144.08-88.13a19.82,19.82,0,0,0,0-33.86ZM84,208.85V47.15L216.16,128Z"></path>
+                  </svg>
+                </button>
+              </div>
             </div>
           </div>
 
@@ -126,7 +146,34 @@ const DynamicComponent = dynamic(
   async () => {
     const wasmModule = await import("monkey_interpreter");
 
-    const HomeWrapper = () => <Home wasmFn={wasmModule.interpret} />;
+    // Keep runaway programs from freezing or crashing the tab. The call depth
+    // has to fit in the 8 MB stack the wasm build is linked with.
+    const interpret = (input: string) =>
+      wasmModule.interpret_with_limits(
+        input,
+        undefined,
+        1000,
+        5000,
+        64 * 1024 * 1024,
+        undefined,
+      );
+
+    const editorService: EditorService = {
+      semanticTokens: (input) => JSON.parse(wasmModule.semantic_tokens(input)),
+      diagnostics: (input) => JSON.parse(wasmModule.diagnostics(input)),
+      completions: (input, offset) =>
+        JSON.parse(wasmModule.completions(input, offset)),
+      hover: wasmModule.hover,
+      matchingBracket: wasmModule.matching_bracket,
+    };
+
+    const HomeWrapper = () => (
+      <Home
+        wasmFn={interpret}
+        formatFn={wasmModule.format_code}
+        editorService={editorService}
+      />
+    );
 
     return HomeWrapper;
   },
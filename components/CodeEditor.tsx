@@ -0,0 +1,212 @@
+"use client";
+
+import { KeyboardEvent, ReactNode, useMemo, useRef, useState } from "react";
+
+export type SemanticToken = {
+  start: number;
+  end: number;
+  kind: string;
+};
+
+export type Diagnostic = {
+  start: number;
+  end: number;
+  line: number;
+  column: number;
+  severity: "error" | "warning";
+  message: string;
+  rule: string | null;
+};
+
+export type Completion = {
+  label: string;
+  kind: "binding" | "function" | "builtin" | "keyword";
+  detail: string;
+};
+
+// Offsets are UTF-16 code units, the same as JavaScript string indices.
+export type EditorService = {
+  semanticTokens: (input: string) => SemanticToken[];
+  diagnostics: (input: string) => Diagnostic[];
+  completions: (input: string, offset: number) => Completion[];
+  hover: (input: string, offset: number) => string | undefined;
+  matchingBracket: (input: string, offset: number) => number | undefined;
+};
+
+type CodeEditorProps = {
+  value: string;
+  onChange: (value: string) => void;
+  service: EditorService;
+};
+
+const TOKEN_CLASSES: Record<string, string> = {
+  keyword: "text-primary-accent",
+  builtin: "text-sky-600 dark:text-sky-400",
+  number: "text-orange-600 dark:text-orange-400",
+  string: "text-green-700 dark:text-green-400",
+  comment: "text-placeholder italic",
+  invalid: "text-red-600 underline decoration-wavy",
+};
+
+const MAX_COMPLETIONS = 8;
+
+export default function CodeEditor({
+  value,
+  onChange,
+  service,
+}: CodeEditorProps) {
+  const textareaRef = useRef<HTMLTextAreaElement>(null);
+  const highlightRef = useRef<HTMLPreElement>(null);
+  const [cursor, setCursor] = useState(0);
+
+  const tokens = useMemo(() => service.semanticTokens(value), [service, value]);
+  const diagnostics = useMemo(
+    () => service.diagnostics(value),
+    [service, value],
+  );
+
+  const wordStart = useMemo(() => {
+    let start = cursor;
+    while (start > 0 && /[A-Za-z_]/.test(value[start - 1])) start--;
+    return start;
+  }, [value, cursor]);
+
+  const completions = useMemo(
+    () =>
+      wordStart < cursor
+        ? service
+            .completions(value, cursor)
+            .filter((c) => c.label !== value.slice(wordStart, cursor))
+            .slice(0, MAX_COMPLETIONS)
+        : [],
+    [service, value, cursor, wordStart],
+  );
+  const hover = useMemo(
+    () => service.hover(value, cursor),
+    [service, value, cursor],
+  );
+  const brackets = useMemo(() => {
+    const match = service.matchingBracket(value, cursor);
+    if (match === undefined) return [];
+    const atCursor = "()[]{}".includes(value[cursor] ?? " ");
+    return [atCursor ? cursor : cursor - 1, match];
+  }, [service, value, cursor]);
+
+  const highlighted = useMemo(() => {
+    const nodes: ReactNode[] = [];
+    let pos = 0;
+    tokens.forEach((token, i) => {
+      if (token.start > pos) nodes.push(value.slice(pos, token.start));
+      const matched = brackets.includes(token.start)
+        ? " underline decoration-2 decoration-primary-accent"
+        : "";
+      nodes.push(
+        <span key={i} className={(TOKEN_CLASSES[token.kind] ?? "") + matched}>
+          {value.slice(token.start, token.end)}
+        </span>,
+      );
+      pos = Math.max(pos, token.end);
+    });
+    // A trailing newline needs content after it to take up a line.
+    nodes.push(value.slice(pos) + "\n");
+    return nodes;
+  }, [tokens, value, brackets]);
+
+  const updateCursor = () =>
+    setCursor(textareaRef.current?.selectionStart ?? 0);
+
+  const complete = (label: string) => {
+    const next = value.slice(0, wordStart) + label + value.slice(cursor);
+    const offset = wordStart + label.length;
+    onChange(next);
+    setCursor(offset);
+    requestAnimationFrame(() => {
+      textareaRef.current?.focus();
+      textareaRef.current?.setSelectionRange(offset, offset);
+    });
+  };
+
+  const onKeyDown = (e: KeyboardEvent<HTMLTextAreaElement>) => {
+    if (e.key === "Tab" && completions.length > 0) {
+      e.preventDefault();
+      complete(completions[0].label);
+    }
+  };
+
+  const syncScroll = () => {
+    if (highlightRef.current && textareaRef.current) {
+      highlightRef.current.scrollTop = textareaRef.current.scrollTop;
+      highlightRef.current.scrollLeft = textareaRef.current.scrollLeft;
+    }
+  };
+
+  return (
+    <div className="flex flex-col flex-grow w-full bg-secondary dark:bg-primary border-b border-primary">
+      <div className="relative flex-grow min-h-[256px] font-mono text-sm">
+        <pre
+          ref={highlightRef}
+          aria-hidden
+          className="absolute inset-0 m-0 p-4 overflow-hidden whitespace-pre-wrap break-words pointer-events-none"
+        >
+          {highlighted}
+        </pre>
+        <textarea
+          ref={textareaRef}
+          spellCheck={false}
+          className="absolute inset-0 p-4 w-full h-full resize-none bg-transparent text-transparent focus:outline-none whitespace-pre-wrap break-words"
+          style={{ caretColor: "var(--color-text)" }}
+          value={value}
+          onChange={(e) => {
+            onChange(e.target.value);
+            setCursor(e.target.selectionStart);
+          }}
+          onSelect={updateCursor}
+          onKeyDown={onKeyDown}
+          onScroll={syncScroll}
+        ></textarea>
+      </div>
+
+      {completions.length > 0 && (
+        <div className="flex flex-wrap gap-1 px-4 py-2 border-t border-primary font-mono text-sm">
+          {completions.map((c) => (
+            <button
+              key={c.label}
+              title={c.detail}
+              className="px-2 rounded border border-primary hover:bg-hover"
+              onMouseDown={(e) => {
+                e.preventDefault();
+                complete(c.label);
+              }}
+            >
+              {c.label}
+            </button>
+          ))}
+        </div>
+      )}
+
+      {hover && (
+        <p className="px-4 py-2 border-t border-primary font-mono text-sm text-placeholder">
+          {hover}
+        </p>
+      )}
+
+      {diagnostics.length > 0 && (
+        <ul className="px-4 py-2 border-t border-primary font-mono text-sm">
+          {diagnostics.map((d, i) => (
+            <li
+              key={i}
+              className={
+                d.severity === "error"
+                  ? "text-red-600"
+                  : "text-yellow-700 dark:text-yellow-400"
+              }
+            >
+              {d.line}:{d.column} {d.message}
+              {d.rule && ` (${d.rule})`}
+            </li>
+          ))}
+        </ul>
+      )}
+    </div>
+  );
+}
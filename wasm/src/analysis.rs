@@ -0,0 +1,764 @@
+//! Static facts about a parsed program for editors: where each binding is
+//! defined, which identifiers refer to it and what it holds.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::{
+    ast::{
+        BlockStatement, ElseBranch, ExportStatement, Identifier, IfExpression, MatchExpression,
+        Node, Pattern, Program, Statement, EXPRESSION,
+    },
+    complete::{word_start, CompletionKind},
+    lexer::Lexer,
+    lint::lint,
+    object::{ObjectType, BUILTINS},
+    parser::{ParseError, Parser},
+    prelude,
+    token::{Span, KEYWORDS},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DefinitionKind {
+    Let,
+    Param,
+    Import,
+    /// Bound by the pattern of a `match` arm.
+    Match,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Definition {
+    pub name: String,
+    pub kind: DefinitionKind,
+    /// The span of the name where it is bound.
+    pub span: Span,
+    /// Byte range the binding is visible in: its function, its `match` arm,
+    /// or the whole program.
+    pub scope: (usize, usize),
+    /// Whether it is bound by a statement of the program itself.
+    pub top_level: bool,
+    /// The parameter names when it is bound to a function literal.
+    pub params: Option<Vec<String>>,
+    /// The type of the value it is bound to, when that is known statically.
+    pub r#type: Option<ObjectType>,
+    /// How an editor describes it, e.g. `let add = fn(a, b)`.
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reference {
+    pub name: String,
+    pub span: Span,
+    /// Index into `Analysis::definitions`, `None` for builtins, prelude
+    /// functions and unbound names.
+    pub definition: Option<usize>,
+}
+
+#[derive(Debug, Default)]
+pub struct Analysis {
+    pub definitions: Vec<Definition>,
+    pub references: Vec<Reference>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub span: Span,
+    /// The lint rule that reported it, `None` for parse errors.
+    pub rule: Option<&'static str>,
+}
+
+pub fn parse(source: &str) -> Result<Program, Vec<ParseError>> {
+    let mut l = Lexer::new(source.to_string());
+    let mut p = Parser::new(&mut l);
+    let program = p.parse_program();
+
+    match program {
+        Some(program) if p.parse_errors().is_empty() => Ok(program),
+        _ => Err(p.parse_errors().to_vec()),
+    }
+}
+
+/// The parse errors of `source`, or the lint warnings if it parses.
+pub fn diagnostics(source: &str) -> Vec<Diagnostic> {
+    match lint(source, &[]) {
+        Ok(warnings) => warnings
+            .into_iter()
+            .map(|w| Diagnostic {
+                severity: Severity::Warning,
+                message: w.message,
+                span: w.span,
+                rule: Some(w.rule),
+            })
+            .collect(),
+        Err(_) => parse(source)
+            .err()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|e| Diagnostic {
+                severity: Severity::Error,
+                message: e.message,
+                span: e.span,
+                rule: None,
+            })
+            .collect(),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CompletionItem {
+    pub label: String,
+    pub kind: CompletionKind,
+    pub detail: String,
+}
+
+/// Bindings in scope, prelude functions, builtins and keywords starting
+/// with the word that ends at byte `offset`, sorted by label. Bindings are
+/// left out while `source` doesn't parse.
+pub fn completions(source: &str, offset: usize) -> Vec<CompletionItem> {
+    let offset = offset.min(source.len());
+    let prefix = &source[word_start(source, offset)..offset];
+
+    let mut items = vec![];
+    if let Ok(program) = parse(source) {
+        for d in analyze(&program).visible_at(offset) {
+            let kind = match d.params {
+                Some(_) => CompletionKind::Function,
+                None => CompletionKind::Binding,
+            };
+            items.push((d.name.clone(), kind, d.detail.clone()));
+        }
+    }
+    for (name, params) in prelude_functions() {
+        let detail = format!("prelude fn {}({})", name, params.join(", "));
+        items.push((name, CompletionKind::Function, detail));
+    }
+    for name in BUILTINS.keys() {
+        items.push((
+            name.to_string(),
+            CompletionKind::Builtin,
+            "builtin".to_string(),
+        ));
+    }
+    for name in KEYWORDS.keys() {
+        items.push((
+            name.to_string(),
+            CompletionKind::Keyword,
+            "keyword".to_string(),
+        ));
+    }
+
+    // Earlier items shadow later ones with the same name.
+    let mut seen = vec![];
+    items.retain(|(name, _, _)| {
+        let new = name.starts_with(prefix) && !seen.contains(name);
+        seen.push(name.clone());
+        new
+    });
+
+    let mut items: Vec<CompletionItem> = items
+        .into_iter()
+        .map(|(label, kind, detail)| CompletionItem {
+            label,
+            kind,
+            detail,
+        })
+        .collect();
+    items.sort_by(|a, b| a.label.cmp(&b.label));
+    items
+}
+
+/// Binds every identifier of `program` the way the resolver does: `let`
+/// bindings are visible in their whole function, inner functions see the
+/// bindings of the outer ones.
+pub fn analyze(program: &Program) -> Analysis {
+    let mut analyzer = Analyzer {
+        analysis: Analysis::default(),
+        frames: vec![],
+    };
+
+    analyzer.analyze_frame((0, usize::MAX), &[], &program.statements);
+    analyzer.analysis
+}
+
+impl Analysis {
+    /// The definition bound or referred to at byte `offset`.
+    pub fn definition_at(&self, offset: usize) -> Option<usize> {
+        let contains = |span: &Span| span.start <= offset && offset <= span.end;
+
+        self.definitions
+            .iter()
+            .position(|d| contains(&d.span))
+            .or_else(|| {
+                self.references
+                    .iter()
+                    .find(|r| contains(&r.span))
+                    .and_then(|r| r.definition)
+            })
+    }
+
+    /// The spans of every identifier referring to `definition`.
+    pub fn references_to(&self, definition: usize) -> Vec<Span> {
+        self.references
+            .iter()
+            .filter(|r| r.definition == Some(definition))
+            .map(|r| r.span)
+            .collect()
+    }
+
+    /// The bindings visible at byte `offset`, innermost first, without the
+    /// ones they shadow.
+    pub fn visible_at(&self, offset: usize) -> Vec<&Definition> {
+        let mut visible: Vec<&Definition> = self
+            .definitions
+            .iter()
+            .filter(|d| d.scope.0 <= offset && offset <= d.scope.1)
+            .collect();
+        visible.sort_by_key(|d| std::cmp::Reverse(d.scope.0));
+
+        let mut seen = vec![];
+        visible.retain(|d| {
+            let new = !seen.contains(&&d.name);
+            seen.push(&d.name);
+            new
+        });
+        visible
+    }
+
+    /// What an editor shows for the identifier at byte `offset`.
+    pub fn hover(&self, offset: usize) -> Option<String> {
+        if let Some(index) = self.definition_at(offset) {
+            return Some(self.definitions[index].detail.clone());
+        }
+
+        let reference = self
+            .references
+            .iter()
+            .find(|r| r.span.start <= offset && offset <= r.span.end)?;
+        if let Some(params) = prelude_functions().get(&reference.name) {
+            return Some(format!(
+                "prelude fn {}({})",
+                reference.name,
+                params.join(", ")
+            ));
+        }
+
+        let builtin = BUILTINS.get(reference.name.as_str())?;
+        Some(match builtin.arity {
+            Some(1) => format!("builtin {}, takes 1 argument", reference.name),
+            Some(n) => format!("builtin {}, takes {} arguments", reference.name, n),
+            None => format!("builtin {}, takes any number of arguments", reference.name),
+        })
+    }
+}
+
+/// The parameter names of every function the prelude defines.
+pub fn prelude_functions() -> HashMap<String, Vec<String>> {
+    let program = match prelude::program() {
+        Some(program) => program,
+        None => return HashMap::new(),
+    };
+
+    program
+        .statements
+        .iter()
+        .filter_map(|stmt| match stmt {
+            Statement::LETSTATEMENT(s) => match (s.name(), &s.value) {
+                (Some(name), EXPRESSION::FN(f)) => Some((
+                    name.value.clone(),
+                    f.parameters.iter().map(|p| p.string()).collect(),
+                )),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect()
+}
+
+struct Analyzer {
+    analysis: Analysis,
+    frames: Vec<HashMap<String, usize>>,
+}
+
+impl Analyzer {
+    fn analyze_frame(&mut self, scope: (usize, usize), params: &[Pattern], stmts: &[Statement]) {
+        self.frames.push(HashMap::new());
+
+        for param in params.iter().flat_map(Pattern::bindings) {
+            let detail = format!("parameter {}", param.value);
+            self.define(param, DefinitionKind::Param, scope, None, detail);
+        }
+        self.declare_statements(scope, stmts);
+        self.analyze_statements(stmts);
+
+        self.frames.pop();
+    }
+
+    fn define(
+        &mut self,
+        ident: &Identifier,
+        kind: DefinitionKind,
+        scope: (usize, usize),
+        value: Option<&EXPRESSION>,
+        detail: String,
+    ) {
+        let params = match value {
+            Some(EXPRESSION::FN(f)) => Some(f.parameters.iter().map(|p| p.string()).collect()),
+            _ => None,
+        };
+
+        let index = self.analysis.definitions.len();
+        self.analysis.definitions.push(Definition {
+            name: ident.value.clone(),
+            kind,
+            span: ident.token.span,
+            scope,
+            top_level: self.frames.len() == 1,
+            params,
+            r#type: value.and_then(|v| self.infer(v)),
+            detail,
+        });
+
+        if let Some(frame) = self.frames.last_mut() {
+            frame.entry(ident.value.clone()).or_insert(index);
+        }
+    }
+
+    fn define_let(&mut self, scope: (usize, usize), stmt: &Statement) {
+        let (s, prefix) = match stmt {
+            Statement::LETSTATEMENT(s) => (s, "let"),
+            Statement::EXPORTSTATEMENT(ExportStatement { stmt: s, .. }) => (s, "export let"),
+            _ => return,
+        };
+
+        let name = match s.name() {
+            Some(name) => name,
+            None => {
+                for name in s.pattern.bindings() {
+                    let detail = format!("{} {}", prefix, name.value);
+                    self.define(name, DefinitionKind::Let, scope, None, detail);
+                }
+                return;
+            }
+        };
+
+        let detail = match (&s.value, self.infer(&s.value)) {
+            (EXPRESSION::FN(f), _) => format!(
+                "{} {} = fn({})",
+                prefix,
+                name.value,
+                f.parameters
+                    .iter()
+                    .map(|p| p.string())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+            (_, Some(t)) => format!("{} {}: {:?}", prefix, name.value, t),
+            (_, None) => format!("{} {}", prefix, name.value),
+        };
+        self.define(name, DefinitionKind::Let, scope, Some(&s.value), detail);
+    }
+
+    fn declare_statements(&mut self, scope: (usize, usize), stmts: &[Statement]) {
+        for stmt in stmts {
+            match stmt {
+                Statement::LETSTATEMENT(s)
+                | Statement::EXPORTSTATEMENT(ExportStatement { stmt: s, .. }) => {
+                    self.define_let(scope, stmt);
+                    self.declare_expression(scope, &s.value);
+                }
+                Statement::RETURNSTATEMENT(s) => self.declare_expression(scope, &s.return_value),
+                Statement::EXPRESSIONSTATEMENT(s) => self.declare_expression(scope, &s.expression),
+                Statement::IMPORTSTATEMENT(s) => {
+                    if let Some(alias) = &s.alias {
+                        let detail = format!("import \"{}\" as {}", s.path, alias.value);
+                        self.define(alias, DefinitionKind::Import, scope, None, detail);
+                    }
+                    for name in s.names.iter() {
+                        let detail = format!("import {} from \"{}\"", name.value, s.path);
+                        self.define(name, DefinitionKind::Import, scope, None, detail);
+                    }
+                }
+            }
+        }
+    }
+
+    fn declare_if(&mut self, scope: (usize, usize), e: &IfExpression) {
+        self.declare_expression(scope, &e.condition);
+        self.declare_statements(scope, &e.consequence.statements);
+        match &e.alternative {
+            Some(ElseBranch::BLOCK(alt)) => self.declare_statements(scope, &alt.statements),
+            Some(ElseBranch::IF(alt)) => self.declare_if(scope, alt),
+            None => {}
+        }
+    }
+
+    fn declare_expression(&mut self, scope: (usize, usize), exp: &EXPRESSION) {
+        match exp {
+            EXPRESSION::IF(e) => self.declare_if(scope, e),
+            EXPRESSION::MATCH(e) => self.declare_expression(scope, &e.value),
+            EXPRESSION::PREFIX(e) => self.declare_expression(scope, &e.right),
+            EXPRESSION::INFIX(e) => {
+                self.declare_expression(scope, &e.left);
+                self.declare_expression(scope, &e.right);
+            }
+            EXPRESSION::CALL(e) => {
+                self.declare_expression(scope, &e.function);
+                for arg in e.args.iter() {
+                    self.declare_expression(scope, arg);
+                }
+            }
+            EXPRESSION::DOT(e) => self.declare_expression(scope, &e.left),
+            EXPRESSION::METHOD(e) => {
+                self.declare_expression(scope, &e.receiver);
+                for arg in e.args.iter() {
+                    self.declare_expression(scope, arg);
+                }
+            }
+            EXPRESSION::ArrayLiteral(e) => {
+                for item in e.items.iter() {
+                    self.declare_expression(scope, item);
+                }
+            }
+            EXPRESSION::TEMPLATE(e) => {
+                for exp in e.expressions.iter() {
+                    self.declare_expression(scope, exp);
+                }
+            }
+            EXPRESSION::IndexExpression(e) => {
+                self.declare_expression(scope, &e.left);
+                self.declare_expression(scope, &e.index);
+            }
+            EXPRESSION::SLICE(e) => {
+                self.declare_expression(scope, &e.left);
+                for bound in e.start.iter().chain(e.end.iter()) {
+                    self.declare_expression(scope, bound);
+                }
+            }
+            EXPRESSION::HashLiteral(e) => {
+                for (k, v) in e.pairs.iter() {
+                    self.declare_expression(scope, k);
+                    self.declare_expression(scope, v);
+                }
+            }
+            EXPRESSION::IDENTIFIER(_)
+            | EXPRESSION::INTEGER(_)
+            | EXPRESSION::BOOLEAN(_)
+            | EXPRESSION::NULL(_)
+            | EXPRESSION::FN(_)
+            | EXPRESSION::StringLiteral(_) => {}
+        }
+    }
+
+    fn analyze_statements(&mut self, stmts: &[Statement]) {
+        for stmt in stmts {
+            match stmt {
+                Statement::LETSTATEMENT(s)
+                | Statement::EXPORTSTATEMENT(ExportStatement { stmt: s, .. }) => {
+                    self.analyze_expression(&s.value)
+                }
+                Statement::RETURNSTATEMENT(s) => self.analyze_expression(&s.return_value),
+                Statement::EXPRESSIONSTATEMENT(s) => self.analyze_expression(&s.expression),
+                Statement::IMPORTSTATEMENT(_) => {}
+            }
+        }
+    }
+
+    fn analyze_block(&mut self, block: &BlockStatement) {
+        self.analyze_statements(&block.statements);
+    }
+
+    fn analyze_if(&mut self, e: &IfExpression) {
+        self.analyze_expression(&e.condition);
+        self.analyze_block(&e.consequence);
+        match &e.alternative {
+            Some(ElseBranch::BLOCK(alt)) => self.analyze_block(alt),
+            Some(ElseBranch::IF(alt)) => self.analyze_if(alt),
+            None => {}
+        }
+    }
+
+    /// Each arm is a frame of its own, visible up to the next arm.
+    fn analyze_match(&mut self, e: &MatchExpression) {
+        self.analyze_expression(&e.value);
+
+        for (i, arm) in e.arms.iter().enumerate() {
+            let end = e
+                .arms
+                .get(i + 1)
+                .map_or(e.end.end, |next| next.token.span.start);
+            let scope = (arm.token.span.start, end);
+            self.frames.push(HashMap::new());
+
+            for name in arm.pattern.bindings() {
+                let detail = format!("match {}", name.value);
+                self.define(name, DefinitionKind::Match, scope, None, detail);
+            }
+            for exp in arm.guard.iter().chain([&arm.body]) {
+                self.declare_expression(scope, exp);
+                self.analyze_expression(exp);
+            }
+
+            self.frames.pop();
+        }
+    }
+
+    fn analyze_expression(&mut self, exp: &EXPRESSION) {
+        match exp {
+            EXPRESSION::IDENTIFIER(ident) => self.reference(ident),
+            EXPRESSION::INTEGER(_)
+            | EXPRESSION::BOOLEAN(_)
+            | EXPRESSION::NULL(_)
+            | EXPRESSION::StringLiteral(_) => {}
+            EXPRESSION::PREFIX(e) => self.analyze_expression(&e.right),
+            EXPRESSION::INFIX(e) => {
+                self.analyze_expression(&e.left);
+                self.analyze_expression(&e.right);
+            }
+            EXPRESSION::IF(e) => self.analyze_if(e),
+            EXPRESSION::MATCH(e) => self.analyze_match(e),
+            EXPRESSION::FN(e) => {
+                let scope = (e.token.span.start, e.body.end.end);
+                self.analyze_frame(scope, &e.parameters, &e.body.statements);
+            }
+            EXPRESSION::CALL(e) => {
+                self.analyze_expression(&e.function);
+                for arg in e.args.iter() {
+                    self.analyze_expression(arg);
+                }
+            }
+            EXPRESSION::DOT(e) => self.analyze_expression(&e.left),
+            EXPRESSION::METHOD(e) => {
+                self.analyze_expression(&e.receiver);
+                self.reference(&e.method);
+                for arg in e.args.iter() {
+                    self.analyze_expression(arg);
+                }
+            }
+            EXPRESSION::ArrayLiteral(e) => {
+                for item in e.items.iter() {
+                    self.analyze_expression(item);
+                }
+            }
+            EXPRESSION::TEMPLATE(e) => {
+                for exp in e.expressions.iter() {
+                    self.analyze_expression(exp);
+                }
+            }
+            EXPRESSION::IndexExpression(e) => {
+                self.analyze_expression(&e.left);
+                self.analyze_expression(&e.index);
+            }
+            EXPRESSION::SLICE(e) => {
+                self.analyze_expression(&e.left);
+                for bound in e.start.iter().chain(e.end.iter()) {
+                    self.analyze_expression(bound);
+                }
+            }
+            EXPRESSION::HashLiteral(e) => {
+                for (k, v) in e.pairs.iter() {
+                    self.analyze_expression(k);
+                    self.analyze_expression(v);
+                }
+            }
+        }
+    }
+
+    fn reference(&mut self, ident: &Identifier) {
+        let definition = self.lookup(&ident.value);
+        self.analysis.references.push(Reference {
+            name: ident.value.clone(),
+            span: ident.token.span,
+            definition,
+        });
+    }
+
+    fn lookup(&self, name: &str) -> Option<usize> {
+        self.frames
+            .iter()
+            .rev()
+            .find_map(|frame| frame.get(name).copied())
+    }
+
+    /// The type `exp` evaluates to, when it doesn't depend on anything
+    /// only known at runtime.
+    fn infer(&self, exp: &EXPRESSION) -> Option<ObjectType> {
+        match exp {
+            EXPRESSION::INTEGER(_) => Some(ObjectType::INTEGER),
+            EXPRESSION::BOOLEAN(_) => Some(ObjectType::BOOLEAN),
+            EXPRESSION::NULL(_) => Some(ObjectType::NULL),
+            EXPRESSION::StringLiteral(_) | EXPRESSION::TEMPLATE(_) => Some(ObjectType::STRING),
+            EXPRESSION::ArrayLiteral(_) => Some(ObjectType::ARRAY),
+            EXPRESSION::HashLiteral(_) => Some(ObjectType::HASH),
+            EXPRESSION::FN(_) => Some(ObjectType::FUNCTION),
+            EXPRESSION::PREFIX(e) => match (e.operator.as_str(), self.infer(&e.right)?) {
+                ("!", _) => Some(ObjectType::BOOLEAN),
+                ("-", ObjectType::INTEGER) => Some(ObjectType::INTEGER),
+                _ => None,
+            },
+            EXPRESSION::INFIX(e) if e.operator == ">>" => Some(ObjectType::FUNCTION),
+            EXPRESSION::INFIX(e) if e.operator == "|>" => None,
+            EXPRESSION::INFIX(e) => {
+                let (left, right) = (self.infer(&e.left), self.infer(&e.right));
+                match (e.operator.as_str(), left?, right?) {
+                    ("==" | "!=" | "<" | ">", _, _) => Some(ObjectType::BOOLEAN),
+                    (".." | "..=", _, _) => Some(ObjectType::RANGE),
+                    ("&&" | "||" | "??", left, right) => (left == right).then_some(left),
+                    (_, ObjectType::INTEGER, ObjectType::INTEGER) => Some(ObjectType::INTEGER),
+                    ("+", ObjectType::STRING, ObjectType::STRING) => Some(ObjectType::STRING),
+                    _ => None,
+                }
+            }
+            EXPRESSION::IDENTIFIER(ident) => {
+                let index = self.lookup(&ident.value)?;
+                self.analysis.definitions[index].r#type.clone()
+            }
+            EXPRESSION::CALL(e) => match e.function.as_ref() {
+                EXPRESSION::IDENTIFIER(f) if f.value == "len" && self.lookup("len").is_none() => {
+                    Some(ObjectType::INTEGER)
+                }
+                _ => None,
+            },
+            EXPRESSION::METHOD(e) if e.method.value == "len" && self.lookup("len").is_none() => {
+                Some(ObjectType::INTEGER)
+            }
+            EXPRESSION::IF(_)
+            | EXPRESSION::MATCH(_)
+            | EXPRESSION::IndexExpression(_)
+            | EXPRESSION::SLICE(_)
+            | EXPRESSION::DOT(_)
+            | EXPRESSION::METHOD(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{analyze, DefinitionKind};
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    #[test]
+    fn test_analyze() {
+        let input = "let x = 1 + 2;
+let add = fn(a, b) { let x = a; x + b };
+add(x, len(\"ab\"));
+let n = len(\"ab\");
+map([1], add);";
+
+        let mut l = Lexer::new(input.to_string());
+        let mut p = Parser::new(&mut l);
+        let analysis = analyze(&p.parse_program().unwrap());
+
+        let offset = |line: usize, column: usize| {
+            input
+                .split('\n')
+                .take(line - 1)
+                .map(|l| l.len() + 1)
+                .sum::<usize>()
+                + column
+                - 1
+        };
+
+        // The inner `x` shadows the outer one inside `add` only.
+        let outer = analysis.definition_at(offset(1, 5)).unwrap();
+        let inner = analysis.definition_at(offset(2, 26)).unwrap();
+        if outer == inner || analysis.definitions[inner].top_level {
+            panic!("inner x should be its own binding");
+        }
+        if analysis.definition_at(offset(2, 33)) != Some(inner)
+            || analysis.definition_at(offset(3, 5)) != Some(outer)
+        {
+            panic!("x should resolve to the closest binding");
+        }
+        let refs: Vec<(usize, usize)> = analysis
+            .references_to(outer)
+            .iter()
+            .map(|s| (s.line, s.column))
+            .collect();
+        if refs != vec![(3, 5)] {
+            panic!("wrong references to x: {:?}", refs);
+        }
+
+        let tests = [
+            ((1, 5), Some("let x: INTEGER")),
+            ((2, 6), Some("let add = fn(a, b)")),
+            ((2, 14), Some("parameter a")),
+            ((4, 5), Some("let n: INTEGER")),
+            ((3, 8), Some("builtin len, takes 1 argument")),
+            ((5, 1), Some("prelude fn map(arr, f)")),
+            ((3, 7), None),
+        ];
+        for ((line, column), expected) in tests {
+            let actual = analysis.hover(offset(line, column));
+            if actual.as_deref() != expected {
+                panic!(
+                    "hover at {}:{} = {:?}, want {:?}",
+                    line, column, actual, expected
+                );
+            }
+        }
+
+        let visible: Vec<(&str, DefinitionKind)> = analysis
+            .visible_at(offset(2, 30))
+            .iter()
+            .map(|d| (d.name.as_str(), d.kind))
+            .collect();
+        if visible.len() != 5
+            || visible[..3]
+                != [
+                    ("a", DefinitionKind::Param),
+                    ("b", DefinitionKind::Param),
+                    ("x", DefinitionKind::Let),
+                ]
+        {
+            panic!("wrong bindings visible in add: {:?}", visible);
+        }
+    }
+
+    #[test]
+    fn test_analyze_match() {
+        let input = "let v = [1];\nmatch v { [a, ...r] if a => r, n => a }";
+
+        let mut l = Lexer::new(input.to_string());
+        let mut p = Parser::new(&mut l);
+        let analysis = analyze(&p.parse_program().unwrap());
+
+        let a = analysis.definition_at(24).unwrap();
+        if analysis.definitions[a].kind != DefinitionKind::Match
+            || analysis.hover(24).as_deref() != Some("match a")
+        {
+            panic!(
+                "a should be bound by the pattern: {:?}",
+                analysis.definitions[a]
+            );
+        }
+        let refs: Vec<usize> = analysis.references_to(a).iter().map(|s| s.start).collect();
+        if refs != vec![36] {
+            panic!("wrong references to a: {:?}", refs);
+        }
+
+        // The second arm doesn't see what the first one binds.
+        if analysis.definition_at(49).is_some() {
+            panic!("a should not be visible in the second arm");
+        }
+        let visible: Vec<&str> = analysis
+            .visible_at(48)
+            .iter()
+            .map(|d| d.name.as_str())
+            .collect();
+        if visible != vec!["n", "v"] {
+            panic!("wrong bindings visible in the second arm: {:?}", visible);
+        }
+    }
+}
@@ -1,10 +1,13 @@
-use crate::token::Token;
+use serde::{Deserialize, Serialize};
+
+use crate::token::{Span, Token};
 
 pub trait Node {
     fn token_literal(&self) -> String;
     fn string(&self) -> String;
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Program {
     pub statements: Vec<Statement>,
 }
@@ -27,11 +30,170 @@ impl Node for Program {
     }
 }
 
-#[derive(Debug, Clone)]
+impl Program {
+    /// The tree as an indented outline, one node per line.
+    pub fn tree(&self) -> String {
+        let mut out = "Program\n".to_string();
+        for stmt in self.statements.iter() {
+            statement_tree(stmt, 1, &mut out);
+        }
+        out
+    }
+}
+
+fn tree_line(depth: usize, line: &str, out: &mut String) {
+    out.push_str(&"  ".repeat(depth));
+    out.push_str(line);
+    out.push('\n');
+}
+
+fn block_tree(label: &str, block: &BlockStatement, depth: usize, out: &mut String) {
+    tree_line(depth, label, out);
+    for stmt in block.statements.iter() {
+        statement_tree(stmt, depth + 1, out);
+    }
+}
+
+fn statement_tree(stmt: &Statement, depth: usize, out: &mut String) {
+    match stmt {
+        Statement::LETSTATEMENT(s) => {
+            tree_line(depth, &format!("Let {}", s.pattern.string()), out);
+            expression_tree(&s.value, depth + 1, out);
+        }
+        Statement::RETURNSTATEMENT(s) => {
+            tree_line(depth, "Return", out);
+            expression_tree(&s.return_value, depth + 1, out);
+        }
+        Statement::EXPRESSIONSTATEMENT(s) => {
+            tree_line(depth, "Expression", out);
+            expression_tree(&s.expression, depth + 1, out);
+        }
+        Statement::IMPORTSTATEMENT(s) => tree_line(depth, &s.string(), out),
+        Statement::EXPORTSTATEMENT(s) => {
+            tree_line(depth, &format!("Export {}", s.stmt.pattern.string()), out);
+            expression_tree(&s.stmt.value, depth + 1, out);
+        }
+    }
+}
+
+fn if_tree(e: &IfExpression, depth: usize, out: &mut String) {
+    tree_line(depth, "If", out);
+    expression_tree(&e.condition, depth + 1, out);
+    block_tree("Then", &e.consequence, depth + 1, out);
+    match &e.alternative {
+        Some(ElseBranch::BLOCK(alt)) => block_tree("Else", alt, depth + 1, out),
+        Some(ElseBranch::IF(alt)) => {
+            tree_line(depth + 1, "Else", out);
+            if_tree(alt, depth + 2, out);
+        }
+        None => {}
+    }
+}
+
+fn expression_tree(exp: &EXPRESSION, depth: usize, out: &mut String) {
+    match exp {
+        EXPRESSION::IDENTIFIER(e) => tree_line(depth, &format!("Identifier {}", e.value), out),
+        EXPRESSION::INTEGER(e) => tree_line(depth, &format!("Integer {}", e.value), out),
+        EXPRESSION::BOOLEAN(e) => tree_line(depth, &format!("Boolean {}", e.value), out),
+        EXPRESSION::NULL(_) => tree_line(depth, "Null", out),
+        EXPRESSION::StringLiteral(e) => tree_line(depth, &format!("String {:?}", e.value), out),
+        EXPRESSION::SLICE(e) => {
+            tree_line(depth, "Slice", out);
+            expression_tree(&e.left, depth + 1, out);
+            for (label, bound) in [("Start", &e.start), ("End", &e.end)] {
+                if let Some(bound) = bound {
+                    tree_line(depth + 1, label, out);
+                    expression_tree(bound, depth + 2, out);
+                }
+            }
+        }
+        EXPRESSION::DOT(e) => {
+            tree_line(depth, &format!("Dot {}", e.name.value), out);
+            expression_tree(&e.left, depth + 1, out);
+        }
+        EXPRESSION::METHOD(e) => {
+            tree_line(depth, &format!("Method {}", e.method.value), out);
+            expression_tree(&e.receiver, depth + 1, out);
+            for arg in e.args.iter() {
+                expression_tree(arg, depth + 1, out);
+            }
+        }
+        EXPRESSION::TEMPLATE(e) => {
+            tree_line(depth, "Template", out);
+            tree_line(depth + 1, &format!("String {:?}", e.strings[0]), out);
+            for (exp, string) in e.expressions.iter().zip(e.strings[1..].iter()) {
+                expression_tree(exp, depth + 1, out);
+                tree_line(depth + 1, &format!("String {:?}", string), out);
+            }
+        }
+        EXPRESSION::PREFIX(e) => {
+            tree_line(depth, &format!("Prefix {}", e.operator), out);
+            expression_tree(&e.right, depth + 1, out);
+        }
+        EXPRESSION::INFIX(e) => {
+            tree_line(depth, &format!("Infix {}", e.operator), out);
+            expression_tree(&e.left, depth + 1, out);
+            expression_tree(&e.right, depth + 1, out);
+        }
+        EXPRESSION::IF(e) => if_tree(e, depth, out),
+        EXPRESSION::FN(e) => {
+            let params: Vec<String> = e.parameters.iter().map(|p| p.string()).collect();
+            block_tree(&format!("Fn({})", params.join(", ")), &e.body, depth, out);
+        }
+        EXPRESSION::CALL(e) => {
+            tree_line(depth, "Call", out);
+            expression_tree(&e.function, depth + 1, out);
+            for arg in e.args.iter() {
+                expression_tree(arg, depth + 1, out);
+            }
+        }
+        EXPRESSION::ArrayLiteral(e) => {
+            tree_line(depth, "Array", out);
+            for item in e.items.iter() {
+                expression_tree(item, depth + 1, out);
+            }
+        }
+        EXPRESSION::IndexExpression(e) => {
+            tree_line(depth, "Index", out);
+            expression_tree(&e.left, depth + 1, out);
+            expression_tree(&e.index, depth + 1, out);
+        }
+        EXPRESSION::HashLiteral(e) => {
+            tree_line(depth, "Hash", out);
+            for (k, v) in e.pairs.iter() {
+                tree_line(depth + 1, "Pair", out);
+                expression_tree(k, depth + 2, out);
+                expression_tree(v, depth + 2, out);
+            }
+        }
+        EXPRESSION::MATCH(e) => {
+            tree_line(depth, "Match", out);
+            expression_tree(&e.value, depth + 1, out);
+            for arm in e.arms.iter() {
+                tree_line(depth + 1, &format!("Arm {}", arm.pattern.string()), out);
+                if let Some(guard) = &arm.guard {
+                    tree_line(depth + 2, "Guard", out);
+                    expression_tree(guard, depth + 3, out);
+                }
+                expression_tree(&arm.body, depth + 2, out);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind")]
 pub enum Statement {
+    #[serde(rename = "Let")]
     LETSTATEMENT(LetStatement),
+    #[serde(rename = "Return")]
     RETURNSTATEMENT(ReturnStatement),
+    #[serde(rename = "Expression")]
     EXPRESSIONSTATEMENT(ExpressionStatement),
+    #[serde(rename = "Import")]
+    IMPORTSTATEMENT(ImportStatement),
+    #[serde(rename = "Export")]
+    EXPORTSTATEMENT(ExportStatement),
 }
 
 impl Node for Statement {
@@ -40,6 +202,8 @@ impl Node for Statement {
             Self::LETSTATEMENT(stmt) => stmt.token_literal(),
             Self::RETURNSTATEMENT(stmt) => stmt.token_literal(),
             Self::EXPRESSIONSTATEMENT(stmt) => stmt.token_literal(),
+            Self::IMPORTSTATEMENT(stmt) => stmt.token_literal(),
+            Self::EXPORTSTATEMENT(stmt) => stmt.token_literal(),
         }
     }
     fn string(&self) -> String {
@@ -47,17 +211,29 @@ impl Node for Statement {
             Self::LETSTATEMENT(stmt) => stmt.string(),
             Self::RETURNSTATEMENT(stmt) => stmt.string(),
             Self::EXPRESSIONSTATEMENT(stmt) => stmt.string(),
+            Self::IMPORTSTATEMENT(stmt) => stmt.string(),
+            Self::EXPORTSTATEMENT(stmt) => stmt.string(),
         }
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LetStatement {
     pub token: Token,
-    pub name: Identifier,
+    pub pattern: Pattern,
     pub value: EXPRESSION,
 }
 
+impl LetStatement {
+    /// The bound name, `None` when the value is destructured.
+    pub fn name(&self) -> Option<&Identifier> {
+        match &self.pattern {
+            Pattern::BINDING(name) => Some(name),
+            _ => None,
+        }
+    }
+}
+
 impl Node for LetStatement {
     fn token_literal(&self) -> String {
         self.token.literal.clone()
@@ -66,14 +242,14 @@ impl Node for LetStatement {
         let mut str = String::new();
 
         str = str + &self.token_literal() + " ";
-        str = str + &self.name.string() + " = ";
+        str = str + &self.pattern.string() + " = ";
         str = str + &self.value.string() + ";";
 
         str
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ReturnStatement {
     pub token: Token,
     pub return_value: EXPRESSION,
@@ -88,13 +264,13 @@ impl Node for ReturnStatement {
         let mut str = String::new();
 
         str = str + &self.token_literal() + " ";
-        str = str + ";";
+        str += ";";
 
         str
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExpressionStatement {
     pub token: Token,
     pub expression: EXPRESSION,
@@ -109,20 +285,90 @@ impl Node for ExpressionStatement {
     }
 }
 
-#[derive(Debug, Clone)]
+/// `import "path" as alias;` binds the module's exports as a hash,
+/// `import { a, b } from "path";` binds the named exports directly.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportStatement {
+    pub token: Token,
+    pub path: String,
+    pub alias: Option<Identifier>,
+    pub names: Vec<Identifier>,
+}
+
+impl Node for ImportStatement {
+    fn token_literal(&self) -> String {
+        self.token.literal.clone()
+    }
+    fn string(&self) -> String {
+        match &self.alias {
+            Some(alias) => format!("import \"{}\" as {};", self.path, alias.string()),
+            None => format!(
+                "import {{ {} }} from \"{}\";",
+                self.names
+                    .iter()
+                    .map(|name| name.string())
+                    .collect::<Vec<String>>()
+                    .join(", "),
+                self.path
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportStatement {
+    pub token: Token,
+    pub stmt: LetStatement,
+}
+
+impl Node for ExportStatement {
+    fn token_literal(&self) -> String {
+        self.token.literal.clone()
+    }
+    fn string(&self) -> String {
+        format!("{} {}", self.token_literal(), self.stmt.string())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind")]
 pub enum EXPRESSION {
+    #[serde(rename = "Identifier")]
     IDENTIFIER(Identifier),
+    #[serde(rename = "Integer")]
     INTEGER(IntegerLiteral),
+    #[serde(rename = "Prefix")]
     PREFIX(PrefixExpression),
+    #[serde(rename = "Infix")]
     INFIX(InfixExpression),
+    #[serde(rename = "Boolean")]
     BOOLEAN(BooleanExpression),
+    #[serde(rename = "Null")]
+    NULL(NullLiteral),
+    #[serde(rename = "If")]
     IF(IfExpression),
+    #[serde(rename = "Fn")]
     FN(FnExpression),
+    #[serde(rename = "Call")]
     CALL(CallExpression),
+    #[serde(rename = "String")]
     StringLiteral(StringLiteral),
+    #[serde(rename = "Array")]
     ArrayLiteral(ArrayLitearl),
+    #[serde(rename = "Index")]
     IndexExpression(IndexExpression),
+    #[serde(rename = "Hash")]
     HashLiteral(HashLiteral),
+    #[serde(rename = "Match")]
+    MATCH(MatchExpression),
+    #[serde(rename = "Template")]
+    TEMPLATE(TemplateLiteral),
+    #[serde(rename = "Dot")]
+    DOT(DotExpression),
+    #[serde(rename = "Method")]
+    METHOD(MethodCallExpression),
+    #[serde(rename = "Slice")]
+    SLICE(SliceExpression),
 }
 impl Node for EXPRESSION {
     fn token_literal(&self) -> String {
@@ -132,6 +378,7 @@ impl Node for EXPRESSION {
             EXPRESSION::PREFIX(obj) => obj.token_literal(),
             EXPRESSION::INFIX(obj) => obj.token_literal(),
             EXPRESSION::BOOLEAN(obj) => obj.token_literal(),
+            EXPRESSION::NULL(obj) => obj.token_literal(),
             EXPRESSION::IF(obj) => obj.token_literal(),
             EXPRESSION::FN(obj) => obj.token_literal(),
             EXPRESSION::CALL(obj) => obj.token_literal(),
@@ -139,6 +386,11 @@ impl Node for EXPRESSION {
             EXPRESSION::ArrayLiteral(obj) => obj.token_literal(),
             EXPRESSION::IndexExpression(obj) => obj.token_literal(),
             EXPRESSION::HashLiteral(obj) => obj.token_literal(),
+            EXPRESSION::MATCH(obj) => obj.token_literal(),
+            EXPRESSION::TEMPLATE(obj) => obj.token_literal(),
+            EXPRESSION::DOT(obj) => obj.token_literal(),
+            EXPRESSION::METHOD(obj) => obj.token_literal(),
+            EXPRESSION::SLICE(obj) => obj.token_literal(),
         }
     }
     fn string(&self) -> String {
@@ -148,6 +400,7 @@ impl Node for EXPRESSION {
             EXPRESSION::PREFIX(obj) => obj.string(),
             EXPRESSION::INFIX(obj) => obj.string(),
             EXPRESSION::BOOLEAN(obj) => obj.string(),
+            EXPRESSION::NULL(obj) => obj.string(),
             EXPRESSION::IF(obj) => obj.string(),
             EXPRESSION::FN(obj) => obj.string(),
             EXPRESSION::CALL(obj) => obj.string(),
@@ -155,14 +408,30 @@ impl Node for EXPRESSION {
             EXPRESSION::ArrayLiteral(obj) => obj.string(),
             EXPRESSION::IndexExpression(obj) => obj.string(),
             EXPRESSION::HashLiteral(obj) => obj.string(),
+            EXPRESSION::MATCH(obj) => obj.string(),
+            EXPRESSION::TEMPLATE(obj) => obj.string(),
+            EXPRESSION::DOT(obj) => obj.string(),
+            EXPRESSION::METHOD(obj) => obj.string(),
+            EXPRESSION::SLICE(obj) => obj.string(),
         }
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Identifier {
     pub token: Token,
     pub value: String,
+    /// Filled in by the resolver, `None` for builtins and unresolved trees.
+    #[serde(skip)]
+    pub slot: Option<Slot>,
+}
+
+/// Where a binding lives at runtime: `depth` environments up the chain from
+/// the current one, at `index` in that environment's frame.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Slot {
+    pub depth: usize,
+    pub index: usize,
 }
 
 impl Node for Identifier {
@@ -174,7 +443,7 @@ impl Node for Identifier {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct IntegerLiteral {
     pub token: Token,
     pub value: i64,
@@ -189,7 +458,7 @@ impl Node for IntegerLiteral {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PrefixExpression {
     pub token: Token,
     pub operator: String,
@@ -205,7 +474,7 @@ impl Node for PrefixExpression {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct InfixExpression {
     pub token: Token,
     pub operator: String,
@@ -227,7 +496,7 @@ impl Node for InfixExpression {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BooleanExpression {
     pub token: Token,
     pub value: bool,
@@ -242,12 +511,78 @@ impl Node for BooleanExpression {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NullLiteral {
+    pub token: Token,
+}
+
+impl Node for NullLiteral {
+    fn token_literal(&self) -> String {
+        self.token.literal.clone()
+    }
+    fn string(&self) -> String {
+        self.token.literal.clone()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct IfExpression {
     pub token: Token,
     pub condition: Box<EXPRESSION>,
     pub consequence: BlockStatement,
-    pub alternative: Option<BlockStatement>,
+    pub alternative: Option<ElseBranch>,
+}
+
+impl IfExpression {
+    /// Where the closing brace of the last branch is.
+    pub fn end(&self) -> Span {
+        match &self.alternative {
+            Some(ElseBranch::BLOCK(alt)) => alt.end,
+            Some(ElseBranch::IF(alt)) => alt.end(),
+            None => self.consequence.end,
+        }
+    }
+}
+
+/// What follows `else`: a block, or the next `if` of an `else if` chain.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ElseBranch {
+    BLOCK(BlockStatement),
+    IF(Box<IfExpression>),
+}
+
+impl ElseBranch {
+    /// The branch as a block, an `else if` becoming a block that holds the
+    /// nested `if`.
+    pub fn into_block(self) -> BlockStatement {
+        match self {
+            Self::BLOCK(block) => block,
+            Self::IF(e) => BlockStatement {
+                token: e.token.clone(),
+                end: e.end(),
+                statements: vec![Statement::EXPRESSIONSTATEMENT(ExpressionStatement {
+                    token: e.token.clone(),
+                    expression: EXPRESSION::IF(*e),
+                })],
+            },
+        }
+    }
+}
+
+impl Node for ElseBranch {
+    fn token_literal(&self) -> String {
+        match self {
+            Self::BLOCK(block) => block.token_literal(),
+            Self::IF(e) => e.token_literal(),
+        }
+    }
+    fn string(&self) -> String {
+        match self {
+            Self::BLOCK(block) => block.string(),
+            Self::IF(e) => e.string(),
+        }
+    }
 }
 
 impl Node for IfExpression {
@@ -259,7 +594,7 @@ impl Node for IfExpression {
 
         str.push_str("if");
         str.push_str(&self.condition.string());
-        str.push_str(" ");
+        str.push(' ');
         str.push_str(&self.consequence.string());
 
         if let Some(alt) = &self.alternative {
@@ -271,10 +606,12 @@ impl Node for IfExpression {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BlockStatement {
     pub token: Token,
     pub statements: Vec<Statement>,
+    /// Where the closing brace is.
+    pub end: Span,
 }
 
 impl Node for BlockStatement {
@@ -292,10 +629,10 @@ impl Node for BlockStatement {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FnExpression {
     pub token: Token,
-    pub parameters: Vec<Identifier>,
+    pub parameters: Vec<Pattern>,
     pub body: BlockStatement,
 }
 
@@ -313,16 +650,16 @@ impl Node for FnExpression {
             .join(", ");
 
         str.push_str(&self.token_literal());
-        str.push_str("(");
+        str.push('(');
         str.push_str(&params);
-        str.push_str(")");
+        str.push(')');
         str.push_str(&self.body.string());
 
         str
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CallExpression {
     pub token: Token,
     pub function: Box<EXPRESSION>,
@@ -342,15 +679,15 @@ impl Node for CallExpression {
             .join(", ");
 
         str.push_str(&self.function.string());
-        str.push_str("(");
+        str.push('(');
         str.push_str(&args);
-        str.push_str(")");
+        str.push(')');
 
         str
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StringLiteral {
     pub token: Token,
     pub value: String,
@@ -366,7 +703,57 @@ impl Node for StringLiteral {
     }
 }
 
-#[derive(Debug, Clone)]
+/// A backtick string. `strings` are the pieces of text around the `${}`
+/// expressions, so there is always one more of them than of expressions.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemplateLiteral {
+    pub token: Token,
+    pub strings: Vec<String>,
+    pub expressions: Vec<EXPRESSION>,
+}
+
+impl TemplateLiteral {
+    /// The template as source code, with `text` as the code of each
+    /// expression.
+    pub fn source(&self, mut text: impl FnMut(&EXPRESSION) -> String) -> String {
+        let mut str = String::from("`");
+        str.push_str(&escape_template(&self.strings[0]));
+        for (exp, string) in self.expressions.iter().zip(self.strings[1..].iter()) {
+            str.push_str(&format!("${{{}}}", text(exp)));
+            str.push_str(&escape_template(string));
+        }
+        str.push('`');
+
+        str
+    }
+}
+
+impl Node for TemplateLiteral {
+    fn token_literal(&self) -> String {
+        self.token.literal.clone()
+    }
+
+    fn string(&self) -> String {
+        self.source(|exp| exp.string())
+    }
+}
+
+/// Puts back the backslashes the lexer removed from template text.
+fn escape_template(text: &str) -> String {
+    let mut escaped = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match (ch, chars.peek()) {
+            ('`', _) | ('$', Some('{')) => escaped.push('\\'),
+            ('\\', None | Some('`' | '$' | '\\')) => escaped.push('\\'),
+            _ => {}
+        }
+        escaped.push(ch);
+    }
+    escaped
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ArrayLitearl {
     pub token: Token,
     pub items: Vec<EXPRESSION>,
@@ -387,15 +774,15 @@ impl Node for ArrayLitearl {
             .collect::<Vec<String>>()
             .join(", ");
 
-        str.push_str("[");
+        str.push('[');
         str.push_str(&items);
-        str.push_str("]");
+        str.push(']');
 
         str
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct IndexExpression {
     pub token: Token,
     pub left: Box<EXPRESSION>,
@@ -410,9 +797,9 @@ impl Node for IndexExpression {
     fn string(&self) -> String {
         let mut str = String::new();
 
-        str.push_str("(");
+        str.push('(');
         str.push_str(&self.left.string());
-        str.push_str("[");
+        str.push('[');
         str.push_str(&self.index.string());
         str.push_str("])");
 
@@ -420,7 +807,79 @@ impl Node for IndexExpression {
     }
 }
 
-#[derive(Debug, Clone)]
+/// `left[start:end]`, either bound can be left out.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SliceExpression {
+    pub token: Token,
+    pub left: Box<EXPRESSION>,
+    pub start: Option<Box<EXPRESSION>>,
+    pub end: Option<Box<EXPRESSION>>,
+}
+
+impl Node for SliceExpression {
+    fn token_literal(&self) -> String {
+        self.token.literal.clone()
+    }
+
+    fn string(&self) -> String {
+        let bound =
+            |b: &Option<Box<EXPRESSION>>| b.as_ref().map(|e| e.string()).unwrap_or_default();
+        format!(
+            "({}[{}:{}])",
+            self.left.string(),
+            bound(&self.start),
+            bound(&self.end)
+        )
+    }
+}
+
+/// `left.name`, the same as `left["name"]`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DotExpression {
+    pub token: Token,
+    pub left: Box<EXPRESSION>,
+    /// The name after the dot, as the string key it stands for.
+    pub name: StringLiteral,
+}
+
+impl Node for DotExpression {
+    fn token_literal(&self) -> String {
+        self.token.literal.clone()
+    }
+
+    fn string(&self) -> String {
+        format!("({}.{})", self.left.string(), self.name.value)
+    }
+}
+
+/// `receiver.method(args)`, calling the builtin or bound function `method`
+/// with `receiver` as its first argument.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MethodCallExpression {
+    pub token: Token,
+    pub receiver: Box<EXPRESSION>,
+    pub method: Identifier,
+    pub args: Vec<EXPRESSION>,
+}
+
+impl Node for MethodCallExpression {
+    fn token_literal(&self) -> String {
+        self.token.literal.clone()
+    }
+
+    fn string(&self) -> String {
+        let args = self
+            .args
+            .iter()
+            .map(|arg| arg.string())
+            .collect::<Vec<String>>()
+            .join(", ");
+
+        format!("{}.{}({})", self.receiver.string(), self.method.value, args)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HashLiteral {
     pub token: Token,
     pub pairs: Vec<(EXPRESSION, EXPRESSION)>,
@@ -440,22 +899,169 @@ impl Node for HashLiteral {
             .collect::<Vec<String>>()
             .join(", ");
 
-        str.push_str("{");
+        str.push('{');
         str.push_str(&pairs);
-        str.push_str("}");
+        str.push('}');
 
         str
     }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MatchExpression {
+    pub token: Token,
+    pub value: Box<EXPRESSION>,
+    pub arms: Vec<MatchArm>,
+    /// Where the closing brace is.
+    pub end: Span,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MatchArm {
+    /// The first token of the pattern.
+    pub token: Token,
+    pub pattern: Pattern,
+    pub guard: Option<EXPRESSION>,
+    pub body: EXPRESSION,
+}
+
+impl Node for MatchExpression {
+    fn token_literal(&self) -> String {
+        self.token.literal.clone()
+    }
+
+    fn string(&self) -> String {
+        let arms = self
+            .arms
+            .iter()
+            .map(|arm| match &arm.guard {
+                Some(guard) => format!(
+                    "{} if {} => {}",
+                    arm.pattern.string(),
+                    guard.string(),
+                    arm.body.string()
+                ),
+                None => format!("{} => {}", arm.pattern.string(), arm.body.string()),
+            })
+            .collect::<Vec<String>>()
+            .join(", ");
+
+        format!("match {} {{{}}}", self.value.string(), arms)
+    }
+}
+
+/// The shape a value is matched against. Literals are integers, strings,
+/// booleans and `null`; `_` matches anything without binding it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "pattern")]
+pub enum Pattern {
+    #[serde(rename = "Wildcard")]
+    WILDCARD(Token),
+    #[serde(rename = "Literal")]
+    LITERAL(EXPRESSION),
+    #[serde(rename = "Binding")]
+    BINDING(Identifier),
+    #[serde(rename = "Array")]
+    ARRAY(ArrayPattern),
+    #[serde(rename = "Hash")]
+    HASH(HashPattern),
+}
+
+/// `[first, second, ...rest]`. Without a rest pattern the array must have
+/// exactly as many items as there are patterns.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArrayPattern {
+    pub token: Token,
+    pub items: Vec<Pattern>,
+    /// A binding or `_` for the remaining items.
+    pub rest: Option<Box<Pattern>>,
+}
+
+/// `{"type": "add", "x": x}`, or `{x, y}` for `{"x": x, "y": y}`. The hash
+/// may have other keys too.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HashPattern {
+    pub token: Token,
+    pub pairs: Vec<(EXPRESSION, Pattern)>,
+}
+
+impl Pattern {
+    /// The names the pattern binds, in order.
+    pub fn bindings(&self) -> Vec<&Identifier> {
+        match self {
+            Pattern::WILDCARD(_) | Pattern::LITERAL(_) => vec![],
+            Pattern::BINDING(ident) => vec![ident],
+            Pattern::ARRAY(p) => p
+                .items
+                .iter()
+                .chain(p.rest.as_deref())
+                .flat_map(Pattern::bindings)
+                .collect(),
+            Pattern::HASH(p) => p.pairs.iter().flat_map(|(_, v)| v.bindings()).collect(),
+        }
+    }
+}
+
+/// The name bound by a hash pattern pair that can be written as `{name}`.
+pub fn shorthand<'a>(key: &EXPRESSION, value: &'a Pattern) -> Option<&'a Identifier> {
+    match (key, value) {
+        (EXPRESSION::StringLiteral(k), Pattern::BINDING(name)) if k.value == name.value => {
+            Some(name)
+        }
+        _ => None,
+    }
+}
+
+impl Node for Pattern {
+    fn token_literal(&self) -> String {
+        match self {
+            Pattern::WILDCARD(token) => token.literal.clone(),
+            Pattern::LITERAL(e) => e.token_literal(),
+            Pattern::BINDING(ident) => ident.token_literal(),
+            Pattern::ARRAY(p) => p.token.literal.clone(),
+            Pattern::HASH(p) => p.token.literal.clone(),
+        }
+    }
+
+    fn string(&self) -> String {
+        match self {
+            Pattern::WILDCARD(_) => "_".to_string(),
+            Pattern::LITERAL(e) => e.string(),
+            Pattern::BINDING(ident) => ident.string(),
+            Pattern::ARRAY(p) => {
+                let mut items: Vec<String> = p.items.iter().map(|item| item.string()).collect();
+                if let Some(rest) = &p.rest {
+                    items.push(format!("...{}", rest.string()));
+                }
+                format!("[{}]", items.join(", "))
+            }
+            Pattern::HASH(p) => {
+                let pairs = p
+                    .pairs
+                    .iter()
+                    .map(|(k, v)| match shorthand(k, v) {
+                        Some(name) => name.string(),
+                        None => format!("{} : {}", k.string(), v.string()),
+                    })
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                format!("{{{}}}", pairs)
+            }
+        }
+    }
+}
+
 #[cfg(test)]
+#[allow(clippy::cmp_owned)]
 mod tests {
     use crate::{
         ast::EXPRESSION,
-        token::{Token, TokenType},
+        lexer::Lexer,
+        parser::Parser,
+        token::{Span, Token, TokenType},
     };
 
-    use super::{Identifier, LetStatement, Node, Program, Statement};
+    use super::{Identifier, LetStatement, Node, Pattern, Program, Statement};
 
     #[test]
     fn test_string() {
@@ -464,20 +1070,25 @@ mod tests {
                 token: Token {
                     r#type: TokenType::LET,
                     literal: "let".to_string(),
+                    span: Span::default(),
                 },
-                name: Identifier {
+                pattern: Pattern::BINDING(Identifier {
                     token: Token {
                         r#type: TokenType::IDENT,
                         literal: "myVar".to_string(),
+                        span: Span::default(),
                     },
                     value: "myVar".to_string(),
-                },
+                    slot: None,
+                }),
                 value: EXPRESSION::IDENTIFIER(Identifier {
                     token: Token {
                         r#type: TokenType::IDENT,
                         literal: "anotherVar".to_string(),
+                        span: Span::default(),
                     },
                     value: "anotherVar".to_string(),
+                    slot: None,
                 }),
             })],
         };
@@ -487,4 +1098,45 @@ mod tests {
             panic!("program.string() failed");
         }
     }
+
+    #[test]
+    fn test_tree() {
+        let input = "let f = fn(x) { if (x > 1) { x } else { -x } }; f([1][0], {\"a\": true});";
+
+        let mut l = Lexer::new(input.to_string());
+        let mut p = Parser::new(&mut l);
+        let program = p.parse_program().unwrap();
+
+        let expected = "Program
+  Let f
+    Fn(x)
+      Expression
+        If
+          Infix >
+            Identifier x
+            Integer 1
+          Then
+            Expression
+              Identifier x
+          Else
+            Expression
+              Prefix -
+                Identifier x
+  Expression
+    Call
+      Identifier f
+      Index
+        Array
+          Integer 1
+        Integer 0
+      Hash
+        Pair
+          String \"a\"
+          Boolean true
+";
+
+        if program.tree() != expected {
+            panic!("Expected:\n{}\nGot:\n{}", expected, program.tree());
+        }
+    }
 }
@@ -0,0 +1,166 @@
+//! The syntax tree as JSON, for tools that don't link the interpreter.
+//!
+//! A program is `{"statements": [Statement, ...]}`. Statements and
+//! expressions are objects whose `kind` names the node, next to the fields of
+//! that node:
+//!
+//! | kind         | fields                                                  |
+//! |--------------|---------------------------------------------------------|
+//! | `Let`        | `token`, `pattern`: Pattern, `value`: Expression        |
+//! | `Return`     | `token`, `return_value`: Expression                     |
+//! | `Expression` | `token`, `expression`: Expression                       |
+//! | `Import`     | `token`, `path`: string, `alias`: Identifier or null, `names`: [Identifier] |
+//! | `Export`     | `token`, `stmt`: the `Let` fields, without `kind`       |
+//! | `Identifier` | `token`, `value`: string                                |
+//! | `Integer`    | `token`, `value`: number                                |
+//! | `Boolean`    | `token`, `value`: bool                                  |
+//! | `Null`       | `token`                                                 |
+//! | `String`     | `token`, `value`: string                                |
+//! | `Prefix`     | `token`, `operator`: string, `right`: Expression        |
+//! | `Infix`      | `token`, `operator`: string, `left`, `right`: Expression; ranges are `..` and `..=` |
+//! | `If`         | `token`, `condition`: Expression, `consequence`: Block, `alternative`: Block, the `If` fields of an `else if` without `kind`, or null |
+//! | `Fn`         | `token`, `parameters`: [Pattern], `body`: Block         |
+//! | `Call`       | `token`, `function`: Expression, `args`: [Expression]   |
+//! | `Array`      | `token`, `items`: [Expression]                          |
+//! | `Index`      | `token`, `left`, `index`: Expression                    |
+//! | `Slice`      | `token`, `left`: Expression, `start`, `end`: Expression or null |
+//! | `Hash`       | `token`, `pairs`: [[key, value]] of Expressions         |
+//! | `Match`      | `token`, `value`: Expression, `arms`: [Arm], `end`: Span |
+//! | `Dot`        | `token`, `left`: Expression, `name`: String             |
+//! | `Method`     | `token`, `receiver`: Expression, `method`: Identifier, `args`: [Expression] |
+//! | `Template`   | `token`, `strings`: [string], `expressions`: [Expression], one string more than expressions |
+//!
+//! An Arm is `{"token", "pattern": Pattern, "guard": Expression or null,
+//! "body": Expression}`. Patterns, also used by `let` and parameters, name
+//! their variant with `pattern` instead of `kind`: `Wildcard` (a token),
+//! `Literal` (an Expression), `Binding` (an Identifier), `Array` (`token`,
+//! `items`: [Pattern], `rest`: Pattern or null) and `Hash` (`token`,
+//! `pairs`: [[key Expression, Pattern]]). A shorthand `{x}` is stored as
+//! `[String x, Binding x]`.
+//!
+//! Identifiers outside of expressions (`alias`, `names`, ...) have no
+//! `kind`. A Block is `{"token", "statements": [Statement], "end": Span}`,
+//! where `end` is the closing brace. A token is `{"type": "LET", "literal":
+//! "let", "span": Span}` with the `TokenType` name as `type`, and a Span is
+//! `{"start", "end", "line", "column"}`: byte offsets and 1-based position.
+
+use serde::Deserialize;
+use serde_json::de::StrRead;
+
+use crate::ast::Program;
+
+pub fn to_json(program: &Program) -> String {
+    serde_json::to_string(program).expect("syntax trees always serialize")
+}
+
+pub fn to_json_pretty(program: &Program) -> String {
+    serde_json::to_string_pretty(program).expect("syntax trees always serialize")
+}
+
+/// Decodes a syntax tree. Natively the stack grows with the nesting of the
+/// tree, so anything `to_json` wrote comes back. In wasm, where the stack
+/// can't grow, serde_json's nesting limit of 128 stays in place.
+pub fn from_json(json: &str) -> Result<Program, String> {
+    let mut de = serde_json::Deserializer::from_str(json);
+    deserialize(&mut de)
+        .and_then(|program| de.end().map(|_| program))
+        .map_err(|err| format!("invalid syntax tree: {}", err))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn deserialize(de: &mut serde_json::Deserializer<StrRead>) -> serde_json::Result<Program> {
+    de.disable_recursion_limit();
+    Program::deserialize(serde_stacker::Deserializer::new(de))
+}
+
+#[cfg(target_arch = "wasm32")]
+fn deserialize(de: &mut serde_json::Deserializer<StrRead>) -> serde_json::Result<Program> {
+    Program::deserialize(de)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{from_json, to_json};
+    use crate::ast::Node;
+    use crate::eval::eval;
+    use crate::lexer::Lexer;
+    use crate::object::ObjectTrait;
+    use crate::parser::Parser;
+
+    fn parse(input: &str) -> crate::ast::Program {
+        let mut l = Lexer::new(input.to_string());
+        let mut p = Parser::new(&mut l);
+        p.parse_program().unwrap()
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let tests = [
+            ("let add = fn(a, b) { a + b }; add(1, -2) * 3", "-3"),
+            ("if (1 < 2) { \"yes\" } else { \"no\" }", "yes"),
+            ("let h = {\"a\": [1, 2, 3]}; h[\"a\"][2]", "3"),
+            ("!true == false", "true"),
+            ("if 1 > 2 { 1 } else if true { 2 }", "2"),
+            (
+                "let p = {\"xs\": [1, 2]}; p.xs.len() + p.xs.push(3).sum()",
+                "8",
+            ),
+            ("let n = 2; `n is ${n * 2}${`!`}`", "n is 4!"),
+            (
+                "match [1, {\"x\": 2}] { [_, {\"x\": x}, ...r] if x > 1 => x, _ => null }",
+                "2",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse(input);
+            let json = to_json(&program);
+
+            let decoded = match from_json(&json) {
+                Ok(decoded) => decoded,
+                Err(err) => panic!("{}: {}", input, err),
+            };
+            if decoded.string() != program.string() || to_json(&decoded) != json {
+                panic!("{} changed in a JSON round trip", input);
+            }
+
+            let evaluated = eval(decoded).inspect();
+            if evaluated != expected {
+                panic!("{}: Expected {}, Got {}", input, expected, evaluated);
+            }
+        }
+    }
+
+    #[test]
+    fn test_json_round_trip_deep_tree() {
+        let input = vec!["1"; 300].join(" + ");
+        let program = parse(&input);
+        let json = to_json(&program);
+
+        let decoded = match from_json(&json) {
+            Ok(decoded) => decoded,
+            Err(err) => panic!("deep tree: {}", err),
+        };
+        if to_json(&decoded) != json {
+            panic!("deep tree changed in a JSON round trip");
+        }
+    }
+
+    #[test]
+    fn test_json_schema() {
+        let json = to_json(&parse("let x = -1;"));
+        let expected = concat!(
+            r#"{"statements":[{"kind":"Let","token":{"type":"LET","literal":"let","span":{"start":0,"end":3,"line":1,"column":1}},"#,
+            r#""pattern":{"pattern":"Binding","token":{"type":"IDENT","literal":"x","span":{"start":4,"end":5,"line":1,"column":5}},"value":"x"},"#,
+            r#""value":{"kind":"Prefix","token":{"type":"MINUS","literal":"-","span":{"start":8,"end":9,"line":1,"column":9}},"operator":"-","#,
+            r#""right":{"kind":"Integer","token":{"type":"INT","literal":"1","span":{"start":9,"end":10,"line":1,"column":10}},"value":1}}}]}"#
+        );
+        if json != expected {
+            panic!("Expected {}\nGot {}", expected, json);
+        }
+
+        if from_json(r#"{"statements":[{"kind":"Loop"}]}"#).is_ok() {
+            panic!("Expected an unknown kind to be rejected");
+        }
+    }
+}
@@ -0,0 +1,8 @@
+use std::{io, process};
+
+use monkey_interpreter::lsp;
+
+fn main() {
+    let code = lsp::serve(&mut io::stdin().lock(), &mut io::stdout());
+    process::exit(code)
+}
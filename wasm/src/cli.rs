@@ -0,0 +1,679 @@
+use std::{
+    fs,
+    io::{self, ErrorKind, Write},
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use crate::{
+    ast::Program,
+    ast_json::to_json_pretty,
+    eval::{check, eval_with_globals, Limits, MAX_DEPTH},
+    formatter::format_source,
+    graph::{call_graph, syntax_tree, GraphFormat},
+    lexer::Lexer,
+    lint::{lint, RULES},
+    module::{self, FileResolver},
+    object::{Array, Object, ObjectTrait, StringLiteral},
+    optimize::optimize,
+    parser::Parser,
+    repl,
+    token::TokenType,
+};
+
+pub const EXIT_SUCCESS: i32 = 0;
+pub const EXIT_RUNTIME_ERROR: i32 = 1;
+/// `fmt --check` found files that aren't formatted, and no other errors.
+pub const EXIT_UNFORMATTED: i32 = 3;
+/// `lint` reported diagnostics.
+pub const EXIT_LINT_WARNINGS: i32 = 1;
+/// The source doesn't parse or refers to unbound identifiers.
+pub const EXIT_PARSE_ERROR: i32 = 2;
+pub const EXIT_USAGE: i32 = 64;
+pub const EXIT_NO_INPUT: i32 = 66;
+/// Writing the output failed, for another reason than the reader going away.
+pub const EXIT_IO_ERROR: i32 = 74;
+
+const USAGE: &str = "Usage: monkey <command> [arguments]
+
+Commands:
+  run <file> [args...]   Run a script, `args` holds the extra arguments
+  eval -e <code> [args...]
+                         Evaluate code and print its value
+  repl [--dump-optimized]
+                         Start the interactive prompt (the default)
+  check <file>           Parse and resolve a script without running it
+  tokens <file>          Print the tokens of a script
+  ast [--json] <file>    Print the syntax tree of a script
+  graph [--calls] [--mermaid] <file>
+                         Print the syntax tree, or the calls between
+                         functions, as a Graphviz or Mermaid graph
+  fmt [--check] <files...>
+                         Format scripts in place, or list the ones that
+                         aren't formatted
+  lint [--disable <rules>] <files...>
+                         Report suspicious code, except for the
+                         comma-separated rules";
+
+/// Why a command stopped before it was done.
+enum Failure {
+    /// An input file couldn't be read.
+    NoInput(String),
+    /// Writing to `out` or `err` failed.
+    Output(io::Error),
+}
+
+impl From<io::Error> for Failure {
+    fn from(e: io::Error) -> Self {
+        Failure::Output(e)
+    }
+}
+
+/// Runs the command line `args`, without the program name, and returns the
+/// process exit code. Output closing early, like when piped into `head`,
+/// ends the command without an error.
+pub fn run(args: &[String], out: &mut dyn Write, err: &mut dyn Write) -> i32 {
+    match dispatch(args, out, err) {
+        Ok(code) => code,
+        Err(Failure::NoInput(msg)) => {
+            let _ = writeln!(err, "{}", msg);
+            EXIT_NO_INPUT
+        }
+        Err(Failure::Output(e)) if e.kind() == ErrorKind::BrokenPipe => EXIT_SUCCESS,
+        Err(Failure::Output(e)) => {
+            let _ = writeln!(err, "cannot write output: {}", e);
+            EXIT_IO_ERROR
+        }
+    }
+}
+
+fn dispatch(args: &[String], out: &mut dyn Write, err: &mut dyn Write) -> Result<i32, Failure> {
+    let command = args.first().map(String::as_str);
+    let rest = args.get(1..).unwrap_or_default();
+
+    match command {
+        None => {
+            repl::start(false);
+            Ok(EXIT_SUCCESS)
+        }
+        Some("--dump-optimized") => {
+            repl::start(true);
+            Ok(EXIT_SUCCESS)
+        }
+        Some("repl") => {
+            repl::start(rest.iter().any(|arg| arg == "--dump-optimized"));
+            Ok(EXIT_SUCCESS)
+        }
+        Some("run") => match rest.split_first() {
+            Some((path, script_args)) => run_file(path, script_args, err),
+            None => usage(err),
+        },
+        Some("eval") => match rest {
+            [flag, code, script_args @ ..] if flag == "-e" => {
+                eval_code(code, script_args, out, err)
+            }
+            _ => usage(err),
+        },
+        Some("check") => match rest {
+            [path] => check_file(path, out, err),
+            _ => usage(err),
+        },
+        Some("tokens") => match rest {
+            [path] => print_tokens(path, out),
+            _ => usage(err),
+        },
+        Some("ast") => match rest {
+            [path] => print_ast(path, false, out, err),
+            [flag, path] if flag == "--json" => print_ast(path, true, out, err),
+            _ => usage(err),
+        },
+        Some("graph") => {
+            let calls = rest.iter().any(|arg| arg == "--calls");
+            let format = if rest.iter().any(|arg| arg == "--mermaid") {
+                GraphFormat::Mermaid
+            } else {
+                GraphFormat::Dot
+            };
+            match rest.last() {
+                Some(path) if !path.starts_with("--") => print_graph(path, calls, format, out, err),
+                _ => usage(err),
+            }
+        }
+        Some("fmt") => {
+            let check = rest.iter().any(|arg| arg == "--check");
+            let paths: Vec<&String> = rest.iter().filter(|arg| *arg != "--check").collect();
+            if paths.is_empty() {
+                usage(err)
+            } else {
+                format_files(&paths, check, out, err)
+            }
+        }
+        Some("lint") => match rest {
+            [flag, rules, paths @ ..] if flag == "--disable" && !paths.is_empty() => {
+                let disabled: Vec<String> = rules.split(',').map(str::to_string).collect();
+                match disabled
+                    .iter()
+                    .find(|rule| !RULES.iter().any(|(id, _)| id == rule))
+                {
+                    Some(rule) => {
+                        writeln!(err, "unknown lint rule: {}", rule)?;
+                        usage(err)
+                    }
+                    None => lint_files(paths, &disabled, out, err),
+                }
+            }
+            [first, ..] if first.starts_with("--") => usage(err),
+            [] => usage(err),
+            paths => lint_files(paths, &[], out, err),
+        },
+        Some("help" | "-h" | "--help") => {
+            writeln!(out, "{}", USAGE)?;
+            Ok(EXIT_SUCCESS)
+        }
+        Some(other) => {
+            writeln!(err, "unknown command: {}", other)?;
+            usage(err)
+        }
+    }
+}
+
+fn usage(err: &mut dyn Write) -> Result<i32, Failure> {
+    writeln!(err, "{}", USAGE)?;
+    Ok(EXIT_USAGE)
+}
+
+fn read_source(path: &str) -> Result<String, Failure> {
+    fs::read_to_string(path).map_err(|e| Failure::NoInput(format!("cannot read {}: {}", path, e)))
+}
+
+fn parse(source: String, name: &str, err: &mut dyn Write) -> io::Result<Option<Program>> {
+    let mut l = Lexer::new(source);
+    let mut p = Parser::new(&mut l);
+    let program = p.parse_program();
+
+    let errors = p.errors();
+    if !errors.is_empty() {
+        for e in errors {
+            writeln!(err, "{}: {}", name, e)?;
+        }
+        return Ok(None);
+    }
+
+    Ok(program)
+}
+
+/// Resolves `program` as it will run, with `args` bound, so that unbound
+/// identifiers are reported before anything runs.
+fn resolve(mut program: Program, name: &str, err: &mut dyn Write) -> io::Result<Option<Program>> {
+    let errors = check(&mut program, &["args"]);
+    if !errors.is_empty() {
+        for e in errors {
+            writeln!(err, "{}: {}", name, e)?;
+        }
+        return Ok(None);
+    }
+
+    Ok(Some(program))
+}
+
+/// Parses and resolves `source`, reporting errors on `err` as `name`.
+fn load(source: String, name: &str, err: &mut dyn Write) -> io::Result<Option<Program>> {
+    match parse(source, name, err)? {
+        Some(program) => resolve(program, name, err),
+        None => Ok(None),
+    }
+}
+
+fn script_args(args: &[String]) -> Vec<(String, Object)> {
+    let elements = args
+        .iter()
+        .map(|arg| Object::STRING(StringLiteral { value: arg.clone() }))
+        .collect();
+
+    vec![("args".to_string(), Object::ARRAY(Array::new(elements)))]
+}
+
+/// Scripts run on a thread with `eval::STACK_SIZE` of stack, see `main`.
+fn limits() -> Limits {
+    Limits {
+        max_depth: Some(MAX_DEPTH),
+        ..Limits::default()
+    }
+}
+
+fn run_file(path: &str, args: &[String], err: &mut dyn Write) -> Result<i32, Failure> {
+    let source = read_source(path)?;
+    let program = match load(source, path, err)? {
+        Some(program) => program,
+        None => return Ok(EXIT_PARSE_ERROR),
+    };
+
+    // Imports of the script are relative to the script itself.
+    let root = Path::new(path)
+        .parent()
+        .map_or(PathBuf::from("."), Path::to_path_buf);
+    module::set_resolver(Rc::new(FileResolver { root }));
+
+    match eval_with_globals(optimize(program), limits(), script_args(args)) {
+        Object::ERROR(e) => {
+            writeln!(err, "{}: {}", path, e.msg)?;
+            Ok(EXIT_RUNTIME_ERROR)
+        }
+        _ => Ok(EXIT_SUCCESS),
+    }
+}
+
+fn eval_code(
+    code: &str,
+    args: &[String],
+    out: &mut dyn Write,
+    err: &mut dyn Write,
+) -> Result<i32, Failure> {
+    let program = match load(code.to_string(), "<eval>", err)? {
+        Some(program) => program,
+        None => return Ok(EXIT_PARSE_ERROR),
+    };
+
+    match eval_with_globals(optimize(program), limits(), script_args(args)) {
+        Object::ERROR(e) => {
+            writeln!(err, "{}", e.inspect())?;
+            Ok(EXIT_RUNTIME_ERROR)
+        }
+        value => {
+            writeln!(out, "{}", value.inspect())?;
+            Ok(EXIT_SUCCESS)
+        }
+    }
+}
+
+fn check_file(path: &str, out: &mut dyn Write, err: &mut dyn Write) -> Result<i32, Failure> {
+    let source = read_source(path)?;
+    if load(source, path, err)?.is_none() {
+        return Ok(EXIT_PARSE_ERROR);
+    }
+
+    writeln!(out, "{}: ok", path)?;
+    Ok(EXIT_SUCCESS)
+}
+
+fn print_tokens(path: &str, out: &mut dyn Write) -> Result<i32, Failure> {
+    let mut l = Lexer::new(read_source(path)?);
+
+    loop {
+        let tok = l.next_token();
+        writeln!(
+            out,
+            "{}:{}\t{:?}\t{}",
+            tok.span.line, tok.span.column, tok.r#type, tok.literal
+        )?;
+
+        if tok.r#type == TokenType::EOF {
+            return Ok(EXIT_SUCCESS);
+        }
+    }
+}
+
+fn print_ast(
+    path: &str,
+    json: bool,
+    out: &mut dyn Write,
+    err: &mut dyn Write,
+) -> Result<i32, Failure> {
+    let source = read_source(path)?;
+    match parse(source, path, err)? {
+        Some(program) if json => {
+            writeln!(out, "{}", to_json_pretty(&program))?;
+            Ok(EXIT_SUCCESS)
+        }
+        Some(program) => {
+            write!(out, "{}", program.tree())?;
+            Ok(EXIT_SUCCESS)
+        }
+        None => Ok(EXIT_PARSE_ERROR),
+    }
+}
+
+fn print_graph(
+    path: &str,
+    calls: bool,
+    format: GraphFormat,
+    out: &mut dyn Write,
+    err: &mut dyn Write,
+) -> Result<i32, Failure> {
+    let source = read_source(path)?;
+    let graph = if calls {
+        call_graph(&source, format)
+    } else {
+        syntax_tree(&source, format)
+    };
+
+    match graph {
+        Ok(graph) => {
+            write!(out, "{}", graph)?;
+            Ok(EXIT_SUCCESS)
+        }
+        Err(errors) => {
+            writeln!(err, "{}: {}", path, errors)?;
+            Ok(EXIT_PARSE_ERROR)
+        }
+    }
+}
+
+fn format_files(
+    paths: &[&String],
+    check: bool,
+    out: &mut dyn Write,
+    err: &mut dyn Write,
+) -> Result<i32, Failure> {
+    let mut code = EXIT_SUCCESS;
+    let mut unformatted = false;
+
+    for path in paths {
+        let source = match read_source(path) {
+            Ok(source) => source,
+            Err(Failure::NoInput(msg)) => {
+                writeln!(err, "{}", msg)?;
+                code = code.max(EXIT_NO_INPUT);
+                continue;
+            }
+            Err(failure) => return Err(failure),
+        };
+
+        let formatted = match format_source(&source) {
+            Ok(formatted) => formatted,
+            Err(errors) => {
+                for e in errors {
+                    writeln!(err, "{}: {}", path, e)?;
+                }
+                code = code.max(EXIT_PARSE_ERROR);
+                continue;
+            }
+        };
+
+        if formatted == source {
+            continue;
+        }
+
+        if check {
+            writeln!(out, "{}", path)?;
+            unformatted = true;
+        } else if let Err(e) = fs::write(path, formatted) {
+            writeln!(err, "cannot write {}: {}", path, e)?;
+            code = code.max(EXIT_NO_INPUT);
+        }
+    }
+
+    match code {
+        EXIT_SUCCESS if unformatted => Ok(EXIT_UNFORMATTED),
+        code => Ok(code),
+    }
+}
+
+fn lint_files(
+    paths: &[String],
+    disabled: &[String],
+    out: &mut dyn Write,
+    err: &mut dyn Write,
+) -> Result<i32, Failure> {
+    let mut code = EXIT_SUCCESS;
+
+    for path in paths {
+        let source = match read_source(path) {
+            Ok(source) => source,
+            Err(Failure::NoInput(msg)) => {
+                writeln!(err, "{}", msg)?;
+                code = code.max(EXIT_NO_INPUT);
+                continue;
+            }
+            Err(failure) => return Err(failure),
+        };
+
+        match lint(&source, disabled) {
+            Ok(diagnostics) => {
+                for d in diagnostics.iter() {
+                    writeln!(
+                        out,
+                        "{}:{}:{}: {}: {}",
+                        path, d.span.line, d.span.column, d.rule, d.message
+                    )?;
+                }
+                if !diagnostics.is_empty() {
+                    code = code.max(EXIT_LINT_WARNINGS);
+                }
+            }
+            Err(errors) => {
+                for e in errors {
+                    writeln!(err, "{}: {}", path, e)?;
+                }
+                code = code.max(EXIT_PARSE_ERROR);
+            }
+        }
+    }
+
+    Ok(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        io::{self, ErrorKind, Write},
+    };
+
+    use super::{
+        run, EXIT_IO_ERROR, EXIT_LINT_WARNINGS, EXIT_NO_INPUT, EXIT_PARSE_ERROR,
+        EXIT_RUNTIME_ERROR, EXIT_SUCCESS, EXIT_UNFORMATTED, EXIT_USAGE,
+    };
+
+    /// Output whose every write fails with `kind`.
+    struct FailingOutput(ErrorKind);
+
+    impl Write for FailingOutput {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::Error::new(self.0, "output closed"))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn run_cli(args: &[&str]) -> (i32, String, String) {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        let mut out = vec![];
+        let mut err = vec![];
+
+        let code = run(&args, &mut out, &mut err);
+
+        (
+            code,
+            String::from_utf8(out).unwrap(),
+            String::from_utf8(err).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_cli_commands() {
+        let dir = std::env::temp_dir().join(format!("monkey-cli-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let script = dir.join("script.monkey");
+        fs::write(
+            &script,
+            "#!/usr/bin/env monkey run\nimport { double } from \"lib.monkey\";\nputs(double(len(args)));",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("lib.monkey"),
+            "export let double = fn(x) { x * 2 };",
+        )
+        .unwrap();
+
+        let broken = dir.join("broken.monkey");
+        fs::write(&broken, "let x 1;").unwrap();
+
+        let failing = dir.join("failing.monkey");
+        fs::write(&failing, "let x = 1;\nx / 0;").unwrap();
+
+        let unbound = dir.join("unbound.monkey");
+        fs::write(&unbound, "map(args, fn(a) { b });").unwrap();
+
+        let script = script.to_str().unwrap();
+        let broken = broken.to_str().unwrap();
+        let failing = failing.to_str().unwrap();
+        let unbound = unbound.to_str().unwrap();
+
+        let tests = [
+            (vec!["run", script, "a", "b"], EXIT_SUCCESS, ""),
+            (vec!["run", broken], EXIT_PARSE_ERROR, ""),
+            (vec!["run", failing], EXIT_RUNTIME_ERROR, ""),
+            (vec!["run", unbound], EXIT_PARSE_ERROR, ""),
+            (vec!["run", "missing.monkey"], EXIT_NO_INPUT, ""),
+            (vec!["run"], EXIT_USAGE, ""),
+            (
+                vec!["eval", "-e", "sum(map([1, 2], fn(x) { x * 10 }))"],
+                EXIT_SUCCESS,
+                "30\n",
+            ),
+            (vec!["eval", "-e", "args", "a"], EXIT_SUCCESS, "[a]\n"),
+            (vec!["eval", "-e", "1 / 0"], EXIT_RUNTIME_ERROR, ""),
+            (vec!["eval", "-e", "let 1;"], EXIT_PARSE_ERROR, ""),
+            (vec!["eval", "-e", "undefined"], EXIT_PARSE_ERROR, ""),
+            (vec!["check", script], EXIT_SUCCESS, ""),
+            (vec!["check", failing], EXIT_SUCCESS, ""),
+            (vec!["check", unbound], EXIT_PARSE_ERROR, ""),
+            (vec!["check", broken], EXIT_PARSE_ERROR, ""),
+            (vec!["frobnicate"], EXIT_USAGE, ""),
+        ];
+
+        for (args, expected_code, expected_out) in tests {
+            let (code, out, err) = run_cli(&args);
+            if code != expected_code {
+                panic!(
+                    "{:?} exited with {}, want {}. stderr: {}",
+                    args, code, expected_code, err
+                );
+            }
+            if !expected_out.is_empty() && out != expected_out {
+                panic!("{:?} printed {:?}, want {:?}", args, out, expected_out);
+            }
+        }
+
+        let (_, _, err) = run_cli(&["check", unbound]);
+        if err
+            != format!(
+                "{}: identifier not found: b at line 1, column 19\n",
+                unbound
+            )
+        {
+            panic!("unexpected check output: {:?}", err);
+        }
+
+        let messy = dir.join("messy.monkey");
+        fs::write(&messy, "let x=1").unwrap();
+        let messy = messy.to_str().unwrap();
+
+        let (code, out, _) = run_cli(&["fmt", "--check", messy]);
+        if code != EXIT_UNFORMATTED || out != format!("{}\n", messy) {
+            panic!(
+                "fmt --check should report {}. Got {} {:?}",
+                messy, code, out
+            );
+        }
+        let (code, _, _) = run_cli(&["fmt", "--check", messy, broken]);
+        if code != EXIT_PARSE_ERROR {
+            panic!("fmt --check should fail on the parse error. Got {}", code);
+        }
+        let (code, _, _) = run_cli(&["fmt", messy]);
+        if code != EXIT_SUCCESS || fs::read_to_string(messy).unwrap() != "let x = 1;\n" {
+            panic!("fmt should rewrite {}", messy);
+        }
+        let (code, out, _) = run_cli(&["fmt", "--check", messy]);
+        if code != EXIT_SUCCESS || !out.is_empty() {
+            panic!(
+                "fmt --check should accept formatted files. Got {} {:?}",
+                code, out
+            );
+        }
+        let (code, _, _) = run_cli(&["fmt", broken]);
+        if code != EXIT_PARSE_ERROR {
+            panic!("fmt should fail on parse errors. Got {}", code);
+        }
+
+        let sloppy = dir.join("sloppy.monkey");
+        fs::write(&sloppy, "let unused = 1;\nlen(1, 2);").unwrap();
+        let sloppy = sloppy.to_str().unwrap();
+
+        let (code, out, _) = run_cli(&["lint", sloppy, script]);
+        let expected = format!(
+            "{0}:1:5: unused-let: unused is never used\n{0}:2:1: builtin-arity: len takes 1 argument, got 2\n",
+            sloppy
+        );
+        if code != EXIT_LINT_WARNINGS || out != expected {
+            panic!("lint should report {:?}. Got {} {:?}", expected, code, out);
+        }
+        let (code, out, _) = run_cli(&["lint", "--disable", "unused-let,builtin-arity", sloppy]);
+        if code != EXIT_SUCCESS || !out.is_empty() {
+            panic!("lint --disable should skip rules. Got {} {:?}", code, out);
+        }
+        let (code, _, _) = run_cli(&["lint", "--disable", "bogus", sloppy]);
+        if code != EXIT_USAGE {
+            panic!("lint should reject unknown rules. Got {}", code);
+        }
+
+        let (code, out, _) = run_cli(&["ast", "--json", broken]);
+        if code != EXIT_PARSE_ERROR || !out.is_empty() {
+            panic!(
+                "ast --json should fail on parse errors. Got {} {:?}",
+                code, out
+            );
+        }
+        let (code, out, _) = run_cli(&["ast", "--json", failing]);
+        if code != EXIT_SUCCESS || crate::ast_json::from_json(&out).is_err() {
+            panic!("ast --json should print the tree. Got {} {:?}", code, out);
+        }
+
+        let (code, out, _) = run_cli(&["graph", "--calls", "--mermaid", script]);
+        if code != EXIT_SUCCESS || !out.starts_with("graph TD\n") {
+            panic!("graph should print a mermaid graph. Got {} {:?}", code, out);
+        }
+        let (code, out, _) = run_cli(&["graph", failing]);
+        if code != EXIT_SUCCESS || !out.starts_with("digraph ast {\n") {
+            panic!("graph should print a dot graph. Got {} {:?}", code, out);
+        }
+
+        let (_, out, _) = run_cli(&["tokens", broken]);
+        if out != "1:1\tLET\tlet\n1:5\tIDENT\tx\n1:7\tINT\t1\n1:8\tSEMICOLON\t;\n1:9\tEOF\t\n" {
+            panic!("unexpected tokens output: {:?}", out);
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_cli_failing_output() {
+        let tests = [
+            (ErrorKind::BrokenPipe, EXIT_SUCCESS, ""),
+            (
+                ErrorKind::StorageFull,
+                EXIT_IO_ERROR,
+                "cannot write output: output closed\n",
+            ),
+        ];
+
+        for (kind, expected_code, expected_err) in tests {
+            for args in [&["eval", "-e", "1"][..], &["help"][..]] {
+                let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+                let mut err = vec![];
+                let code = run(&args, &mut FailingOutput(kind), &mut err);
+                let err = String::from_utf8(err).unwrap();
+                if code != expected_code || err != expected_err {
+                    panic!(
+                        "{:?} with {:?} output exited with {} {:?}, want {} {:?}",
+                        args, kind, code, err, expected_code, expected_err
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use std::{cell::RefCell, collections::HashSet, rc::Rc};
+
+use serde::Serialize;
+
+use crate::{
+    ast::Node,
+    object::{Environment, Object, BUILTINS},
+    token::KEYWORDS,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CompletionKind {
+    Binding,
+    Function,
+    Builtin,
+    Keyword,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Completion {
+    pub name: String,
+    pub kind: CompletionKind,
+    /// Parameter names, for functions bound in the environment.
+    pub params: Option<Vec<String>>,
+}
+
+impl Completion {
+    /// The name with the parameter list of functions, e.g. `map(arr, f)`.
+    pub fn label(&self) -> String {
+        match &self.params {
+            Some(params) => format!("{}({})", self.name, params.join(", ")),
+            None => self.name.clone(),
+        }
+    }
+}
+
+fn is_identifier_byte(b: u8) -> bool {
+    b == b'_' || b.is_ascii_alphabetic()
+}
+
+/// Byte offset where the identifier ending at `pos` starts.
+pub fn word_start(line: &str, pos: usize) -> usize {
+    let bytes = line.as_bytes();
+    let mut start = pos.min(bytes.len());
+    while start > 0 && is_identifier_byte(bytes[start - 1]) {
+        start -= 1;
+    }
+    start
+}
+
+/// Every binding visible from `env`, builtin and keyword starting with
+/// `prefix`, sorted by name. Inner bindings shadow outer ones and builtins.
+pub fn completions(env: &Rc<RefCell<Environment>>, prefix: &str) -> Vec<Completion> {
+    let mut seen = HashSet::new();
+    let mut result = vec![];
+
+    let mut current = Some(Rc::clone(env));
+    while let Some(env) = current {
+        let env = env.borrow();
+        for (name, index) in env.names() {
+            if !name.starts_with(prefix) || !seen.insert(name.clone()) {
+                continue;
+            }
+
+            result.push(match env.get_at(0, index) {
+                Some(Object::FN(f)) => Completion {
+                    name: name.clone(),
+                    kind: CompletionKind::Function,
+                    params: Some(f.params.iter().map(|p| p.string()).collect()),
+                },
+                Some(Object::COMPOSED(_)) => Completion {
+                    name: name.clone(),
+                    kind: CompletionKind::Function,
+                    params: None,
+                },
+                _ => Completion {
+                    name: name.clone(),
+                    kind: CompletionKind::Binding,
+                    params: None,
+                },
+            });
+        }
+        current = env.outer().cloned();
+    }
+
+    for name in BUILTINS.keys() {
+        if name.starts_with(prefix) && seen.insert(name.to_string()) {
+            result.push(Completion {
+                name: name.to_string(),
+                kind: CompletionKind::Builtin,
+                params: None,
+            });
+        }
+    }
+
+    for name in KEYWORDS.keys() {
+        if name.starts_with(prefix) && seen.insert(name.to_string()) {
+            result.push(Completion {
+                name: name.to_string(),
+                kind: CompletionKind::Keyword,
+                params: None,
+            });
+        }
+    }
+
+    result.sort_by(|a, b| a.name.cmp(&b.name));
+    result
+}
+
+/// The parameter list of the function called by the unclosed call that
+/// `pos` is in, e.g. `(arr, f)` for `map([1], `.
+pub fn signature(env: &Rc<RefCell<Environment>>, line: &str, pos: usize) -> Option<String> {
+    let bytes = line.as_bytes();
+    let mut depth = 0;
+    let mut open = None;
+
+    for i in (0..pos.min(bytes.len())).rev() {
+        match bytes[i] {
+            b')' | b']' | b'}' => depth += 1,
+            b'(' | b'[' | b'{' if depth > 0 => depth -= 1,
+            b'(' => {
+                open = Some(i);
+                break;
+            }
+            b'[' | b'{' => return None,
+            _ => {}
+        }
+    }
+
+    let open = open?;
+    let name = &line[word_start(line, open)..open];
+    if name.is_empty() {
+        return None;
+    }
+
+    completions(env, name)
+        .into_iter()
+        .find(|c| c.name == name)
+        .and_then(|c| c.params)
+        .map(|params| format!("({})", params.join(", ")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{completions, signature, word_start, CompletionKind};
+    use crate::eval::{eval_in, new_environment, Limits};
+    use crate::lexer::Lexer;
+    use crate::object::{enclosed_environment, Null, Object};
+    use crate::parser::Parser;
+    use std::{cell::RefCell, rc::Rc};
+
+    #[test]
+    fn test_completions() {
+        let env = new_environment().unwrap();
+        let mut l = Lexer::new("let lengths = fn(list, unit) { 1 }; let level = 2;".to_string());
+        let mut p = Parser::new(&mut l);
+        let _ = eval_in(p.parse_program().unwrap(), &env, Limits::default());
+
+        let inner = Rc::new(RefCell::new(enclosed_environment(&env)));
+        inner
+            .borrow_mut()
+            .set("level".to_string(), Object::NULL(Null {}));
+        inner
+            .borrow_mut()
+            .set("leaf".to_string(), Object::NULL(Null {}));
+
+        let labels: Vec<(String, CompletionKind)> = completions(&inner, "le")
+            .iter()
+            .map(|c| (c.label(), c.kind))
+            .collect();
+        let expected = vec![
+            ("leaf".to_string(), CompletionKind::Binding),
+            ("len".to_string(), CompletionKind::Builtin),
+            ("lengths(list, unit)".to_string(), CompletionKind::Function),
+            ("let".to_string(), CompletionKind::Keyword),
+            ("level".to_string(), CompletionKind::Binding),
+        ];
+        if labels != expected {
+            panic!("Expected {:?}. Got {:?}", expected, labels);
+        }
+
+        let tests = [
+            ("map([1, 2], ", Some("(arr, f)")),
+            ("lengths(", Some("(list, unit)")),
+            ("map([1, 2], fn(x) { x }) + ", None),
+            ("len(", None),
+            ("[1, ", None),
+        ];
+        for (line, expected) in tests {
+            let actual = signature(&env, line, line.len());
+            if actual.as_deref() != expected {
+                panic!("signature({:?}) = {:?}, want {:?}", line, actual, expected);
+            }
+        }
+
+        if word_start("let x = ma", 10) != 8 || word_start("ma", 0) != 0 {
+            panic!("wrong word start");
+        }
+    }
+}
@@ -0,0 +1,332 @@
+//! What the playground editor needs to highlight and check code, computed
+//! from the real lexer and parser. Offsets are UTF-16 code units, the way
+//! JavaScript indexes strings.
+
+use serde::Serialize;
+
+use crate::{
+    analysis::{self, analyze, parse, CompletionItem, Severity},
+    lexer::Lexer,
+    object::BUILTINS,
+    token::{Span, TokenType},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenKind {
+    Keyword,
+    Identifier,
+    Builtin,
+    Number,
+    String,
+    Operator,
+    Punctuation,
+    Comment,
+    Invalid,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SemanticToken {
+    pub start: usize,
+    pub end: usize,
+    pub kind: TokenKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Diagnostic {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+    pub severity: Severity,
+    pub message: String,
+    pub rule: Option<&'static str>,
+}
+
+/// Converts between byte offsets into a source and UTF-16 offsets.
+struct Offsets {
+    /// The UTF-16 offset of every byte offset, up to and including the end.
+    utf16: Vec<usize>,
+}
+
+impl Offsets {
+    fn new(source: &str) -> Offsets {
+        let mut utf16 = Vec::with_capacity(source.len() + 1);
+        let mut units = 0;
+        for c in source.chars() {
+            for _ in 0..c.len_utf8() {
+                utf16.push(units);
+            }
+            units += c.len_utf16();
+        }
+        utf16.push(units);
+
+        Offsets { utf16 }
+    }
+
+    fn to_utf16(&self, byte: usize) -> usize {
+        self.utf16[byte.min(self.utf16.len() - 1)]
+    }
+
+    fn to_byte(&self, utf16: usize) -> usize {
+        self.utf16.partition_point(|&units| units < utf16)
+    }
+}
+
+fn token_kind(r#type: TokenType, literal: &str) -> Option<TokenKind> {
+    Some(match r#type {
+        TokenType::LET
+        | TokenType::FUNCTION
+        | TokenType::TRUE
+        | TokenType::FALSE
+        | TokenType::IF
+        | TokenType::ELSE
+        | TokenType::RETURN
+        | TokenType::IMPORT
+        | TokenType::EXPORT
+        | TokenType::NULL
+        | TokenType::MATCH => TokenKind::Keyword,
+        TokenType::IDENT if BUILTINS.contains_key(literal) => TokenKind::Builtin,
+        TokenType::IDENT => TokenKind::Identifier,
+        TokenType::INT => TokenKind::Number,
+        TokenType::STRING
+        | TokenType::TEMPLATE
+        | TokenType::TEMPLATEHEAD
+        | TokenType::TEMPLATEMIDDLE
+        | TokenType::TEMPLATETAIL => TokenKind::String,
+        TokenType::ASSIGN
+        | TokenType::PLUS
+        | TokenType::MINUS
+        | TokenType::BANG
+        | TokenType::ASTERISK
+        | TokenType::SLASH
+        | TokenType::LT
+        | TokenType::GT
+        | TokenType::EQ
+        | TokenType::NOTEQ
+        | TokenType::AND
+        | TokenType::OR
+        | TokenType::COALESCE
+        | TokenType::ARROW
+        | TokenType::ELLIPSIS
+        | TokenType::PIPE
+        | TokenType::COMPOSE
+        | TokenType::DOTDOT
+        | TokenType::DOTDOTEQ => TokenKind::Operator,
+        TokenType::COMMA
+        | TokenType::SEMICOLON
+        | TokenType::COLON
+        | TokenType::LPAREN
+        | TokenType::RPAREN
+        | TokenType::LBRACE
+        | TokenType::RBRACE
+        | TokenType::LBRACKET
+        | TokenType::RBRACKET
+        | TokenType::DOT => TokenKind::Punctuation,
+        TokenType::ILLEGAL => TokenKind::Invalid,
+        TokenType::EOF => return None,
+    })
+}
+
+/// Every token and comment of `source` in order, as the lexer reads them.
+pub fn semantic_tokens(source: &str) -> Vec<SemanticToken> {
+    let offsets = Offsets::new(source);
+    let mut l = Lexer::new(source.to_string());
+    let mut tokens = vec![];
+
+    loop {
+        let tok = l.next_token();
+        let kind = match token_kind(tok.r#type, &tok.literal) {
+            Some(kind) => kind,
+            None => break,
+        };
+
+        // The lexer reads a character of several bytes as one illegal
+        // token per byte; only the last one ends after the character.
+        let (start, end) = (
+            offsets.to_utf16(tok.span.start),
+            offsets.to_utf16(tok.span.end),
+        );
+        if start < end {
+            tokens.push(SemanticToken { start, end, kind });
+        }
+    }
+
+    for comment in l.comments() {
+        tokens.push(SemanticToken {
+            start: offsets.to_utf16(comment.span.start),
+            end: offsets.to_utf16(comment.span.end),
+            kind: TokenKind::Comment,
+        });
+    }
+
+    tokens.sort_by_key(|tok| tok.start);
+    tokens
+}
+
+/// The parse errors of `source`, or its lint warnings if it parses.
+pub fn diagnostics(source: &str) -> Vec<Diagnostic> {
+    let offsets = Offsets::new(source);
+
+    analysis::diagnostics(source)
+        .into_iter()
+        .map(|d| Diagnostic {
+            start: offsets.to_utf16(d.span.start),
+            end: offsets.to_utf16(d.span.end),
+            line: d.span.line,
+            column: d.span.column,
+            severity: d.severity,
+            message: d.message,
+            rule: d.rule,
+        })
+        .collect()
+}
+
+/// What can be typed at `offset` to complete the word before it.
+pub fn completions(source: &str, offset: usize) -> Vec<CompletionItem> {
+    analysis::completions(source, Offsets::new(source).to_byte(offset))
+}
+
+/// A description of the identifier at `offset`: the binding's type or
+/// function signature, or what builtin it is.
+pub fn hover(source: &str, offset: usize) -> Option<String> {
+    let program = parse(source).ok()?;
+    analyze(&program).hover(Offsets::new(source).to_byte(offset))
+}
+
+/// The offset of the bracket matching the one at `offset`, or the one just
+/// before it. Brackets in strings and comments don't count.
+pub fn matching_bracket(source: &str, offset: usize) -> Option<usize> {
+    let offsets = Offsets::new(source);
+    let offset = offsets.to_byte(offset);
+
+    let mut l = Lexer::new(source.to_string());
+    let mut brackets: Vec<(TokenType, Span)> = vec![];
+    loop {
+        let tok = l.next_token();
+        match tok.r#type {
+            TokenType::EOF => break,
+            TokenType::LPAREN
+            | TokenType::RPAREN
+            | TokenType::LBRACE
+            | TokenType::RBRACE
+            | TokenType::LBRACKET
+            | TokenType::RBRACKET => brackets.push((tok.r#type, tok.span)),
+            _ => {}
+        }
+    }
+
+    let at = brackets
+        .iter()
+        .position(|(_, span)| span.start == offset)
+        .or_else(|| brackets.iter().position(|(_, span)| span.end == offset))?;
+
+    let opening = |t: TokenType| match t {
+        TokenType::LPAREN => Some(TokenType::RPAREN),
+        TokenType::LBRACE => Some(TokenType::RBRACE),
+        TokenType::LBRACKET => Some(TokenType::RBRACKET),
+        _ => None,
+    };
+
+    let (r#type, _) = brackets[at];
+    let mut depth = 0;
+    let found = match opening(r#type) {
+        Some(_) => brackets[at..].iter().find(|(t, _)| {
+            depth += if opening(*t).is_some() { 1 } else { -1 };
+            depth == 0
+        }),
+        None => brackets[..=at].iter().rev().find(|(t, _)| {
+            depth += if opening(*t).is_some() { -1 } else { 1 };
+            depth == 0
+        }),
+    }?;
+
+    // Brackets of different kinds don't match, e.g. in `(]`.
+    let (open, close) = if opening(r#type).is_some() {
+        (r#type, found.0)
+    } else {
+        (found.0, r#type)
+    };
+    if opening(open) != Some(close) {
+        return None;
+    }
+
+    Some(offsets.to_utf16(found.1.start))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{completions, diagnostics, hover, matching_bracket, semantic_tokens, TokenKind};
+
+    #[test]
+    fn test_semantic_tokens() {
+        let input = "let é = len(\"ü\"); // done\nx != 1";
+        let tokens: Vec<(usize, usize, TokenKind)> = semantic_tokens(input)
+            .iter()
+            .map(|t| (t.start, t.end, t.kind))
+            .collect();
+
+        let expected = vec![
+            (0, 3, TokenKind::Keyword),
+            (4, 5, TokenKind::Invalid),
+            (6, 7, TokenKind::Operator),
+            (8, 11, TokenKind::Builtin),
+            (11, 12, TokenKind::Punctuation),
+            (12, 15, TokenKind::String),
+            (15, 16, TokenKind::Punctuation),
+            (16, 17, TokenKind::Punctuation),
+            (18, 25, TokenKind::Comment),
+            (26, 27, TokenKind::Identifier),
+            (28, 30, TokenKind::Operator),
+            (31, 32, TokenKind::Number),
+        ];
+        if tokens != expected {
+            panic!("Expected {:?}. Got {:?}", expected, tokens);
+        }
+    }
+
+    #[test]
+    fn test_editor_services() {
+        let input = "let s = \"ü\"; let add = fn(a, b) { [a, b] };\nadd(s, ";
+
+        let errors = diagnostics(input);
+        if errors.len() != 1 || (errors[0].start, errors[0].line, errors[0].column) != (51, 2, 8) {
+            panic!("wrong diagnostics: {:?}", errors);
+        }
+
+        let fixed = format!("{}1)", input);
+        let labels: Vec<String> = completions(&fixed, 14)
+            .into_iter()
+            .map(|c| c.label)
+            .collect();
+        if labels != vec!["last", "len", "let"] {
+            panic!("wrong completions: {:?}", labels);
+        }
+        if hover(&fixed, 17).as_deref() != Some("let add = fn(a, b)") {
+            panic!("wrong hover: {:?}", hover(&fixed, 17));
+        }
+
+        let tests = [
+            (25, Some(30)),
+            (30, Some(25)),
+            (32, Some(41)),
+            (42, Some(32)),
+            (34, Some(39)),
+            (47, Some(52)),
+            (8, None),
+            (0, None),
+        ];
+        for (offset, expected) in tests {
+            let actual = matching_bracket(&fixed, offset);
+            if actual != expected {
+                panic!(
+                    "matching_bracket({}) = {:?}, want {:?}",
+                    offset, actual, expected
+                );
+            }
+        }
+        if matching_bracket("(]", 0).is_some() || matching_bracket("\"(\" )", 4).is_some() {
+            panic!("mismatched brackets should not match");
+        }
+    }
+}
@@ -2,60 +2,436 @@ use std::{cell::RefCell, collections::HashMap, rc::Rc};
 
 use crate::{
     ast::{
-        BlockStatement, CallExpression, ExpressionStatement, HashLiteral, Identifier, IfExpression,
-        LetStatement, Program, ReturnStatement, Statement, EXPRESSION,
+        BlockStatement, CallExpression, ElseBranch, ExpressionStatement, HashLiteral, Identifier,
+        IfExpression, ImportStatement, LetStatement, MatchExpression, MethodCallExpression, Node,
+        Pattern, Program, ReturnStatement, SliceExpression, Statement, TemplateLiteral, EXPRESSION,
     },
+    gc, heap,
+    lexer::Lexer,
+    module,
     object::{
-        enclosed_environment, Array, Boolean, Environment, Error, Function, HashObject, HashPair,
-        Hashable, Integer, Null, Object, Return, StringLiteral, BUILTINS,
+        enclosed_environment, Array, Boolean, Composition, Environment, Error, Function, HashKey,
+        HashObject, HashPair, Hashable, Integer, Null, Object, ObjectTrait, Range, Return,
+        StringLiteral, BUILTINS,
     },
+    parser::Parser,
+    prelude, resolver,
 };
 
+/// How often, in steps, the wall clock and the interrupt callback are polled.
+const POLL_INTERVAL: u64 = 1024;
+
+/// Native stack the command line runs the interpreter on. Every call nests a
+/// few Rust frames, about 3 KB of stack in release builds and 12 KB in debug
+/// builds, more when the call sits in a deeply nested expression.
+pub const STACK_SIZE: usize = 64 * 1024 * 1024;
+
+/// Call depth that fits in `STACK_SIZE`, with room to spare for debug builds.
+pub const MAX_DEPTH: usize = 2000;
+
+/// Bounds on the work a single evaluation is allowed to do.
+///
+/// Every expression evaluated counts as one step. When any limit is hit the
+/// evaluation stops with an "execution limit exceeded" error object.
+#[derive(Clone, Default)]
+pub struct Limits {
+    pub max_steps: Option<u64>,
+    pub max_depth: Option<usize>,
+    /// Wall time budget in milliseconds, measured with `clock`.
+    pub max_time_ms: Option<f64>,
+    /// Host provided clock returning milliseconds. Required for `max_time_ms`.
+    pub clock: Option<Rc<dyn Fn() -> f64>>,
+    /// Polled every `POLL_INTERVAL` steps, returning `true` stops the
+    /// evaluation. Wrap an `Arc<AtomicBool>` to interrupt from another thread.
+    pub interrupt: Option<Rc<dyn Fn() -> bool>>,
+    /// Cap on the heap memory the evaluation holds at once, on top of what
+    /// the thread held when it started. Checked whenever an array, string or
+    /// hash is built, memory freed since then is given back.
+    pub max_allocated_bytes: Option<usize>,
+}
+
+#[derive(Default)]
+struct Budget {
+    limits: Limits,
+    steps: u64,
+    depth: usize,
+    started_at: f64,
+    /// `heap::in_use` when the evaluation started.
+    heap_at_start: usize,
+}
+
+thread_local! {
+    static BUDGET: RefCell<Budget> = RefCell::new(Budget::default());
+}
+
 pub fn eval(program: Program) -> Object {
-    let env = Rc::new(RefCell::new(Environment::new()));
-    eval_statements(program.statements, &env)
+    eval_with_limits(program, Limits::default())
+}
+
+pub fn eval_with_limits(program: Program, limits: Limits) -> Object {
+    eval_with_globals(program, limits, vec![])
+}
+
+/// Evaluates `program` with `globals` bound in its environment, next to the
+/// prelude.
+pub fn eval_with_globals(
+    mut program: Program,
+    limits: Limits,
+    globals: Vec<(String, Object)>,
+) -> Object {
+    gc::with_owner(gc::new_evaluation(), || {
+        module::reset();
+
+        let result = with_budget(limits, || match new_environment() {
+            Ok(env) => {
+                for (name, value) in globals {
+                    env.borrow_mut().set(name, value);
+                }
+
+                let errors = resolver::resolve(&mut program, &env);
+                if errors.is_empty() {
+                    eval_program(program, &env)
+                } else {
+                    Object::ERROR(Error {
+                        msg: errors.join("\n"),
+                    })
+                }
+            }
+            Err(msg) => Object::ERROR(Error { msg }),
+        });
+
+        module::reset();
+
+        // Nothing but the result outlives the evaluation, so cycles through
+        // the global environment can be broken now.
+        gc::collect(&[], &[&result]);
+
+        result
+    })
+}
+
+/// Resolves `program` without evaluating it, as if `globals` were bound next
+/// to the prelude. Returns the resolver errors.
+pub fn check(program: &mut Program, globals: &[&str]) -> Vec<String> {
+    gc::with_owner(gc::new_evaluation(), || {
+        let errors = match new_environment() {
+            Ok(env) => {
+                for name in globals {
+                    env.borrow_mut()
+                        .set(name.to_string(), Object::NULL(Null {}));
+                }
+
+                resolver::resolve(program, &env)
+            }
+            Err(msg) => vec![msg],
+        };
+
+        gc::collect(&[], &[]);
+
+        errors
+    })
 }
 
-fn eval_statements(stmts: Vec<Statement>, env: &Rc<RefCell<Environment>>) -> Object {
+/// Evaluates `program` in `env` and keeps its bindings there, so that the
+/// next program evaluated in `env` sees them.
+///
+/// A later program may still bind a name this one refers to, so unresolved
+/// identifiers are looked up by name when they are evaluated. They are
+/// returned as warnings next to the result.
+pub fn eval_in(
+    mut program: Program,
+    env: &Rc<RefCell<Environment>>,
+    limits: Limits,
+) -> (Object, Vec<String>) {
+    gc::with_owner(gc::Owner::Session(Rc::as_ptr(env)), || {
+        let warnings = resolver::resolve(&mut program, env);
+        let result = with_budget(limits, || eval_program(program, env));
+
+        gc::collect(&[env], &[&result]);
+
+        (result, warnings)
+    })
+}
+
+/// A global environment with the prelude loaded into it.
+pub fn new_environment() -> Result<Rc<RefCell<Environment>>, String> {
+    let env = gc::track(Environment::new());
+
+    if let Some(prelude) = prelude::program() {
+        let mut program = Program::clone(&prelude);
+
+        let errors = resolver::resolve(&mut program, &env);
+        if !errors.is_empty() {
+            return Err(format!("in prelude: {}", errors.join("; ")));
+        }
+
+        if let Object::ERROR(err) = eval_program(program, &env) {
+            return Err(format!("in prelude: {}", err.msg));
+        }
+    }
+
+    Ok(env)
+}
+
+fn eval_program(program: Program, env: &Rc<RefCell<Environment>>) -> Object {
+    gc::enter_program();
+
     let mut result = Object::NULL(Null {});
 
-    for stmt in stmts {
+    for stmt in &program.statements {
         match eval_statement(stmt, env) {
             Some(r) => result = r,
             None => continue,
         };
 
         match result {
-            Object::RETURN(o) => return *o.value,
-            Object::ERROR(_) => return result,
-            _ => continue,
+            Object::RETURN(o) => {
+                result = *o.value;
+                break;
+            }
+            Object::ERROR(_) => break,
+            _ => gc::safe_point(env, &result),
         }
     }
 
+    gc::leave_program();
+
     result
 }
 
-fn eval_statement(stmt: Statement, env: &Rc<RefCell<Environment>>) -> Option<Object> {
+/// Runs `f` with a fresh budget for `limits`, then puts the previous one back.
+fn with_budget<T>(limits: Limits, f: impl FnOnce() -> T) -> T {
+    let started_at = limits.clock.as_ref().map_or(0.0, |clock| clock());
+    let previous = BUDGET.with(|b| {
+        b.replace(Budget {
+            limits,
+            steps: 0,
+            depth: 0,
+            started_at,
+            heap_at_start: heap::in_use(),
+        })
+    });
+
+    let result = f();
+
+    BUDGET.with(|b| b.replace(previous));
+    result
+}
+
+fn limit_exceeded(reason: String) -> Object {
+    Object::ERROR(Error {
+        msg: format!("execution limit exceeded: {}", reason),
+    })
+}
+
+fn tick() -> Option<Object> {
+    BUDGET.with(|b| {
+        let mut b = b.borrow_mut();
+        b.steps += 1;
+
+        if let Some(max) = b.limits.max_steps {
+            if b.steps > max {
+                return Some(limit_exceeded(format!("more than {} steps", max)));
+            }
+        }
+
+        if b.steps % POLL_INTERVAL != 0 {
+            return None;
+        }
+
+        if let Some(interrupt) = &b.limits.interrupt {
+            if interrupt() {
+                return Some(limit_exceeded("interrupted".to_string()));
+            }
+        }
+
+        if let (Some(max), Some(clock)) = (b.limits.max_time_ms, &b.limits.clock) {
+            if clock() - b.started_at > max {
+                return Some(limit_exceeded(format!("ran longer than {}ms", max)));
+            }
+        }
+
+        None
+    })
+}
+
+fn enter_call() -> Option<Object> {
+    BUDGET.with(|b| {
+        let mut b = b.borrow_mut();
+        b.depth += 1;
+
+        match b.limits.max_depth {
+            Some(max) if b.depth > max => Some(limit_exceeded(format!("call depth above {}", max))),
+            _ => None,
+        }
+    })
+}
+
+fn leave_call() {
+    BUDGET.with(|b| b.borrow_mut().depth -= 1);
+}
+
+/// Checks the memory in use against the cap once a value is built. Returns
+/// the value itself, or an error once the cap is exceeded.
+fn allocate(obj: Object) -> Object {
+    BUDGET.with(|b| {
+        let b = b.borrow();
+        let in_use = heap::in_use().saturating_sub(b.heap_at_start);
+
+        match b.limits.max_allocated_bytes {
+            Some(max) if in_use > max => Object::ERROR(Error {
+                msg: format!("memory limit exceeded: more than {} bytes in use", max),
+            }),
+            _ => obj,
+        }
+    })
+}
+
+// Every nested call stacks up the frames of `eval_statement` and
+// `eval_expression`. The helpers marked `#[inline(never)]` keep their locals
+// out of those frames, so that calls take less native stack.
+fn eval_statement(stmt: &Statement, env: &Rc<RefCell<Environment>>) -> Option<Object> {
     match stmt {
         Statement::LETSTATEMENT(s) => eval_let_statement(s, env),
         Statement::RETURNSTATEMENT(s) => Some(eval_return_statement(s, env)),
         Statement::EXPRESSIONSTATEMENT(s) => Some(eval_expression_statement(s, env)),
+        Statement::EXPORTSTATEMENT(s) => eval_let_statement(&s.stmt, env),
+        Statement::IMPORTSTATEMENT(s) => eval_import_statement(s, env),
+    }
+}
+
+#[inline(never)]
+fn eval_import_statement(stmt: &ImportStatement, env: &Rc<RefCell<Environment>>) -> Option<Object> {
+    let exports = match import_module(&stmt.path) {
+        Ok(Object::HashLitearl(exports)) => exports,
+        Ok(other) => {
+            return Some(Object::ERROR(Error {
+                msg: format!(
+                    "module {} exports {} instead of a hash",
+                    stmt.path,
+                    other.inspect()
+                ),
+            }))
+        }
+        Err(msg) => return Some(Object::ERROR(Error { msg })),
+    };
+
+    if let Some(alias) = &stmt.alias {
+        bind(env, alias, Object::HashLitearl(exports));
+        return None;
+    }
+
+    for name in &stmt.names {
+        let key = StringLiteral {
+            value: name.value.clone(),
+        }
+        .hash_key();
+
+        match exports.pairs.get(&key) {
+            Some(pair) => bind(env, name, pair.value.clone()),
+            None => {
+                return Some(Object::ERROR(Error {
+                    msg: format!("module {:?} has no export named {}", stmt.path, name.value),
+                }))
+            }
+        }
+    }
+
+    None
+}
+
+/// Evaluates the module at `path` once and returns its exports as a hash.
+fn import_module(path: &str) -> Result<Object, String> {
+    let (name, source) = module::load(path)?;
+
+    if let Some(exports) = module::cached(&name) {
+        return Ok(exports);
+    }
+
+    module::begin(&name)?;
+    let exports = eval_module(&name, source);
+    module::finish(exports.as_ref().ok().cloned());
+
+    exports
+}
+
+fn eval_module(name: &str, source: String) -> Result<Object, String> {
+    let mut l = Lexer::new(source);
+    let mut p = Parser::new(&mut l);
+    let mut program = match p.parse_program() {
+        Some(program) if p.errors().is_empty() => program,
+        _ => return Err(format!("in module {}: {}", name, p.errors().join("; "))),
+    };
+
+    let env = new_environment()?;
+
+    let errors = resolver::resolve(&mut program, &env);
+    if !errors.is_empty() {
+        return Err(format!("in module {}: {}", name, errors.join("; ")));
+    }
+
+    let exported: Vec<String> = program
+        .statements
+        .iter()
+        .filter_map(|stmt| match stmt {
+            Statement::EXPORTSTATEMENT(s) => Some(s.stmt.pattern.bindings()),
+            _ => None,
+        })
+        .flatten()
+        .map(|name| name.value.clone())
+        .collect();
+
+    if let Object::ERROR(err) = eval_program(program, &env) {
+        return Err(format!("in module {}: {}", name, err.msg));
+    }
+
+    let mut pairs = HashMap::new();
+    for export in exported {
+        if let Some(value) = env.borrow().get(&export) {
+            let key = StringLiteral { value: export };
+            pairs.insert(
+                key.hash_key(),
+                HashPair {
+                    key: Object::STRING(key),
+                    value,
+                },
+            );
+        }
     }
+
+    Ok(Object::HashLitearl(HashObject::new(pairs)))
 }
 
-fn eval_let_statement(stmt: LetStatement, env: &Rc<RefCell<Environment>>) -> Option<Object> {
-    let val = eval_expression(stmt.value, env);
+fn eval_let_statement(stmt: &LetStatement, env: &Rc<RefCell<Environment>>) -> Option<Object> {
+    let val = eval_expression(&stmt.value, env);
 
     if is_error(&val) {
         return Some(val);
     }
 
-    env.borrow_mut().set(stmt.name.value, val);
+    if let Err(reason) = match_pattern(&stmt.pattern, &val, env) {
+        return Some(Object::ERROR(Error {
+            msg: format!(
+                "cannot destructure {} as {}: {}",
+                val.inspect(),
+                stmt.pattern.string(),
+                reason
+            ),
+        }));
+    }
 
     None
 }
-fn eval_return_statement(stmt: ReturnStatement, env: &Rc<RefCell<Environment>>) -> Object {
-    let val = eval_expression(stmt.return_value, env);
+
+fn bind(env: &Rc<RefCell<Environment>>, name: &Identifier, val: Object) {
+    match name.slot {
+        Some(slot) => env.borrow_mut().define(slot.index, name.value.clone(), val),
+        None => env.borrow_mut().set(name.value.clone(), val),
+    }
+}
+#[inline(never)]
+fn eval_return_statement(stmt: &ReturnStatement, env: &Rc<RefCell<Environment>>) -> Object {
+    let val = eval_expression(&stmt.return_value, env);
 
     if is_error(&val) {
         return val;
@@ -65,88 +441,119 @@ fn eval_return_statement(stmt: ReturnStatement, env: &Rc<RefCell<Environment>>)
         value: Box::new(val),
     })
 }
-fn eval_expression_statement(stmt: ExpressionStatement, env: &Rc<RefCell<Environment>>) -> Object {
-    eval_expression(stmt.expression, env)
+fn eval_expression_statement(stmt: &ExpressionStatement, env: &Rc<RefCell<Environment>>) -> Object {
+    eval_expression(&stmt.expression, env)
 }
 
-fn eval_expression(exp: EXPRESSION, env: &Rc<RefCell<Environment>>) -> Object {
+fn eval_expression(exp: &EXPRESSION, env: &Rc<RefCell<Environment>>) -> Object {
+    if let Some(err) = tick() {
+        return err;
+    }
+
     match exp {
         EXPRESSION::INTEGER(e) => Object::INTEGER(Integer { value: e.value }),
         EXPRESSION::BOOLEAN(e) => Object::BOOLEAN(Boolean { value: e.value }),
+        EXPRESSION::NULL(_) => Object::NULL(Null {}),
         EXPRESSION::IF(e) => eval_if_expression(e, env),
         EXPRESSION::IDENTIFIER(e) => eval_identifier(e, env),
         EXPRESSION::FN(e) => Object::FN(Function {
-            params: e.parameters,
-            body: e.body,
+            params: Rc::new(e.parameters.clone()),
+            body: Rc::new(e.body.clone()),
             env: Rc::clone(env),
         }),
         EXPRESSION::CALL(e) => eval_call_expression(e, env),
         EXPRESSION::PREFIX(e) => {
-            let right = eval_expression(*e.right, env);
+            let right = eval_expression(&e.right, env);
             if is_error(&right) {
                 return right;
             }
-            eval_prefix_expression(e.operator, right)
+            eval_prefix_expression(&e.operator, right)
         }
         EXPRESSION::INFIX(e) => {
-            let left = eval_expression(*e.left, env);
+            let left = eval_expression(&e.left, env);
             if is_error(&left) {
                 return left;
             }
-            let right = eval_expression(*e.right, env);
+            match left_decides(&e.operator, &left) {
+                Some(true) => return left,
+                Some(false) => return eval_expression(&e.right, env),
+                None => {}
+            }
+            if e.operator == "|>" {
+                return eval_pipe_expression(left, &e.right, env);
+            }
+            let right = eval_expression(&e.right, env);
             if is_error(&right) {
                 return right;
             }
-            eval_infix_expression(e.operator, left, right)
+            allocate(eval_infix_expression(&e.operator, left, right))
         }
-        EXPRESSION::StringLiteral(e) => Object::STRING(StringLiteral { value: e.value }),
+        EXPRESSION::StringLiteral(e) => Object::STRING(StringLiteral {
+            value: e.value.clone(),
+        }),
+        EXPRESSION::TEMPLATE(e) => eval_template_literal(e, env),
         EXPRESSION::ArrayLiteral(e) => {
-            let elements = eval_expressions(e.items, env);
+            let elements = eval_expressions(&e.items, env);
 
             if elements.len() == 1 && is_error(&elements[0]) {
                 return elements[0].clone();
             }
 
-            Object::ARRAY(Array { elements })
+            allocate(Object::ARRAY(Array::new(elements)))
         }
         EXPRESSION::IndexExpression(e) => {
-            let left = eval_expression(*e.left, env);
+            let left = eval_expression(&e.left, env);
             if is_error(&left) {
                 return left;
             }
 
-            let index = eval_expression(*e.index, env);
+            let index = eval_expression(&e.index, env);
             if is_error(&index) {
                 return index;
             }
 
             eval_index_expression(left, index)
         }
+        EXPRESSION::SLICE(e) => eval_slice_expression(e, env),
         EXPRESSION::HashLiteral(e) => eval_hash_literal(e, env),
+        EXPRESSION::DOT(e) => {
+            let left = eval_expression(&e.left, env);
+            match left {
+                Object::HashLitearl(_) => eval_index_expression(
+                    left,
+                    Object::STRING(StringLiteral {
+                        value: e.name.value.clone(),
+                    }),
+                ),
+                Object::ERROR(_) => left,
+                other => Object::ERROR(Error {
+                    msg: format!(
+                        "cannot read .{} of {}, only hashes have fields",
+                        e.name.value,
+                        other.inspect()
+                    ),
+                }),
+            }
+        }
+        EXPRESSION::METHOD(e) => eval_method_call_expression(e, env),
+        EXPRESSION::MATCH(e) => eval_match_expression(e, env),
     }
 }
 
-fn eval_hash_literal(hash_lit: HashLiteral, env: &Rc<RefCell<Environment>>) -> Object {
+#[inline(never)]
+fn eval_hash_literal(hash_lit: &HashLiteral, env: &Rc<RefCell<Environment>>) -> Object {
     let mut pairs = HashMap::new();
 
-    for (k, v) in hash_lit.pairs {
+    for (k, v) in &hash_lit.pairs {
         let key = eval_expression(k, env);
         if is_error(&key) {
             return key;
         }
 
-        let hash_key;
-
-        match &key {
-            Object::STRING(o) => {
-                hash_key = o.hash_key();
-            }
-            Object::INTEGER(o) => {
-                hash_key = o.hash_key();
-            }
-            Object::BOOLEAN(o) => {
-                hash_key = o.hash_key();
-            }
+        let hash_key = match &key {
+            Object::STRING(o) => o.hash_key(),
+            Object::INTEGER(o) => o.hash_key(),
+            Object::BOOLEAN(o) => o.hash_key(),
             other => {
                 return Object::ERROR(Error {
                     msg: format!("unusable as hash key: {:?}", other),
@@ -162,26 +569,32 @@ fn eval_hash_literal(hash_lit: HashLiteral, env: &Rc<RefCell<Environment>>) -> O
         pairs.insert(hash_key, HashPair { key, value });
     }
 
-    Object::HashLitearl(HashObject { pairs })
+    allocate(Object::HashLitearl(HashObject::new(pairs)))
 }
 
 fn eval_index_expression(left: Object, index: Object) -> Object {
     match (left, index) {
         (Object::ARRAY(arr), Object::INTEGER(i)) => {
-            let max = arr.elements.len();
-            let i = match usize::try_from(i.value) {
-                Ok(i) => i,
-                Err(_) => {
-                    return Object::NULL(Null {});
-                }
-            };
-
-            if i >= max {
-                return Object::NULL(Null {});
+            match sequence_index(i.value, arr.elements.len() as i64) {
+                Some(i) => arr.elements[i as usize].clone(),
+                None => Object::NULL(Null {}),
             }
-
-            arr.elements[i].clone()
         }
+        (Object::STRING(str), Object::INTEGER(i)) => {
+            let chars: Vec<char> = str.value.chars().collect();
+            match sequence_index(i.value, chars.len() as i64) {
+                Some(i) => Object::STRING(StringLiteral {
+                    value: chars[i as usize].to_string(),
+                }),
+                None => Object::NULL(Null {}),
+            }
+        }
+        (Object::RANGE(range), Object::INTEGER(i)) => match sequence_index(i.value, range.len()) {
+            Some(i) => Object::INTEGER(Integer {
+                value: range.start + i,
+            }),
+            None => Object::NULL(Null {}),
+        },
         (Object::HashLitearl(map), i) => {
             let key;
             match i {
@@ -212,48 +625,237 @@ fn eval_index_expression(left: Object, index: Object) -> Object {
     }
 }
 
-fn eval_call_expression(call_exp: CallExpression, env: &Rc<RefCell<Environment>>) -> Object {
-    let function = eval_expression(*call_exp.function, env);
+/// Resolves a possibly negative index against `len`, counting from the end
+/// like Python. `None` when it falls outside.
+fn sequence_index(index: i64, len: i64) -> Option<i64> {
+    let index = if index < 0 { index + len } else { index };
+    (0..len).contains(&index).then_some(index)
+}
+
+/// Clamps slice bounds to `0..=len`, counting negative bounds from the end.
+/// Bounds that cross give an empty slice rather than an error.
+fn slice_bounds(start: Option<i64>, end: Option<i64>, len: i64) -> (i64, i64) {
+    let clamp = |bound: i64| match bound < 0 {
+        true => (bound + len).max(0),
+        false => bound.min(len),
+    };
+    let start = start.map_or(0, clamp);
+    let end = end.map_or(len, clamp);
+    (start, end.max(start))
+}
+
+#[inline(never)]
+fn eval_slice_expression(e: &SliceExpression, env: &Rc<RefCell<Environment>>) -> Object {
+    let left = eval_expression(&e.left, env);
+    if is_error(&left) {
+        return left;
+    }
+
+    let mut bounds = [None, None];
+    for (slot, bound) in bounds.iter_mut().zip([&e.start, &e.end]) {
+        let Some(bound) = bound else { continue };
+        match eval_expression(bound, env) {
+            Object::INTEGER(i) => *slot = Some(i.value),
+            Object::NULL(_) => {}
+            Object::ERROR(err) => return Object::ERROR(err),
+            other => {
+                return Object::ERROR(Error {
+                    msg: format!("slice bounds must be integers, got {}", other.inspect()),
+                })
+            }
+        }
+    }
+    let [start, end] = bounds;
+
+    match left {
+        Object::ARRAY(arr) => {
+            let (start, end) = slice_bounds(start, end, arr.elements.len() as i64);
+            allocate(Object::ARRAY(Array::new(
+                arr.elements[start as usize..end as usize].to_vec(),
+            )))
+        }
+        Object::STRING(str) => {
+            let chars: Vec<char> = str.value.chars().collect();
+            let (start, end) = slice_bounds(start, end, chars.len() as i64);
+            allocate(Object::STRING(StringLiteral {
+                value: chars[start as usize..end as usize].iter().collect(),
+            }))
+        }
+        Object::RANGE(range) => {
+            let (start, end) = slice_bounds(start, end, range.len());
+            Object::RANGE(Range {
+                start: range.start + start,
+                end: range.start + end,
+            })
+        }
+        other => Object::ERROR(Error {
+            msg: format!("Slice operator not supported on {:?}", other),
+        }),
+    }
+}
+
+fn eval_call_expression(call_exp: &CallExpression, env: &Rc<RefCell<Environment>>) -> Object {
+    let function = eval_expression(&call_exp.function, env);
 
     if is_error(&function) {
         return function;
     }
 
-    let evaluated_args = eval_expressions(call_exp.args, env);
+    let evaluated_args = eval_expressions(&call_exp.args, env);
 
     if evaluated_args.len() == 1 && is_error(&evaluated_args[0]) {
         return evaluated_args[0].clone();
     }
 
+    apply_function(function, evaluated_args)
+}
+
+/// `left |> f(args)` calls `f(left, args)`. Anything else on the right is
+/// called with `left` as its only argument.
+fn eval_pipe_expression(
+    left: Object,
+    right: &EXPRESSION,
+    env: &Rc<RefCell<Environment>>,
+) -> Object {
+    let (function, args) = match right {
+        EXPRESSION::CALL(call) => (&*call.function, &call.args[..]),
+        other => (other, &[][..]),
+    };
+
+    let function = eval_expression(function, env);
+    if is_error(&function) {
+        return function;
+    }
+
+    let mut evaluated_args = vec![left];
+    for arg in eval_expressions(args, env) {
+        if is_error(&arg) {
+            return arg;
+        }
+        evaluated_args.push(arg);
+    }
+
+    apply_function(function, evaluated_args)
+}
+
+fn is_callable(obj: &Object) -> bool {
+    matches!(
+        obj,
+        Object::FN(_) | Object::BUILTINFUNC(_) | Object::COMPOSED(_)
+    )
+}
+
+/// Calls `receiver.method(args)` as `method(receiver, args)`.
+#[inline(never)]
+fn eval_method_call_expression(
+    exp: &MethodCallExpression,
+    env: &Rc<RefCell<Environment>>,
+) -> Object {
+    let receiver = eval_expression(&exp.receiver, env);
+    if is_error(&receiver) {
+        return receiver;
+    }
+
+    // A hash that has the field calls it, like a method of a record.
+    // Anything else is passed as the first argument of the function in scope.
+    let field = match &receiver {
+        Object::HashLitearl(hash) => {
+            let key = StringLiteral {
+                value: exp.method.value.clone(),
+            };
+            hash.pairs
+                .get(&key.hash_key())
+                .map(|pair| pair.value.clone())
+        }
+        _ => None,
+    };
+    let (function, mut args) = match field {
+        Some(field) => (field, vec![]),
+        None => (eval_identifier(&exp.method, env), vec![receiver]),
+    };
+    if is_error(&function) {
+        return function;
+    }
+
+    for arg in eval_expressions(&exp.args, env) {
+        if is_error(&arg) {
+            return arg;
+        }
+        args.push(arg);
+    }
+
+    apply_function(function, args)
+}
+
+fn apply_function(function: Object, evaluated_args: Vec<Object>) -> Object {
     match function {
         Object::FN(obj) => {
-            let extended_env = extend_fn_env(&obj, evaluated_args);
+            if let Some(err) = enter_call() {
+                leave_call();
+                return err;
+            }
+
+            let extended_env = match extend_fn_env(&obj, evaluated_args) {
+                Ok(env) => env,
+                Err(msg) => {
+                    leave_call();
+                    return Object::ERROR(Error { msg });
+                }
+            };
 
-            let evaluated_function = eval_block_statements(obj.body, &extended_env);
+            let evaluated_function = eval_block_statements(&obj.body, &extended_env);
+
+            leave_call();
 
             match evaluated_function {
                 Object::RETURN(obj) => *obj.value,
                 _ => evaluated_function,
             }
         }
-        Object::BUILTINFUNC(obj) => (obj.func)(evaluated_args),
+        Object::BUILTINFUNC(obj) => allocate((obj.func)(evaluated_args)),
+        Object::COMPOSED(obj) => {
+            let value = apply_function(*obj.first, evaluated_args);
+            if is_error(&value) {
+                return value;
+            }
+            apply_function(*obj.second, vec![value])
+        }
         other => Object::ERROR(Error {
             msg: format!("expected fn object. Got {:?}", other),
         }),
     }
 }
 
-fn extend_fn_env(function: &Function, args: Vec<Object>) -> Rc<RefCell<Environment>> {
-    let mut inner_env = enclosed_environment(&function.env);
+#[inline(never)]
+fn extend_fn_env(
+    function: &Function,
+    args: Vec<Object>,
+) -> Result<Rc<RefCell<Environment>>, String> {
+    if args.len() < function.params.len() {
+        return Err(format!(
+            "wrong number of arguments. got={}, want={}",
+            args.len(),
+            function.params.len()
+        ));
+    }
 
-    for (i, param) in function.params.iter().enumerate() {
-        inner_env.set(param.value.clone(), args[i].clone());
+    let inner_env = gc::track(enclosed_environment(&function.env));
+
+    for (param, arg) in function.params.iter().zip(args.iter()) {
+        if let Err(reason) = match_pattern(param, arg, &inner_env) {
+            return Err(format!(
+                "cannot destructure {} as {}: {}",
+                arg.inspect(),
+                param.string(),
+                reason
+            ));
+        }
     }
 
-    Rc::new(RefCell::new(inner_env))
+    Ok(inner_env)
 }
 
-fn eval_expressions(exps: Vec<EXPRESSION>, env: &Rc<RefCell<Environment>>) -> Vec<Object> {
+fn eval_expressions(exps: &[EXPRESSION], env: &Rc<RefCell<Environment>>) -> Vec<Object> {
     let mut result = vec![];
 
     for exp in exps {
@@ -269,9 +871,14 @@ fn eval_expressions(exps: Vec<EXPRESSION>, env: &Rc<RefCell<Environment>>) -> Ve
     result
 }
 
-fn eval_identifier(ident: Identifier, env: &Rc<RefCell<Environment>>) -> Object {
-    if let Some(obj) = env.borrow().get(&ident.value) {
-        return obj.clone();
+fn eval_identifier(ident: &Identifier, env: &Rc<RefCell<Environment>>) -> Object {
+    let found = match ident.slot {
+        Some(slot) => env.borrow().get_at(slot.depth, slot.index),
+        None => env.borrow().get(&ident.value),
+    };
+
+    if let Some(obj) = found {
+        return obj;
     };
 
     if let Some(obj) = BUILTINS.get(&ident.value.as_str()) {
@@ -279,12 +886,15 @@ fn eval_identifier(ident: Identifier, env: &Rc<RefCell<Environment>>) -> Object
     };
 
     Object::ERROR(Error {
-        msg: format!("identifier not found: {}", ident.value),
+        msg: format!(
+            "identifier not found: {} at line {}, column {}",
+            ident.value, ident.token.span.line, ident.token.span.column
+        ),
     })
 }
 
-fn eval_prefix_expression(operator: String, right: Object) -> Object {
-    match operator.as_str() {
+pub(crate) fn eval_prefix_expression(operator: &str, right: Object) -> Object {
+    match operator {
         "!" => eval_bang_operator_expression(right),
         "-" => eval_minus_operator_expression(right),
         _ => Object::ERROR(Error {
@@ -293,21 +903,83 @@ fn eval_prefix_expression(operator: String, right: Object) -> Object {
     }
 }
 
-fn eval_infix_expression(operator: String, left: Object, right: Object) -> Object {
+/// A range from `start` up to but excluding `end`, as long as its length
+/// fits in an integer.
+fn eval_range(start: i64, end: i64, left: &Integer, operator: &str, right: &Integer) -> Object {
+    if end > start && end.checked_sub(start).is_none() {
+        return Object::ERROR(Error {
+            msg: format!("range too long: {}{}{}", left.value, operator, right.value),
+        });
+    }
+
+    Object::RANGE(Range { start, end })
+}
+
+fn checked_integer(value: Option<i64>, left: &Integer, operator: &str, right: &Integer) -> Object {
+    match value {
+        Some(value) => Object::INTEGER(Integer { value }),
+        None => Object::ERROR(Error {
+            msg: format!(
+                "integer overflow: {} {} {}",
+                left.value, operator, right.value
+            ),
+        }),
+    }
+}
+
+/// Whether the left operand of `&&`, `||` or `??` is already the result, in
+/// which case the right one isn't evaluated. `None` for other operators.
+pub(crate) fn left_decides(operator: &str, left: &Object) -> Option<bool> {
+    match operator {
+        "&&" => Some(!is_truthy(left.clone())),
+        "||" => Some(is_truthy(left.clone())),
+        "??" => Some(!matches!(left, Object::NULL(_))),
+        _ => None,
+    }
+}
+
+pub(crate) fn eval_infix_expression(operator: &str, left: Object, right: Object) -> Object {
     match (left, right) {
-        (Object::INTEGER(obj1), Object::INTEGER(obj2)) => match operator.as_str() {
-            "+" => Object::INTEGER(Integer {
-                value: obj1.value + obj2.value,
-            }),
-            "-" => Object::INTEGER(Integer {
-                value: obj1.value - obj2.value,
+        (l, r) if operator == ">>" => match (is_callable(&l), is_callable(&r)) {
+            (true, true) => Object::COMPOSED(Composition {
+                first: Box::new(l),
+                second: Box::new(r),
             }),
-            "*" => Object::INTEGER(Integer {
-                value: obj1.value * obj2.value,
+            _ => Object::ERROR(Error {
+                msg: format!(
+                    "cannot compose {} >> {}, both sides must be functions",
+                    l.inspect(),
+                    r.inspect()
+                ),
             }),
-            "/" => Object::INTEGER(Integer {
-                value: obj1.value / obj2.value,
+        },
+        (Object::INTEGER(start), Object::INTEGER(end)) if operator == ".." => {
+            eval_range(start.value, end.value, &start, operator, &end)
+        }
+        (Object::INTEGER(start), Object::INTEGER(end)) if operator == "..=" => {
+            match end.value.checked_add(1) {
+                Some(exclusive) => eval_range(start.value, exclusive, &start, operator, &end),
+                None => Object::ERROR(Error {
+                    msg: format!("integer overflow: {}..={}", start.value, end.value),
+                }),
+            }
+        }
+        (l, r) if operator == ".." || operator == "..=" => Object::ERROR(Error {
+            msg: format!(
+                "range bounds must be integers, got {} {} {}",
+                l.inspect(),
+                operator,
+                r.inspect()
+            ),
+        }),
+        (Object::INTEGER(obj1), Object::INTEGER(obj2)) => match operator {
+            "+" => checked_integer(obj1.value.checked_add(obj2.value), &obj1, "+", &obj2),
+            "-" => checked_integer(obj1.value.checked_sub(obj2.value), &obj1, "-", &obj2),
+            "*" => checked_integer(obj1.value.checked_mul(obj2.value), &obj1, "*", &obj2),
+            "/" if obj2.value == 0 => Object::ERROR(Error {
+                msg: format!("division by zero: {} / 0", obj1.value),
             }),
+            "/" => checked_integer(obj1.value.checked_div(obj2.value), &obj1, "/", &obj2),
             "<" => Object::BOOLEAN(Boolean {
                 value: obj1.value < obj2.value,
             }),
@@ -324,7 +996,7 @@ fn eval_infix_expression(operator: String, left: Object, right: Object) -> Objec
                 msg: format!("unknown operator {:?} {} {:?}", obj1, other, obj2),
             }),
         },
-        (Object::BOOLEAN(obj1), Object::BOOLEAN(obj2)) => match operator.as_str() {
+        (Object::BOOLEAN(obj1), Object::BOOLEAN(obj2)) => match operator {
             "!=" => Object::BOOLEAN(Boolean {
                 value: obj1.value != obj2.value,
             }),
@@ -335,7 +1007,7 @@ fn eval_infix_expression(operator: String, left: Object, right: Object) -> Objec
                 msg: format!("unknown operator {:?} {} {:?}", obj1, other, obj2),
             }),
         },
-        (Object::STRING(obj1), Object::STRING(obj2)) => match operator.as_str() {
+        (Object::STRING(obj1), Object::STRING(obj2)) => match operator {
             "+" => Object::STRING(StringLiteral {
                 value: format!("{}{}", obj1.value, obj2.value),
             }),
@@ -343,6 +1015,16 @@ fn eval_infix_expression(operator: String, left: Object, right: Object) -> Objec
                 msg: format!("unknown operator {:?} {} {:?}", obj1, other, obj2),
             }),
         },
+        // Anything can be compared with null.
+        (l, r)
+            if (operator == "==" || operator == "!=")
+                && (matches!(l, Object::NULL(_)) || matches!(r, Object::NULL(_))) =>
+        {
+            let equal = matches!((l, r), (Object::NULL(_), Object::NULL(_)));
+            Object::BOOLEAN(Boolean {
+                value: equal == (operator == "=="),
+            })
+        }
         (l, r) => Object::ERROR(Error {
             msg: format!("type mismatch {:?} {} {:?}", l, operator, r),
         }),
@@ -358,34 +1040,161 @@ fn eval_bang_operator_expression(object: Object) -> Object {
 }
 fn eval_minus_operator_expression(object: Object) -> Object {
     match object {
-        Object::INTEGER(obj) => Object::INTEGER(Integer { value: -obj.value }),
+        Object::INTEGER(obj) => match obj.value.checked_neg() {
+            Some(value) => Object::INTEGER(Integer { value }),
+            None => Object::ERROR(Error {
+                msg: format!("integer overflow: -{}", obj.value),
+            }),
+        },
         _ => Object::ERROR(Error {
             msg: format!("unknown operator -{:?}", object),
         }),
     }
 }
 
-fn eval_if_expression(exp: IfExpression, env: &Rc<RefCell<Environment>>) -> Object {
-    let condition = eval_expression(*exp.condition, env);
+fn eval_if_expression(exp: &IfExpression, env: &Rc<RefCell<Environment>>) -> Object {
+    let condition = eval_expression(&exp.condition, env);
 
     if is_error(&condition) {
         return condition;
     }
 
     if is_truthy(condition) {
-        return eval_block_statements(exp.consequence, env);
+        return eval_block_statements(&exp.consequence, env);
     }
 
-    match exp.alternative {
-        Some(alt) => eval_block_statements(alt, env),
+    match &exp.alternative {
+        Some(ElseBranch::BLOCK(alt)) => eval_block_statements(alt, env),
+        Some(ElseBranch::IF(alt)) => eval_if_expression(alt, env),
         None => Object::NULL(Null {}),
     }
 }
 
-fn eval_block_statements(block_stmt: BlockStatement, env: &Rc<RefCell<Environment>>) -> Object {
+/// Tries the arms in order, each in an environment of its own that holds
+/// what its pattern binds.
+#[inline(never)]
+fn eval_match_expression(exp: &MatchExpression, env: &Rc<RefCell<Environment>>) -> Object {
+    let value = eval_expression(&exp.value, env);
+    if is_error(&value) {
+        return value;
+    }
+
+    for arm in &exp.arms {
+        let arm_env = gc::track(enclosed_environment(env));
+        if match_pattern(&arm.pattern, &value, &arm_env).is_err() {
+            continue;
+        }
+
+        if let Some(guard) = &arm.guard {
+            let guard = eval_expression(guard, &arm_env);
+            if is_error(&guard) {
+                return guard;
+            }
+            if !is_truthy(guard) {
+                continue;
+            }
+        }
+
+        return eval_expression(&arm.body, &arm_env);
+    }
+
+    Object::ERROR(Error {
+        msg: format!("no match arm for {}", value.inspect()),
+    })
+}
+
+fn eval_template_literal(exp: &TemplateLiteral, env: &Rc<RefCell<Environment>>) -> Object {
+    let mut value = exp.strings[0].clone();
+    for (e, string) in exp.expressions.iter().zip(exp.strings[1..].iter()) {
+        let evaluated = eval_expression(e, env);
+        if is_error(&evaluated) {
+            return evaluated;
+        }
+        value.push_str(&evaluated.inspect());
+        value.push_str(string);
+    }
+
+    allocate(Object::STRING(StringLiteral { value }))
+}
+
+/// Binds the names of `pattern` in `env` if `value` has its shape, or says
+/// why it doesn't.
+fn match_pattern(
+    pattern: &Pattern,
+    value: &Object,
+    env: &Rc<RefCell<Environment>>,
+) -> Result<(), String> {
+    match (pattern, value) {
+        (Pattern::WILDCARD(_), _) => Ok(()),
+        (Pattern::BINDING(name), _) => {
+            bind(env, name, value.clone());
+            Ok(())
+        }
+        (Pattern::LITERAL(literal), _) => {
+            let literal = eval_expression(literal, env);
+            let equal = match (&literal, value) {
+                (Object::NULL(_), Object::NULL(_)) => true,
+                _ => hash_key(&literal).is_some() && hash_key(&literal) == hash_key(value),
+            };
+            match equal {
+                true => Ok(()),
+                false => Err(format!(
+                    "expected {}, got {}",
+                    literal.inspect(),
+                    value.inspect()
+                )),
+            }
+        }
+        (Pattern::ARRAY(p), Object::ARRAY(arr)) => {
+            let (want, got) = (p.items.len(), arr.elements.len());
+            match &p.rest {
+                Some(_) if got < want => {
+                    return Err(format!("expected at least {} items, got {}", want, got))
+                }
+                None if got != want => return Err(format!("expected {} items, got {}", want, got)),
+                _ => {}
+            }
+
+            for (item, element) in p.items.iter().zip(arr.elements.iter()) {
+                match_pattern(item, element, env)?;
+            }
+            if let Some(rest) = &p.rest {
+                let elements = arr.elements[want..].to_vec();
+                match allocate(Object::ARRAY(Array::new(elements))) {
+                    Object::ERROR(e) => return Err(e.msg),
+                    rest_value => match_pattern(rest, &rest_value, env)?,
+                }
+            }
+            Ok(())
+        }
+        (Pattern::HASH(p), Object::HashLitearl(hash)) => {
+            for (key, item) in p.pairs.iter() {
+                let key = eval_expression(key, env);
+                match hash_key(&key).and_then(|k| hash.pairs.get(&k)) {
+                    Some(pair) => match_pattern(item, &pair.value, env)?,
+                    None => return Err(format!("missing key {}", key.inspect())),
+                }
+            }
+            Ok(())
+        }
+        (Pattern::ARRAY(_), _) => Err(format!("expected an array, got {}", value.inspect())),
+        (Pattern::HASH(_), _) => Err(format!("expected a hash, got {}", value.inspect())),
+    }
+}
+
+fn hash_key(obj: &Object) -> Option<HashKey> {
+    match obj {
+        Object::INTEGER(o) => Some(o.hash_key()),
+        Object::BOOLEAN(o) => Some(o.hash_key()),
+        Object::STRING(o) => Some(o.hash_key()),
+        _ => None,
+    }
+}
+
+fn eval_block_statements(block_stmt: &BlockStatement, env: &Rc<RefCell<Environment>>) -> Object {
     let mut result = Object::NULL(Null {});
 
-    for stmt in block_stmt.statements {
+    for stmt in &block_stmt.statements {
         match eval_statement(stmt, env) {
             Some(r) => result = r,
             None => continue,
@@ -409,21 +1218,23 @@ fn is_truthy(obj: Object) -> bool {
 }
 
 fn is_error(obj: &Object) -> bool {
-    match obj {
-        Object::ERROR(_) => true,
-        _ => false,
-    }
+    matches!(obj, Object::ERROR(_))
 }
 
 #[cfg(test)]
+#[allow(clippy::cmp_owned)]
 mod tests {
+    use std::cell::Cell;
     use std::collections::HashMap;
+    use std::rc::Rc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
 
-    use super::eval;
+    use super::{eval, eval_with_limits, Limits, MAX_DEPTH, STACK_SIZE};
 
     use crate::ast::Node;
     use crate::lexer::Lexer;
-    use crate::object::{Boolean, Hashable, Integer, Object, ObjectTrait, StringLiteral};
+    use crate::object::{Boolean, Hashable, Integer, Null, Object, ObjectTrait, StringLiteral};
     use crate::parser::Parser;
 
     #[test]
@@ -670,6 +1481,22 @@ mod tests {
                 input: "if (1 < 2) { 10 } else { 20 }".to_string(),
                 expected: Some(10),
             },
+            Test {
+                input: "if 1 < 2 { 10 }".to_string(),
+                expected: Some(10),
+            },
+            Test {
+                input: "if 1 > 2 { 10 } else if 2 > 1 { 20 } else { 30 }".to_string(),
+                expected: Some(20),
+            },
+            Test {
+                input: "if 1 > 2 { 10 } else if 2 > 3 { 20 } else { 30 }".to_string(),
+                expected: Some(30),
+            },
+            Test {
+                input: "if 1 > 2 { 10 } else if 2 > 3 { 20 }".to_string(),
+                expected: None,
+            },
         ];
 
         for test in tests {
@@ -741,6 +1568,23 @@ mod tests {
                 input: "let a = 5; let b = a; let c = a + b + 5; c;".to_string(),
                 expected: 15,
             },
+            Test {
+                input: "let x = 1; let f = fn() { let x = x + 1; x }; f()".to_string(),
+                expected: 2,
+            },
+            Test {
+                input: "let x = 1; let f = fn() { let y = x; let x = 2; y }; f()".to_string(),
+                expected: 1,
+            },
+            Test {
+                input: "let x = 1; let f = fn() { let g = fn() { x }; let x = 2; g() }; f()"
+                    .to_string(),
+                expected: 2,
+            },
+            Test {
+                input: "let f = fn() { g() }; let g = fn() { 3 }; f()".to_string(),
+                expected: 3,
+            },
         ];
 
         for test in tests {
@@ -824,6 +1668,387 @@ mod tests {
         test_integer_object(evaluated_val, 4);
     }
 
+    #[test]
+    fn test_logical_operators() {
+        struct Test {
+            input: String,
+            expected: Object,
+        }
+
+        let tests = [
+            Test {
+                input: "true && 1 < 2".to_string(),
+                expected: Object::BOOLEAN(Boolean { value: true }),
+            },
+            Test {
+                input: "1 > 2 || 3".to_string(),
+                expected: Object::INTEGER(Integer { value: 3 }),
+            },
+            Test {
+                input: "0 && 5".to_string(),
+                expected: Object::INTEGER(Integer { value: 5 }),
+            },
+            Test {
+                input: "false && 1 / 0".to_string(),
+                expected: Object::BOOLEAN(Boolean { value: false }),
+            },
+            Test {
+                input: "let f = fn() { 1 / 0 }; true || f()".to_string(),
+                expected: Object::BOOLEAN(Boolean { value: true }),
+            },
+            Test {
+                input: "null ?? 7".to_string(),
+                expected: Object::INTEGER(Integer { value: 7 }),
+            },
+            Test {
+                input: "false ?? 7".to_string(),
+                expected: Object::BOOLEAN(Boolean { value: false }),
+            },
+            Test {
+                input: "let h = {\"a\": 1}; h[\"b\"] ?? h[\"a\"]".to_string(),
+                expected: Object::INTEGER(Integer { value: 1 }),
+            },
+            Test {
+                input: "false || null ?? 2".to_string(),
+                expected: Object::INTEGER(Integer { value: 2 }),
+            },
+            Test {
+                input: "null == null".to_string(),
+                expected: Object::BOOLEAN(Boolean { value: true }),
+            },
+            Test {
+                input: "[] != null".to_string(),
+                expected: Object::BOOLEAN(Boolean { value: true }),
+            },
+            Test {
+                input: "null".to_string(),
+                expected: Object::NULL(Null {}),
+            },
+        ];
+
+        for test in tests {
+            let evaluated = test_eval(test.input.clone());
+            match (&evaluated, &test.expected) {
+                (Object::INTEGER(_), Object::INTEGER(e)) => test_integer_object(evaluated, e.value),
+                (Object::BOOLEAN(_), Object::BOOLEAN(e)) => test_boolean_object(evaluated, e.value),
+                (Object::NULL(_), Object::NULL(_)) => {}
+                _ => panic!(
+                    "{:?}: expected {:?}, got {:?}",
+                    test.input, test.expected, evaluated
+                ),
+            }
+        }
+    }
+
+    #[test]
+    fn test_match_expression() {
+        let tests = [
+            ("match (0) { 0 => \"zero\", _ => \"many\" }", "zero"),
+            ("match 5 { 0 => \"zero\", n => n * 2 }", "10"),
+            ("match -1 { -1 => \"minus one\", _ => \"other\" }", "minus one"),
+            ("match null { null => 1, _ => 2 }", "1"),
+            ("match \"b\" { \"a\" => 1, \"b\" => 2, _ => 3 }", "2"),
+            ("match [1, 2, 3] { [] => 0, [first, ...rest] => rest }", "[2, 3]"),
+            ("match [1, 2] { [a] => a, [a, b] => a + b }", "3"),
+            ("match [1] { [a, b, ...rest] => 0, [a, ..._] => a }", "1"),
+            ("match 1 { [a] => a, {\"a\": a} => a, _ => \"neither\" }", "neither"),
+            (
+                "let op = {\"type\": \"add\", \"x\": 2, \"y\": 3};
+                 match op {
+                     {\"type\": \"sub\", \"x\": x, \"y\": y} => x - y,
+                     {\"type\": \"add\", \"x\": x, \"y\": y} => x + y,
+                 }",
+                "5",
+            ),
+            ("match 7 { n if n < 5 => \"small\", n if n < 10 => \"medium\", _ => \"large\" }", "medium"),
+            ("let x = 1; match 2 { x => x }; x", "1"),
+            (
+                "let len_of = fn(arr) { match arr { [] => 0, [_, ...rest] => 1 + len_of(rest) } }; len_of([1, 2, 3])",
+                "3",
+            ),
+            ("match 3 { 1 => true, 2 => false }", "no match arm for 3"),
+            ("match [1] { [a] if a / 0 => a }", "division by zero: 1 / 0"),
+        ];
+
+        for (input, expected) in tests {
+            let evaluated = test_eval(input.to_string());
+            let actual = match evaluated {
+                Object::ERROR(e) => e.msg,
+                other => other.inspect(),
+            };
+            if actual != expected {
+                panic!("{}: expected {}, got {}", input, expected, actual);
+            }
+        }
+    }
+
+    #[test]
+    fn test_pipe_and_compose() {
+        let tests = [
+            (
+                "[1, 2, 3, 4] |> filter(fn(x) { x > 2 }) |> map(fn(x) { x * x }) |> sum()",
+                "25",
+            ),
+            ("let xs = [1, 2]; xs |> push(3) |> len", "3"),
+            ("let add = fn(a, b) { a + b }; 1 + 2 |> add(10)", "13"),
+            ("null ?? 1 |> fn(x) { x + 1 }()", "2"),
+            (
+                "let inc = fn(x) { x + 1 }; let double = fn(x) { x * 2 }; (inc >> double)(3)",
+                "8",
+            ),
+            ("let inc = fn(x) { x + 1 }; 3 |> inc >> inc >> inc", "6"),
+            ("let first_len = first >> len; first_len([\"abc\"])", "3"),
+            (
+                "let add = fn(a, b) { a + b }; (add >> len)(1, 2)",
+                "argument to `len` not supported, got INTEGER",
+            ),
+            (
+                "1 |> 2",
+                "expected fn object. Got INTEGER(Integer { value: 2 })",
+            ),
+            (
+                "let f = fn(x) { x }; f >> 1",
+                "cannot compose fn(x){\nx\n} >> 1, both sides must be functions",
+            ),
+            (
+                "let f = fn(x) { x }; (f >> f)(1 / 0)",
+                "division by zero: 1 / 0",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let evaluated = test_eval(input.to_string());
+            let actual = match evaluated {
+                Object::ERROR(e) => e.msg,
+                other => other.inspect(),
+            };
+            if actual != expected {
+                panic!("{}: expected {}, got {}", input, expected, actual);
+            }
+        }
+    }
+
+    #[test]
+    fn test_ranges_and_slices() {
+        let tests = [
+            ("1..5", "1..5"),
+            ("1..=5", "1..6"),
+            ("len(1..5) + len(1..=5) + len(5..1)", "9"),
+            (
+                "[first(2..5), last(2..5), first(3..3), rest(3..3)]",
+                "[2, 4, null, null]",
+            ),
+            ("(1..=4) |> map(fn(x) { x * x })", "[1, 4, 9, 16]"),
+            ("sum(1..=10)", "55"),
+            ("(0..10) |> filter(fn(x) { x / 2 * 2 == x }) |> len", "5"),
+            ("[(0..10)[3], (0..10)[-1], (0..10)[10]]", "[3, 9, null]"),
+            (
+                "[(0..10)[2:5], (0..10)[-3:], (5..0)[:]]",
+                "[2..5, 7..10, 5..5]",
+            ),
+            (
+                "let xs = [1, 2, 3]; [xs[-1], xs[-3], xs[-4], xs[3]]",
+                "[3, 1, null, null]",
+            ),
+            ("let xs = [1, 2, 3, 4]; xs[1:3]", "[2, 3]"),
+            ("let xs = [1, 2, 3, 4]; xs[:-1]", "[1, 2, 3]"),
+            (
+                "let xs = [1, 2, 3, 4]; [xs[2:], xs[:], xs[-10:10]]",
+                "[[3, 4], [1, 2, 3, 4], [1, 2, 3, 4]]",
+            ),
+            (
+                "let xs = [1, 2, 3, 4]; [xs[3:1], xs[5:], xs[:-5]]",
+                "[[], [], []]",
+            ),
+            ("let xs = [1, 2, 3]; xs[null:2]", "[1, 2]"),
+            (
+                "let s = \"héllo\"; [s[1], s[-1], s[2:], s[:-1], s[9]]",
+                "[é, o, llo, héll, null]",
+            ),
+            ("let s = \"héllo\"; s[len(s) - 1]", "o"),
+            ("let s = \"hello\"; `${s[1:3]}`", "el"),
+            ("1..true", "range bounds must be integers, got 1 .. true"),
+            (
+                "let n = 9223372036854775807; 1..=n",
+                "integer overflow: 1..=9223372036854775807",
+            ),
+            (
+                "-9223372036854775807..9223372036854775807",
+                "range too long: -9223372036854775807..9223372036854775807",
+            ),
+            (
+                "let n = 9223372036854775806; -1..=n",
+                "range too long: -1..=9223372036854775806",
+            ),
+            (
+                "let r = -4611686018427387904..4611686018427387903; [len(r), r[-1], r[0]]",
+                "[9223372036854775807, 4611686018427387902, -4611686018427387904]",
+            ),
+            ("[1, 2][\"a\":]", "slice bounds must be integers, got a"),
+            ("[1, 2][1 / 0:]", "division by zero: 1 / 0"),
+            (
+                "5[1:]",
+                "Slice operator not supported on INTEGER(Integer { value: 5 })",
+            ),
+            (
+                "push(1..3, 3)",
+                "argument to `push` not supported, got RANGE",
+            ),
+            ("sum(1..=10000)", "50005000"),
+            ("len(map(0..3000, fn(x) { x * 2 }))", "3000"),
+            ("last(map(0..3000, fn(x) { x * 2 }))", "5998"),
+            ("len(filter(0..6000, fn(x) { x < 3000 }))", "3000"),
+            ("reduce(0..10000, 0, fn(acc, x) { acc + 1 })", "10000"),
+            ("sum(map(range(0, 3000), fn(x) { 1 }))", "3000"),
+            ("[sum([]), map([], identity), reduce(5..1, 7, identity)]", "[0, [], 7]"),
+            ("map(1, identity)", "argument to `len` not supported, got INTEGER"),
+            ("sum([1, \"a\"])", "type mismatch INTEGER(Integer { value: 1 }) + STRING(StringLiteral { value: \"a\" })"),
+            ("map(1..3, fn(x) { x / 0 })", "division by zero: 1 / 0"),
+        ];
+
+        for (input, expected) in tests {
+            let evaluated = test_eval(input.to_string());
+            let actual = match evaluated {
+                Object::ERROR(e) => e.msg,
+                other => other.inspect(),
+            };
+            if actual != expected {
+                panic!("{}: expected {}, got {}", input, expected, actual);
+            }
+        }
+    }
+
+    #[test]
+    fn test_dot_and_method_call() {
+        let tests = [
+            (
+                "let person = {\"name\": \"Ann\", \"age\": 30}; person.name",
+                "Ann",
+            ),
+            ("let person = {\"name\": \"Ann\"}; person.age", "null"),
+            ("let h = {\"a\": {\"b\": [1, 2]}}; h.a.b[1]", "2"),
+            ("[1, 2, 3].len()", "3"),
+            ("[1, 2].push(3).map(fn(x) { x * 2 })", "[2, 4, 6]"),
+            ("let double = fn(x) { x * 2 }; 5.double().double()", "20"),
+            ("let add = fn(a, b) { a + b }; 1.add(2)", "3"),
+            ("\"abc\".len() + [1].first()", "4"),
+            ("let h = {\"f\": fn() { 1 }}; (h.f)()", "1"),
+            ("let h = {\"f\": fn(x) { x + 1 }}; h.f(1)", "2"),
+            (
+                "let counter = {\"n\": 2, \"twice\": fn(x) { x * 2 }}; counter.twice(counter.n)",
+                "4",
+            ),
+            (
+                "let get = fn(h, k) { h[k] }; let h = {\"a\": 1}; h.get(\"a\")",
+                "1",
+            ),
+            ("let h = {\"len\": fn() { 7 }}; h.len()", "7"),
+            (
+                "let h = {\"a\": 1}; h.missing()",
+                "identifier not found: missing at line 1, column 21",
+            ),
+            (
+                "[1, 2].name",
+                "cannot read .name of [1, 2], only hashes have fields",
+            ),
+            ("1.len()", "argument to `len` not supported, got INTEGER"),
+        ];
+
+        for (input, expected) in tests {
+            let evaluated = test_eval(input.to_string());
+            let actual = match evaluated {
+                Object::ERROR(e) => e.msg,
+                other => other.inspect(),
+            };
+            if actual != expected {
+                panic!("{}: expected {}, got {}", input, expected, actual);
+            }
+        }
+    }
+
+    #[test]
+    fn test_template_literal() {
+        let tests = [
+            ("`plain`", "plain"),
+            (
+                "let name = \"Ann\"; let age = 30; `Hello ${name}, you are ${age + 1}`",
+                "Hello Ann, you are 31",
+            ),
+            (
+                "`${[1, 2]} ${{\"a\": true}} ${null} ${`in${1 + 1}ner`}`",
+                "[1, 2] {a: true} null in2ner",
+            ),
+            ("`a\n${1}\nb`", "a\n1\nb"),
+            ("`\\${x} \\` \\\\`", "${x} ` \\"),
+            ("`${1 / 0}`", "division by zero: 1 / 0"),
+        ];
+
+        for (input, expected) in tests {
+            let evaluated = test_eval(input.to_string());
+            let actual = match evaluated {
+                Object::ERROR(e) => e.msg,
+                other => other.inspect(),
+            };
+            if actual != expected {
+                panic!("{}: expected {}, got {}", input, expected, actual);
+            }
+        }
+    }
+
+    #[test]
+    fn test_destructuring() {
+        let tests = [
+            ("let [a, b] = [1, 2]; a + b", "3"),
+            ("let [first, ...rest] = [1, 2, 3]; rest", "[2, 3]"),
+            ("let [_, second] = [1, 2]; second", "2"),
+            (
+                "let {name, age} = {\"name\": \"Ann\", \"age\": 30}; name",
+                "Ann",
+            ),
+            (
+                "let {\"x\": x, \"y\": [y, z]} = {\"x\": 1, \"y\": [2, 3]}; x + y + z",
+                "6",
+            ),
+            ("let add = fn([a, b]) { a + b }; add([1, 2])", "3"),
+            (
+                "let greet = fn({name}, punct) { name + punct }; greet({\"name\": \"Bo\"}, \"!\")",
+                "Bo!",
+            ),
+            ("export let [x, y] = [1, 2]; x + y", "3"),
+            (
+                "let [a, b] = [1];",
+                "cannot destructure [1] as [a, b]: expected 2 items, got 1",
+            ),
+            (
+                "let [a, ...rest] = 5;",
+                "cannot destructure 5 as [a, ...rest]: expected an array, got 5",
+            ),
+            (
+                "let {name} = {\"age\": 1};",
+                "cannot destructure {age: 1} as {name}: missing key name",
+            ),
+            (
+                "let f = fn([x]) { x }; f([1, 2])",
+                "cannot destructure [1, 2] as [x]: expected 1 items, got 2",
+            ),
+            (
+                "let f = fn(a, b) { a }; f(1)",
+                "wrong number of arguments. got=1, want=2",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let evaluated = test_eval(input.to_string());
+            let actual = match evaluated {
+                Object::ERROR(e) => e.msg,
+                other => other.inspect(),
+            };
+            if actual != expected {
+                panic!("{}: expected {}, got {}", input, expected, actual);
+            }
+        }
+    }
+
     #[test]
     fn test_error_handling() {
         struct Test {
@@ -870,7 +2095,19 @@ mod tests {
             },
             Test {
                 input: "foobar".to_string(),
-                expected: "identifier not found: foobar".to_string(),
+                expected: "identifier not found: foobar at line 1, column 1".to_string(),
+            },
+            Test {
+                input: "let f = fn() { g }; let x = f(); let g = 1;".to_string(),
+                expected: "identifier not found: g at line 1, column 16".to_string(),
+            },
+            Test {
+                input: "9223372036854775807 + 1".to_string(),
+                expected: "integer overflow: 9223372036854775807 + 1".to_string(),
+            },
+            Test {
+                input: "let zero = 0; 10 / zero".to_string(),
+                expected: "division by zero: 10 / 0".to_string(),
             },
             Test {
                 input: "\"Hello\" - \"World\"".to_string(),
@@ -944,6 +2181,10 @@ mod tests {
                 input: "len(\"hello world\")".to_string(),
                 expected: 11.to_string(),
             },
+            Test {
+                input: "len(\"héllo\")".to_string(),
+                expected: 5.to_string(),
+            },
             Test {
                 input: "len(1)".to_string(),
                 expected: "argument to `len` not supported, got INTEGER".to_string(),
@@ -1012,6 +2253,30 @@ mod tests {
                 input: "push([], 1)".to_string(),
                 expected: "[1]".to_string(),
             },
+            Test {
+                input: "first(1)".to_string(),
+                expected: "argument to `first` not supported, got INTEGER".to_string(),
+            },
+            Test {
+                input: "last(null)".to_string(),
+                expected: "argument to `last` not supported, got NULL".to_string(),
+            },
+            Test {
+                input: "rest(\"ab\")".to_string(),
+                expected: "argument to `rest` not supported, got STRING".to_string(),
+            },
+            Test {
+                input: "push(1, 2)".to_string(),
+                expected: "argument to `push` not supported, got INTEGER".to_string(),
+            },
+            Test {
+                input: "len(fn(x) { x })".to_string(),
+                expected: "argument to `len` not supported, got FUNCTION".to_string(),
+            },
+            Test {
+                input: "5.first()".to_string(),
+                expected: "argument to `first` not supported, got INTEGER".to_string(),
+            },
             Test {
                 input: "puts([1, true, \"hello\"])".to_string(),
                 expected: "null".to_string(),
@@ -1022,7 +2287,7 @@ mod tests {
 
             match evaluated_val {
                 Object::INTEGER(num) => {
-                    if num.value != test.expected.parse().unwrap() {
+                    if num.value != test.expected.parse::<i64>().unwrap() {
                         panic!(
                             "{}'s length is {}. Got:  {}",
                             test.input, test.expected, num.value
@@ -1118,6 +2383,10 @@ mod tests {
             },
             Test {
                 input: "[1, 2, 3][-1]".to_string(),
+                expected: Some(3),
+            },
+            Test {
+                input: "[1, 2, 3][-4]".to_string(),
                 expected: None,
             },
         ];
@@ -1242,6 +2511,188 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_execution_limits() {
+        let looping = "let f = fn(x) { f(x + 1) }; f(0);";
+        let long_running = "1 + 1;".repeat(5000);
+
+        let limited = |input: &str, limits: Limits| {
+            let mut l = Lexer::new(input.to_string());
+            let mut p = Parser::new(&mut l);
+            eval_with_limits(p.parse_program().unwrap(), limits)
+        };
+
+        let flag = Arc::new(AtomicBool::new(true));
+        let interrupt_flag = Arc::clone(&flag);
+        let now = Rc::new(Cell::new(0.0));
+        let clock_now = Rc::clone(&now);
+
+        let tests = [
+            (
+                looping,
+                Limits {
+                    max_steps: Some(100),
+                    ..Default::default()
+                },
+                "execution limit exceeded: more than 100 steps",
+            ),
+            (
+                looping,
+                Limits {
+                    max_depth: Some(50),
+                    ..Default::default()
+                },
+                "execution limit exceeded: call depth above 50",
+            ),
+            (
+                long_running.as_str(),
+                Limits {
+                    interrupt: Some(Rc::new(move || interrupt_flag.load(Ordering::Relaxed))),
+                    ..Default::default()
+                },
+                "execution limit exceeded: interrupted",
+            ),
+            (
+                long_running.as_str(),
+                Limits {
+                    max_time_ms: Some(10.0),
+                    clock: Some(Rc::new(move || {
+                        clock_now.set(clock_now.get() + 1.0);
+                        clock_now.get()
+                    })),
+                    ..Default::default()
+                },
+                "execution limit exceeded: ran longer than 10ms",
+            ),
+            (
+                "sum(0..3000000000)",
+                Limits {
+                    max_steps: Some(1000),
+                    ..Default::default()
+                },
+                "execution limit exceeded: more than 1000 steps",
+            ),
+            (
+                "map(0..3000000000, identity)",
+                Limits {
+                    max_steps: Some(10000),
+                    ..Default::default()
+                },
+                "execution limit exceeded: more than 10000 steps",
+            ),
+        ];
+
+        for (input, limits, expected) in tests {
+            match limited(input, limits) {
+                Object::ERROR(err) => {
+                    if err.msg != expected {
+                        panic!("wrong error msg. Expected {} got {}", expected, err.msg)
+                    }
+                }
+                other => panic!("no error object returned. Got {:?}", other),
+            }
+        }
+
+        let within_limits = limited(
+            "let add = fn(x, y) { x + y }; add(1, 2);",
+            Limits {
+                max_steps: Some(100),
+                max_depth: Some(1),
+                ..Default::default()
+            },
+        );
+        test_integer_object(within_limits, 3);
+        test_integer_object(test_eval("let a = 1; a;".to_string()), 1);
+    }
+
+    #[test]
+    fn test_memory_limit() {
+        let exceeded = Err("memory limit exceeded: more than 1048576 bytes in use");
+        let tests = [
+            (
+                "let grow = fn(arr) { grow(push(arr, arr)) }; grow([1]);",
+                exceeded,
+            ),
+            ("let grow = fn(s) { grow(s + s) }; grow(\"ab\");", exceeded),
+            (
+                "let grow = fn(h) { grow({1: h, 2: h}) }; grow({});",
+                exceeded,
+            ),
+            ("len(push([1, 2], \"three\"))", Ok("3")),
+            // Only what is held at once counts, not everything built.
+            (
+                "let xs = [1, 2, 3, 4, 5, 6, 7, 8]; reduce(0..20000, 0, fn(n, x) { n + len(push(xs, x)) })",
+                Ok("180000"),
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let mut l = Lexer::new(input.to_string());
+            let mut p = Parser::new(&mut l);
+            let evaluated_val = eval_with_limits(
+                p.parse_program().unwrap(),
+                Limits {
+                    max_allocated_bytes: Some(1 << 20),
+                    ..Default::default()
+                },
+            );
+
+            match (evaluated_val, expected) {
+                (Object::ERROR(err), Err(expected)) => {
+                    if err.msg != expected {
+                        panic!("wrong error msg. Expected {} got {}", expected, err.msg)
+                    }
+                }
+                (other, Ok(expected)) => {
+                    if other.inspect() != expected {
+                        panic!("{}: expected {} got {:?}", input, expected, other)
+                    }
+                }
+                (other, _) => panic!("no error object returned. Got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_deep_recursion_fits_the_stack() {
+        let countdown = "let f = fn(n) { if (n == 0) { 0 } else { 1 + f(n - 1) } };";
+        let tests = [
+            (MAX_DEPTH - 10, format!("{}", MAX_DEPTH - 10)),
+            (
+                100_000,
+                format!(
+                    "Error: execution limit exceeded: call depth above {}",
+                    MAX_DEPTH
+                ),
+            ),
+        ];
+
+        for (n, expected) in tests {
+            let input = format!("{} f({})", countdown, n);
+            let result = std::thread::Builder::new()
+                .stack_size(STACK_SIZE)
+                .spawn(move || {
+                    let mut l = Lexer::new(input);
+                    let mut p = Parser::new(&mut l);
+                    let limits = Limits {
+                        max_depth: Some(MAX_DEPTH),
+                        ..Default::default()
+                    };
+                    eval_with_limits(p.parse_program().unwrap(), limits).inspect()
+                })
+                .unwrap()
+                .join()
+                .unwrap();
+
+            if result != expected {
+                panic!(
+                    "wrong result for f({}). Expected {} got {}",
+                    n, expected, result
+                )
+            }
+        }
+    }
+
     fn test_null_object(obj: Object) {
         match obj {
             Object::NULL(_) => {}
@@ -0,0 +1,616 @@
+use crate::{
+    ast::{
+        shorthand, BlockStatement, ElseBranch, ExpressionStatement, IfExpression, MatchExpression,
+        Node, Pattern, Program, Statement, EXPRESSION,
+    },
+    lexer::Lexer,
+    parser::Parser,
+    token::{Comment, Span, Token, TokenType},
+};
+
+const INDENT: &str = "    ";
+
+/// Blocks holding a single expression at most this long stay on one line.
+const INLINE_BLOCK_WIDTH: usize = 40;
+
+/// Formats `source` as canonical Monkey code. Comments are kept, the ones in
+/// the middle of a statement move to the line before it, and a leading `#!`
+/// line is passed through as is. Formatting the output again returns it
+/// unchanged.
+pub fn format_source(source: &str) -> Result<String, Vec<String>> {
+    let mut l = Lexer::new(source.to_string());
+    let mut p = Parser::new(&mut l);
+    let program = p.parse_program();
+
+    let errors = p.errors();
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    let program = match program {
+        Some(program) => program,
+        None => return Err(vec!["cannot parse program".to_string()]),
+    };
+
+    let mut l = Lexer::new(source.to_string());
+    let mut tokens = vec![];
+    loop {
+        let tok = l.next_token();
+        if tok.r#type == TokenType::EOF {
+            break;
+        }
+        tokens.push(tok);
+    }
+
+    let mut f = Formatter {
+        tokens,
+        emitted: vec![false; l.comments().len()],
+        comments: l.comments().to_vec(),
+    };
+
+    let shebang = match source.starts_with("#!") {
+        true => source.lines().next().unwrap_or_default(),
+        false => "",
+    };
+    let formatted = f.program(&program, source.len());
+    match shebang {
+        "" => Ok(formatted),
+        shebang => Ok(format!("{}\n{}", shebang, formatted)),
+    }
+}
+
+struct Formatter {
+    tokens: Vec<Token>,
+    comments: Vec<Comment>,
+    emitted: Vec<bool>,
+}
+
+fn statement_start(stmt: &Statement) -> Span {
+    match stmt {
+        Statement::LETSTATEMENT(s) => s.token.span,
+        Statement::RETURNSTATEMENT(s) => s.token.span,
+        Statement::EXPRESSIONSTATEMENT(s) => s.token.span,
+        Statement::IMPORTSTATEMENT(s) => s.token.span,
+        Statement::EXPORTSTATEMENT(s) => s.token.span,
+    }
+}
+
+fn indentation(indent: usize) -> String {
+    INDENT.repeat(indent)
+}
+
+/// Binding strength of an infix operator, as the parser sees it.
+fn precedence(operator: &str) -> u8 {
+    match operator {
+        ".." | "..=" => 1,
+        "??" => 2,
+        "||" => 3,
+        "&&" => 4,
+        "==" | "!=" => 5,
+        "<" | ">" => 6,
+        "|>" => 7,
+        "+" | "-" => 8,
+        "*" | "/" => 9,
+        ">>" => 10,
+        _ => 0,
+    }
+}
+
+const PREFIX_PRECEDENCE: u8 = 11;
+const PRIMARY_PRECEDENCE: u8 = 12;
+
+fn expression_precedence(exp: &EXPRESSION) -> u8 {
+    match exp {
+        EXPRESSION::INFIX(e) => precedence(&e.operator),
+        EXPRESSION::PREFIX(_) => PREFIX_PRECEDENCE,
+        _ => PRIMARY_PRECEDENCE,
+    }
+}
+
+impl Formatter {
+    fn program(&mut self, program: &Program, end: usize) -> String {
+        self.statements(&program.statements, 0, end, 0, false)
+    }
+
+    /// The span of the last token starting before `offset`.
+    fn last_token_before(&self, offset: usize) -> Span {
+        let index = self.tokens.partition_point(|tok| tok.span.start < offset);
+        match index {
+            0 => Span::default(),
+            _ => self.tokens[index - 1].span,
+        }
+    }
+
+    /// Marks the comments starting in `from..to` as emitted and returns them.
+    fn take_comments(&mut self, from: usize, to: usize) -> Vec<Comment> {
+        let mut taken = vec![];
+        for (i, comment) in self.comments.iter().enumerate() {
+            if !self.emitted[i] && comment.span.start >= from && comment.span.start < to {
+                self.emitted[i] = true;
+                taken.push(comment.clone());
+            }
+        }
+        taken
+    }
+
+    /// The comment on the line where a statement ending at `stmt_end` ends.
+    fn take_trailing_comment(&mut self, stmt_end: Span, to: usize) -> Option<Comment> {
+        let index = self.comments.iter().enumerate().position(|(i, c)| {
+            !self.emitted[i]
+                && c.span.start >= stmt_end.end
+                && c.span.start < to
+                && c.span.line == stmt_end.line
+        })?;
+
+        self.emitted[index] = true;
+        Some(self.comments[index].clone())
+    }
+
+    fn has_comments(&self, from: usize, to: usize) -> bool {
+        self.comments
+            .iter()
+            .enumerate()
+            .any(|(i, c)| !self.emitted[i] && c.span.start >= from && c.span.start < to)
+    }
+
+    /// Formats the statements of a block or program spanning `start..end` in
+    /// the source, one per line at `indent`.
+    fn statements(
+        &mut self,
+        stmts: &[Statement],
+        start: usize,
+        end: usize,
+        indent: usize,
+        in_block: bool,
+    ) -> String {
+        let mut out = Lines {
+            out: String::new(),
+            indent,
+            last_line: None,
+        };
+        let mut cursor = start;
+
+        for (i, stmt) in stmts.iter().enumerate() {
+            let stmt_start = statement_start(stmt);
+            let next = stmts.get(i + 1);
+            let boundary = next.map_or(end, |next| statement_start(next).start);
+            let stmt_end = self.last_token_before(boundary);
+
+            for comment in self.take_comments(cursor, stmt_start.start) {
+                out.push(&comment.text, comment.span.line, comment.span.line);
+            }
+
+            let mut text = self.statement(stmt, indent);
+            if needs_semicolon(stmt, next, in_block) {
+                text.push(';');
+            }
+
+            // Comments inside the statement that no nested block took.
+            for comment in self.take_comments(stmt_start.start, stmt_end.end) {
+                out.push(&comment.text, comment.span.line, comment.span.line);
+            }
+
+            if let Some(comment) = self.take_trailing_comment(stmt_end, boundary) {
+                text = format!("{}  {}", text, comment.text);
+            }
+
+            out.push(&text, stmt_start.line, stmt_end.line);
+            cursor = stmt_end.end;
+        }
+
+        for comment in self.take_comments(cursor, end) {
+            out.push(&comment.text, comment.span.line, comment.span.line);
+        }
+
+        out.out
+    }
+
+    fn statement(&mut self, stmt: &Statement, indent: usize) -> String {
+        match stmt {
+            Statement::LETSTATEMENT(s) => {
+                format!(
+                    "let {} = {}",
+                    self.pattern(&s.pattern, indent),
+                    self.expression(&s.value, indent)
+                )
+            }
+            Statement::RETURNSTATEMENT(s) => {
+                format!("return {}", self.expression(&s.return_value, indent))
+            }
+            Statement::EXPRESSIONSTATEMENT(s) => self.expression(&s.expression, indent),
+            Statement::IMPORTSTATEMENT(s) => {
+                let text = s.string();
+                text.trim_end_matches(';').to_string()
+            }
+            Statement::EXPORTSTATEMENT(s) => format!(
+                "export let {} = {}",
+                self.pattern(&s.stmt.pattern, indent),
+                self.expression(&s.stmt.value, indent)
+            ),
+        }
+    }
+
+    fn if_expression(&mut self, e: &IfExpression, indent: usize) -> String {
+        let text = format!(
+            "if ({}) {}",
+            self.expression(&e.condition, indent),
+            self.block(&e.consequence, indent)
+        );
+        match &e.alternative {
+            Some(ElseBranch::BLOCK(alt)) => format!("{} else {}", text, self.block(alt, indent)),
+            Some(ElseBranch::IF(alt)) => {
+                format!("{} else {}", text, self.if_expression(alt, indent))
+            }
+            None => text,
+        }
+    }
+
+    fn block(&mut self, block: &BlockStatement, indent: usize) -> String {
+        let start = block.token.span.end;
+        let end = block.end.start;
+
+        if !self.has_comments(start, end) {
+            if block.statements.is_empty() {
+                return "{}".to_string();
+            }
+
+            if let [Statement::EXPRESSIONSTATEMENT(s)] = block.statements.as_slice() {
+                let text = self.expression(&s.expression, indent + 1);
+                if !text.contains('\n') && text.len() <= INLINE_BLOCK_WIDTH {
+                    return format!("{{ {} }}", text);
+                }
+            }
+        }
+
+        format!(
+            "{{\n{}{}}}",
+            self.statements(&block.statements, start, end, indent + 1, true),
+            indentation(indent)
+        )
+    }
+
+    fn expression(&mut self, exp: &EXPRESSION, indent: usize) -> String {
+        match exp {
+            EXPRESSION::IDENTIFIER(e) => e.value.clone(),
+            EXPRESSION::INTEGER(e) => e.value.to_string(),
+            EXPRESSION::BOOLEAN(e) => e.value.to_string(),
+            EXPRESSION::NULL(_) => "null".to_string(),
+            EXPRESSION::StringLiteral(e) => format!("\"{}\"", e.value),
+            EXPRESSION::PREFIX(e) => {
+                let right = self.expression(&e.right, indent);
+                // `--a` would read like a decrement.
+                let double_minus = e.operator == "-" && right.starts_with('-');
+                if expression_precedence(&e.right) < PREFIX_PRECEDENCE || double_minus {
+                    format!("{}({})", e.operator, right)
+                } else {
+                    format!("{}{}", e.operator, right)
+                }
+            }
+            EXPRESSION::INFIX(e) => {
+                let op = precedence(&e.operator);
+
+                let mut left = self.expression(&e.left, indent);
+                if expression_precedence(&e.left) < op {
+                    left = format!("({})", left);
+                }
+                let mut right = self.expression(&e.right, indent);
+                if expression_precedence(&e.right) <= op {
+                    right = format!("({})", right);
+                }
+
+                match e.operator.as_str() {
+                    ".." | "..=" => format!("{}{}{}", left, e.operator, right),
+                    _ => format!("{} {} {}", left, e.operator, right),
+                }
+            }
+            EXPRESSION::IF(e) => self.if_expression(e, indent),
+            EXPRESSION::MATCH(e) => self.match_expression(e, indent),
+            EXPRESSION::FN(e) => {
+                let params: Vec<String> = e
+                    .parameters
+                    .iter()
+                    .map(|p| self.pattern(p, indent))
+                    .collect();
+                format!("fn({}) {}", params.join(", "), self.block(&e.body, indent))
+            }
+            EXPRESSION::CALL(e) => {
+                let function = self.operand(&e.function, indent);
+                let args: Vec<String> = e
+                    .args
+                    .iter()
+                    .map(|arg| self.expression(arg, indent))
+                    .collect();
+                format!("{}({})", function, args.join(", "))
+            }
+            EXPRESSION::TEMPLATE(e) => e.source(|exp| self.expression(exp, indent)),
+            EXPRESSION::DOT(e) => format!("{}.{}", self.operand(&e.left, indent), e.name.value),
+            EXPRESSION::METHOD(e) => {
+                let receiver = self.operand(&e.receiver, indent);
+                let args: Vec<String> = e
+                    .args
+                    .iter()
+                    .map(|arg| self.expression(arg, indent))
+                    .collect();
+                format!("{}.{}({})", receiver, e.method.value, args.join(", "))
+            }
+            EXPRESSION::ArrayLiteral(e) => {
+                let items: Vec<String> = e
+                    .items
+                    .iter()
+                    .map(|item| self.expression(item, indent))
+                    .collect();
+                format!("[{}]", items.join(", "))
+            }
+            EXPRESSION::IndexExpression(e) => format!(
+                "{}[{}]",
+                self.operand(&e.left, indent),
+                self.expression(&e.index, indent)
+            ),
+            EXPRESSION::SLICE(e) => {
+                let left = self.operand(&e.left, indent);
+                let [start, end] = [&e.start, &e.end].map(|bound| {
+                    bound
+                        .as_ref()
+                        .map(|b| self.expression(b, indent))
+                        .unwrap_or_default()
+                });
+                format!("{}[{}:{}]", left, start, end)
+            }
+            EXPRESSION::HashLiteral(e) => {
+                let pairs: Vec<String> = e
+                    .pairs
+                    .iter()
+                    .map(|(k, v)| {
+                        format!(
+                            "{}: {}",
+                            self.expression(k, indent),
+                            self.expression(v, indent)
+                        )
+                    })
+                    .collect();
+                format!("{{{}}}", pairs.join(", "))
+            }
+        }
+    }
+
+    /// One arm per line, each ending in a comma.
+    fn match_expression(&mut self, e: &MatchExpression, indent: usize) -> String {
+        let mut text = format!("match ({}) {{", self.expression(&e.value, indent));
+        if e.arms.is_empty() {
+            return text + "}";
+        }
+
+        text.push('\n');
+        for arm in e.arms.iter() {
+            text.push_str(&indentation(indent + 1));
+            text.push_str(&self.pattern(&arm.pattern, indent + 1));
+            if let Some(guard) = &arm.guard {
+                text.push_str(" if ");
+                text.push_str(&self.expression(guard, indent + 1));
+            }
+            text.push_str(" => ");
+            text.push_str(&self.expression(&arm.body, indent + 1));
+            text.push_str(",\n");
+        }
+        text.push_str(&indentation(indent));
+        text.push('}');
+        text
+    }
+
+    fn pattern(&mut self, pattern: &Pattern, indent: usize) -> String {
+        match pattern {
+            Pattern::WILDCARD(_) => "_".to_string(),
+            Pattern::LITERAL(e) => self.expression(e, indent),
+            Pattern::BINDING(ident) => ident.value.clone(),
+            Pattern::ARRAY(p) => {
+                let mut items: Vec<String> = p
+                    .items
+                    .iter()
+                    .map(|item| self.pattern(item, indent))
+                    .collect();
+                if let Some(rest) = &p.rest {
+                    items.push(format!("...{}", self.pattern(rest, indent)));
+                }
+                format!("[{}]", items.join(", "))
+            }
+            Pattern::HASH(p) => {
+                let pairs: Vec<String> = p
+                    .pairs
+                    .iter()
+                    .map(|(k, v)| match shorthand(k, v) {
+                        Some(name) => name.value.clone(),
+                        None => format!(
+                            "{}: {}",
+                            self.expression(k, indent),
+                            self.pattern(v, indent)
+                        ),
+                    })
+                    .collect();
+                format!("{{{}}}", pairs.join(", "))
+            }
+        }
+    }
+
+    /// The left side of a call or index expression.
+    fn operand(&mut self, exp: &EXPRESSION, indent: usize) -> String {
+        let text = self.expression(exp, indent);
+        if expression_precedence(exp) < PRIMARY_PRECEDENCE {
+            format!("({})", text)
+        } else {
+            text
+        }
+    }
+}
+
+/// Expression statements end in `;` unless they give the value of a block,
+/// or are an `if` or `match` that the next statement can't be read as
+/// continuing.
+fn needs_semicolon(stmt: &Statement, next: Option<&Statement>, in_block: bool) -> bool {
+    let braced =
+        |s: &ExpressionStatement| matches!(s.expression, EXPRESSION::IF(_) | EXPRESSION::MATCH(_));
+    match stmt {
+        Statement::EXPRESSIONSTATEMENT(s) => match next {
+            None => !in_block && !braced(s),
+            Some(Statement::EXPRESSIONSTATEMENT(_)) => true,
+            Some(_) => !braced(s),
+        },
+        _ => true,
+    }
+}
+
+/// Output lines at one indentation level, with at most one blank line kept
+/// wherever the source had blank lines.
+struct Lines {
+    out: String,
+    indent: usize,
+    last_line: Option<usize>,
+}
+
+impl Lines {
+    fn push(&mut self, text: &str, first_line: usize, last_line: usize) {
+        if let Some(previous) = self.last_line {
+            if first_line > previous + 1 {
+                self.out.push('\n');
+            }
+        }
+
+        self.out.push_str(&indentation(self.indent));
+        self.out.push_str(text);
+        self.out.push('\n');
+        self.last_line = Some(last_line.max(self.last_line.unwrap_or(0)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format_source;
+    use crate::ast::Node;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn parse(input: &str) -> String {
+        let mut l = Lexer::new(input.to_string());
+        let mut p = Parser::new(&mut l);
+        p.parse_program().unwrap().string()
+    }
+
+    #[test]
+    fn test_format() {
+        struct Test {
+            input: String,
+            expected: String,
+        }
+
+        let tests = [
+            Test {
+                input: "let   x=5;let y = x*(2+3)".to_string(),
+                expected: "let x = 5;\nlet y = x * (2 + 3);\n".to_string(),
+            },
+            Test {
+                input: "#!/usr/bin/env monkey\nputs(args);".to_string(),
+                expected: "#!/usr/bin/env monkey\nputs(args);\n".to_string(),
+            },
+            Test {
+                input: "(a - b) - (c - d); -(-a); (1 + 2)(3); !(a == b) == false".to_string(),
+                expected: "a - b - (c - d);\n-(-a);\n(1 + 2)(3);\n!(a == b) == false;\n"
+                    .to_string(),
+            },
+            Test {
+                input: "let add = fn(a,b){return a+b;};let id=fn(x){x}".to_string(),
+                expected: "let add = fn(a, b) {\n    return a + b;\n};\nlet id = fn(x) { x };\n"
+                    .to_string(),
+            },
+            Test {
+                input: "if (x > 1) { puts(x); x } else { 0 }\nlet y = [1,2][0]; {\"a\":1}[\"a\"]"
+                    .to_string(),
+                expected: "if (x > 1) {\n    puts(x);\n    x\n} else { 0 }\nlet y = [1, 2][0];\n{\"a\": 1}[\"a\"];\n"
+                    .to_string(),
+            },
+            Test {
+                input: "if x > 1 { a } else if x < 0 { b } else { c }".to_string(),
+                expected: "if (x > 1) { a } else if (x < 0) { b } else { c }\n".to_string(),
+            },
+            Test {
+                input: "(a || b) && c ?? null; a || (b && c)".to_string(),
+                expected: "(a || b) && c ?? null;\na || b && c;\n".to_string(),
+            },
+            Test {
+                input: "let f = fn(x) { match x { [a,...r] if a>1=>r, {\"k\":-1}=>null, _=>0 } }; match 1 {}; -1"
+                    .to_string(),
+                expected: "let f = fn(x) {\n    match (x) {\n        [a, ...r] if a > 1 => r,\n        {\"k\": -1} => null,\n        _ => 0,\n    }\n};\nmatch (1) {};\n-1;\n"
+                    .to_string(),
+            },
+            Test {
+                input: "let [a,...rest]=xs;let {name,\"age\":[y]}=p;let f=fn({x},[_,z]){x+z}"
+                    .to_string(),
+                expected: "let [a, ...rest] = xs;\nlet {name, \"age\": [y]} = p;\nlet f = fn({x}, [_, z]) { x + z };\n"
+                    .to_string(),
+            },
+            Test {
+                input: "let s = `Hi ${ name }!\n  ${a+b} \\` ${`x${ y*2 }`}`".to_string(),
+                expected: "let s = `Hi ${name}!\n  ${a + b} \\` ${`x${y * 2}`}`;\n".to_string(),
+            },
+            Test {
+                input: "xs|>filter(even)|>map(f>>g);(a |> f) ?? b;a |> (b ?? f);x |> (f |> g);(xs |> len) == 3"
+                    .to_string(),
+                expected: "xs |> filter(even) |> map(f >> g);\na |> f ?? b;\na |> (b ?? f);\nx |> (f |> g);\nxs |> len == 3;\n"
+                    .to_string(),
+            },
+            Test {
+                input: "xs[ 1 : -1 ];s[:2];(1..n)[i+1:];1 ..= 3 |> sum();(a ?? 0)..b".to_string(),
+                expected: "xs[1:-1];\ns[:2];\n(1..n)[i + 1:];\n1..=3 |> sum();\na ?? 0..b;\n"
+                    .to_string(),
+            },
+            Test {
+                input: "person . name;arr.push(4).map( f );(a + b).len();-x.y".to_string(),
+                expected: "person.name;\narr.push(4).map(f);\n(a + b).len();\n-x.y;\n".to_string(),
+            },
+            Test {
+                input: "if (x) { 1 }; -1".to_string(),
+                expected: "if (x) { 1 };\n-1;\n".to_string(),
+            },
+            Test {
+                input: "// header\n\n\n\nlet x = 1; // one\nlet f = fn() {\n  // inside\n  x\n  // last\n};\nlet y = f(1, // arg\n 2);\n\n// end\n"
+                    .to_string(),
+                expected: "// header\n\nlet x = 1;  // one\nlet f = fn() {\n    // inside\n    x\n    // last\n};\n// arg\nlet y = f(1, 2);\n\n// end\n"
+                    .to_string(),
+            },
+            Test {
+                input: "let f = fn() {}; import { a, b } from \"m.monkey\"\nexport let c = 1"
+                    .to_string(),
+                expected: "let f = fn() {};\nimport { a, b } from \"m.monkey\";\nexport let c = 1;\n"
+                    .to_string(),
+            },
+        ];
+
+        for test in tests {
+            let actual = match format_source(&test.input) {
+                Ok(actual) => actual,
+                Err(errors) => panic!("{:?} failed to format: {:?}", test.input, errors),
+            };
+            if actual != test.expected {
+                panic!(
+                    "{:?}: Expected\n{}\nGot\n{}",
+                    test.input, test.expected, actual
+                );
+            }
+
+            let again = format_source(&actual).unwrap();
+            if again != actual {
+                panic!(
+                    "formatting is not idempotent. First\n{}\nThen\n{}",
+                    actual, again
+                );
+            }
+            if parse(&actual) != parse(&test.input) {
+                panic!("formatting {:?} changed its meaning", test.input);
+            }
+        }
+    }
+
+    #[test]
+    fn test_format_errors() {
+        if format_source("let x 5;").is_ok() {
+            panic!("Expected parse errors");
+        }
+    }
+}
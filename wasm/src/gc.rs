@@ -0,0 +1,327 @@
+use std::{
+    cell::RefCell,
+    collections::HashSet,
+    rc::{Rc, Weak},
+};
+
+use crate::{
+    module,
+    object::{Environment, Object},
+};
+
+/// Number of environments allocated between two automatic collections.
+const COLLECT_THRESHOLD: usize = 1000;
+
+/// What an environment was created for. A collection only sweeps the
+/// environments of the current owner, so that a one-shot evaluation doesn't
+/// free the environments of a session an embedder keeps between evaluations.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Owner {
+    /// Environments created outside of any evaluation.
+    None,
+    /// A self-contained evaluation, like `eval` or `check`.
+    Evaluation(u64),
+    /// The programs evaluated one after the other in a session's global
+    /// environment.
+    Session(*const RefCell<Environment>),
+}
+
+/// Closures keep their defining environment alive and that environment keeps
+/// the closure alive, so reference counting alone never frees them. Every
+/// environment is registered here and a mark & sweep pass over the registry
+/// clears the ones that can no longer be reached from the roots, which breaks
+/// those cycles and lets `Rc` free them.
+struct Heap {
+    envs: Vec<(Owner, Weak<RefCell<Environment>>)>,
+    owner: Owner,
+    evaluations: u64,
+    allocated_since_collect: usize,
+    /// Length of `envs` at which the entries of freed environments are
+    /// dropped, see `track`.
+    prune_at: usize,
+    collections: u64,
+    freed: u64,
+    programs: usize,
+}
+
+impl Default for Heap {
+    fn default() -> Self {
+        Heap {
+            envs: vec![],
+            owner: Owner::None,
+            evaluations: 0,
+            allocated_since_collect: 0,
+            prune_at: COLLECT_THRESHOLD,
+            collections: 0,
+            freed: 0,
+            programs: 0,
+        }
+    }
+}
+
+thread_local! {
+    static HEAP: RefCell<Heap> = RefCell::new(Heap::default());
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GcStats {
+    pub live: usize,
+    pub collections: u64,
+    pub freed: u64,
+}
+
+/// Moves an environment onto the managed heap.
+pub fn track(env: Environment) -> Rc<RefCell<Environment>> {
+    let env = Rc::new(RefCell::new(env));
+
+    HEAP.with(|h| {
+        let mut h = h.borrow_mut();
+        let owner = h.owner;
+        h.envs.push((owner, Rc::downgrade(&env)));
+        h.allocated_since_collect += 1;
+
+        // Collections wait for a safe point, which a long running statement
+        // never reaches. Meanwhile, the weak references of environments freed
+        // by reference counting would keep their memory allocated.
+        if h.envs.len() >= h.prune_at {
+            h.envs.retain(|(_, env)| env.strong_count() > 0);
+            h.prune_at = (h.envs.len() * 2).max(COLLECT_THRESHOLD);
+        }
+    });
+
+    env
+}
+
+pub fn stats() -> GcStats {
+    HEAP.with(|h| {
+        let h = h.borrow();
+        GcStats {
+            live: h
+                .envs
+                .iter()
+                .filter(|(_, env)| env.strong_count() > 0)
+                .count(),
+            collections: h.collections,
+            freed: h.freed,
+        }
+    })
+}
+
+/// A fresh owner for a self-contained evaluation.
+pub fn new_evaluation() -> Owner {
+    HEAP.with(|h| {
+        let mut h = h.borrow_mut();
+        h.evaluations += 1;
+        Owner::Evaluation(h.evaluations)
+    })
+}
+
+/// Runs `f` with the environments it creates belonging to `owner`, then
+/// puts the previous owner back.
+pub fn with_owner<T>(owner: Owner, f: impl FnOnce() -> T) -> T {
+    let previous = HEAP.with(|h| std::mem::replace(&mut h.borrow_mut().owner, owner));
+    let result = f();
+    HEAP.with(|h| h.borrow_mut().owner = previous);
+    result
+}
+
+/// Marks the start of a program evaluation. Collections only run at the safe
+/// points of the outermost program, where every live value is either bound in
+/// its environment or is the result of the last statement.
+pub fn enter_program() {
+    HEAP.with(|h| h.borrow_mut().programs += 1);
+}
+
+pub fn leave_program() {
+    HEAP.with(|h| h.borrow_mut().programs -= 1);
+}
+
+/// Collects if enough environments were allocated since the last collection
+/// and no other program evaluation is in progress.
+pub fn safe_point(env: &Rc<RefCell<Environment>>, result: &Object) {
+    let should_collect = HEAP.with(|h| {
+        let h = h.borrow();
+        h.programs == 1 && h.allocated_since_collect >= COLLECT_THRESHOLD
+    });
+
+    if should_collect {
+        let exports = module::cached_exports();
+        let mut values: Vec<&Object> = exports.iter().collect();
+        values.push(result);
+
+        collect(&[env], &values);
+    }
+}
+
+/// Clears every environment of the current owner that is not reachable from
+/// `envs` or `values`. Returns the number of environments freed.
+pub fn collect(envs: &[&Rc<RefCell<Environment>>], values: &[&Object]) -> u64 {
+    let mut marked = HashSet::new();
+
+    for env in envs {
+        mark_env(env, &mut marked);
+    }
+    for value in values {
+        mark_object(value, &mut marked);
+    }
+
+    let (owner, registered) = HEAP.with(|h| {
+        let mut h = h.borrow_mut();
+        (h.owner, std::mem::take(&mut h.envs))
+    });
+
+    let mut unreachable = vec![];
+    let mut survivors = vec![];
+    for (env_owner, weak) in registered {
+        if let Some(env) = weak.upgrade() {
+            if env_owner != owner || marked.contains(&Rc::as_ptr(&env)) {
+                survivors.push((env_owner, weak));
+            } else {
+                unreachable.push(env);
+            }
+        }
+    }
+
+    let freed = unreachable.len() as u64;
+    for env in unreachable.iter() {
+        env.borrow_mut().clear();
+    }
+    drop(unreachable);
+
+    HEAP.with(|h| {
+        let mut h = h.borrow_mut();
+        survivors.append(&mut h.envs);
+        h.envs = survivors;
+        h.prune_at = (h.envs.len() * 2).max(COLLECT_THRESHOLD);
+        h.allocated_since_collect = 0;
+        h.collections += 1;
+        h.freed += freed;
+    });
+
+    freed
+}
+
+fn mark_env(env: &Rc<RefCell<Environment>>, marked: &mut HashSet<*const RefCell<Environment>>) {
+    if !marked.insert(Rc::as_ptr(env)) {
+        return;
+    }
+
+    let env = env.borrow();
+    for value in env.values() {
+        mark_object(value, marked);
+    }
+    if let Some(outer) = env.outer() {
+        mark_env(outer, marked);
+    }
+}
+
+fn mark_object(obj: &Object, marked: &mut HashSet<*const RefCell<Environment>>) {
+    match obj {
+        Object::FN(f) => mark_env(&f.env, marked),
+        Object::RETURN(r) => mark_object(&r.value, marked),
+        Object::COMPOSED(c) => {
+            mark_object(&c.first, marked);
+            mark_object(&c.second, marked);
+        }
+        Object::ARRAY(arr) => {
+            for el in arr.elements.iter() {
+                mark_object(el, marked);
+            }
+        }
+        Object::HashLitearl(hash) => {
+            for pair in hash.pairs.values() {
+                mark_object(&pair.key, marked);
+                mark_object(&pair.value, marked);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::stats;
+    use crate::ast::Program;
+    use crate::eval::{eval, eval_in, new_environment, Limits};
+    use crate::lexer::Lexer;
+    use crate::object::{Object, ObjectTrait};
+    use crate::parser::Parser;
+
+    fn parse(input: &str) -> Program {
+        let mut l = Lexer::new(input.to_string());
+        let mut p = Parser::new(&mut l);
+        p.parse_program().unwrap()
+    }
+
+    #[test]
+    fn test_collect_recursive_closures() {
+        let input = "let countdown = fn(n) { if (n == 0) { 0 } else { countdown(n - 1) } };
+                     countdown(5);"
+            .to_string();
+
+        let mut l = Lexer::new(input);
+        let mut p = Parser::new(&mut l);
+        let evaluated_val = eval(p.parse_program().unwrap());
+
+        match evaluated_val {
+            Object::INTEGER(int) if int.value == 0 => {}
+            other => panic!("Expected 0. Got: {:?}", other),
+        }
+
+        let stats = stats();
+        if stats.live != 0 {
+            panic!("{} environments still alive after eval", stats.live);
+        }
+        if stats.collections == 0 || stats.freed == 0 {
+            panic!("Expected a collection to free the global environment");
+        }
+    }
+
+    #[test]
+    fn test_gc_stats_builtin() {
+        let input =
+            "let f = fn() { 1 }; let stats = gc_stats(); [stats[\"live\"], stats[\"collections\"]]"
+                .to_string();
+
+        let mut l = Lexer::new(input);
+        let mut p = Parser::new(&mut l);
+        let evaluated_val = eval(p.parse_program().unwrap());
+
+        match evaluated_val {
+            Object::ARRAY(arr) => match (&arr.elements[0], &arr.elements[1]) {
+                (Object::INTEGER(live), Object::INTEGER(collections)) => {
+                    if live.value != 1 || collections.value != 0 {
+                        panic!("Expected [1, 0]. Got: {:?}", arr);
+                    }
+                }
+                other => panic!("Expected integer stats. Got: {:?}", other),
+            },
+            other => panic!("Expected array. Got: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_eval_keeps_session_environments() {
+        let env = new_environment().unwrap();
+        let session = [
+            "let f = fn() { 1 };",
+            "let make = fn(x) { fn() { x } }; let k = make(5);",
+        ];
+        for input in session {
+            let _ = eval_in(parse(input), &env, Limits::default());
+        }
+
+        let one_shot = eval(parse("let g = fn(n) { fn() { n } }; g(2)()"));
+        if one_shot.inspect() != "2" {
+            panic!("Expected 2. Got: {}", one_shot.inspect());
+        }
+
+        let (value, _) = eval_in(parse("[f(), k()]"), &env, Limits::default());
+        if value.inspect() != "[1, 5]" {
+            panic!(
+                "Expected the session bindings to survive. Got: {}",
+                value.inspect()
+            );
+        }
+    }
+}
@@ -0,0 +1,549 @@
+use std::collections::HashMap;
+
+use crate::{
+    ast::{
+        BlockStatement, ElseBranch, ExportStatement, IfExpression, Node, Program, Statement,
+        EXPRESSION,
+    },
+    lexer::Lexer,
+    parser::Parser,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GraphFormat {
+    Dot,
+    Mermaid,
+}
+
+impl GraphFormat {
+    pub fn parse(name: &str) -> Result<GraphFormat, String> {
+        match name {
+            "dot" => Ok(GraphFormat::Dot),
+            "mermaid" => Ok(GraphFormat::Mermaid),
+            other => Err(format!(
+                "unknown graph format {:?}, expected \"dot\" or \"mermaid\"",
+                other
+            )),
+        }
+    }
+}
+
+/// Nodes with labels and directed edges between them, optionally labelled.
+#[derive(Default)]
+struct Graph {
+    nodes: Vec<String>,
+    edges: Vec<(usize, usize, Option<&'static str>)>,
+}
+
+impl Graph {
+    fn node(&mut self, label: String) -> usize {
+        self.nodes.push(label);
+        self.nodes.len() - 1
+    }
+
+    fn edge(&mut self, from: usize, to: usize, label: Option<&'static str>) {
+        if !self.edges.contains(&(from, to, label)) {
+            self.edges.push((from, to, label));
+        }
+    }
+
+    fn render(&self, name: &str, format: GraphFormat) -> String {
+        let mut out = String::new();
+
+        match format {
+            GraphFormat::Dot => {
+                out.push_str(&format!("digraph {} {{\n", name));
+                out.push_str("    node [shape=box];\n");
+                for (id, label) in self.nodes.iter().enumerate() {
+                    out.push_str(&format!(
+                        "    n{} [label=\"{}\"];\n",
+                        id,
+                        label.replace('\\', "\\\\").replace('"', "\\\"")
+                    ));
+                }
+                for (from, to, label) in self.edges.iter() {
+                    match label {
+                        Some(label) => out.push_str(&format!(
+                            "    n{} -> n{} [label=\"{}\"];\n",
+                            from, to, label
+                        )),
+                        None => out.push_str(&format!("    n{} -> n{};\n", from, to)),
+                    }
+                }
+                out.push_str("}\n");
+            }
+            GraphFormat::Mermaid => {
+                out.push_str("graph TD\n");
+                for (id, label) in self.nodes.iter().enumerate() {
+                    out.push_str(&format!(
+                        "    n{}[\"{}\"]\n",
+                        id,
+                        label
+                            .replace('"', "#quot;")
+                            .replace('<', "#lt;")
+                            .replace('>', "#gt;")
+                    ));
+                }
+                for (from, to, label) in self.edges.iter() {
+                    match label {
+                        Some(label) => {
+                            out.push_str(&format!("    n{} -->|{}| n{}\n", from, label, to))
+                        }
+                        None => out.push_str(&format!("    n{} --> n{}\n", from, to)),
+                    }
+                }
+            }
+        }
+
+        out
+    }
+}
+
+fn parse(source: &str) -> Result<Program, String> {
+    let mut l = Lexer::new(source.to_string());
+    let mut p = Parser::new(&mut l);
+    let program = p.parse_program();
+
+    let errors = p.errors();
+    match program {
+        Some(program) if errors.is_empty() => Ok(program),
+        _ => Err(errors.join("\n")),
+    }
+}
+
+/// The syntax tree of `source`, one node per AST node. Infix nodes have a
+/// `left` and a `right` edge, so the nesting shows how operators bind.
+pub fn syntax_tree(source: &str, format: GraphFormat) -> Result<String, String> {
+    let program = parse(source)?;
+
+    let mut graph = Graph::default();
+    let root = graph.node("Program".to_string());
+    for stmt in program.statements.iter() {
+        let child = statement_node(&mut graph, stmt);
+        graph.edge(root, child, None);
+    }
+
+    Ok(graph.render("ast", format))
+}
+
+fn block_node(graph: &mut Graph, label: &str, block: &BlockStatement) -> usize {
+    let id = graph.node(label.to_string());
+    for stmt in block.statements.iter() {
+        let child = statement_node(graph, stmt);
+        graph.edge(id, child, None);
+    }
+    id
+}
+
+fn statement_node(graph: &mut Graph, stmt: &Statement) -> usize {
+    let (label, value) = match stmt {
+        Statement::LETSTATEMENT(s) => (format!("Let {}", s.pattern.string()), Some(&s.value)),
+        Statement::RETURNSTATEMENT(s) => ("Return".to_string(), Some(&s.return_value)),
+        Statement::EXPRESSIONSTATEMENT(s) => ("Expression".to_string(), Some(&s.expression)),
+        Statement::IMPORTSTATEMENT(s) => (format!("Import {:?}", s.path), None),
+        Statement::EXPORTSTATEMENT(s) => (
+            format!("Export {}", s.stmt.pattern.string()),
+            Some(&s.stmt.value),
+        ),
+    };
+
+    let id = graph.node(label);
+    if let Some(value) = value {
+        let child = expression_node(graph, value);
+        graph.edge(id, child, None);
+    }
+    id
+}
+
+fn if_node(graph: &mut Graph, e: &IfExpression) -> usize {
+    let id = graph.node("If".to_string());
+    let condition = expression_node(graph, &e.condition);
+    graph.edge(id, condition, Some("condition"));
+    let consequence = block_node(graph, "Block", &e.consequence);
+    graph.edge(id, consequence, Some("then"));
+    let alternative = match &e.alternative {
+        Some(ElseBranch::BLOCK(alt)) => Some(block_node(graph, "Block", alt)),
+        Some(ElseBranch::IF(alt)) => Some(if_node(graph, alt)),
+        None => None,
+    };
+    if let Some(alternative) = alternative {
+        graph.edge(id, alternative, Some("else"));
+    }
+    id
+}
+
+fn expression_node(graph: &mut Graph, exp: &EXPRESSION) -> usize {
+    match exp {
+        EXPRESSION::IDENTIFIER(e) => graph.node(e.value.clone()),
+        EXPRESSION::INTEGER(e) => graph.node(e.value.to_string()),
+        EXPRESSION::BOOLEAN(e) => graph.node(e.value.to_string()),
+        EXPRESSION::NULL(_) => graph.node("null".to_string()),
+        EXPRESSION::StringLiteral(e) => graph.node(format!("{:?}", e.value)),
+        EXPRESSION::PREFIX(e) => {
+            let id = graph.node(e.operator.clone());
+            let right = expression_node(graph, &e.right);
+            graph.edge(id, right, None);
+            id
+        }
+        EXPRESSION::INFIX(e) => {
+            let id = graph.node(e.operator.clone());
+            let left = expression_node(graph, &e.left);
+            graph.edge(id, left, Some("left"));
+            let right = expression_node(graph, &e.right);
+            graph.edge(id, right, Some("right"));
+            id
+        }
+        EXPRESSION::IF(e) => if_node(graph, e),
+        EXPRESSION::FN(e) => {
+            let params: Vec<String> = e.parameters.iter().map(|p| p.string()).collect();
+            block_node(graph, &format!("fn({})", params.join(", ")), &e.body)
+        }
+        EXPRESSION::CALL(e) => {
+            let id = graph.node("Call".to_string());
+            let function = expression_node(graph, &e.function);
+            graph.edge(id, function, Some("function"));
+            for arg in e.args.iter() {
+                let child = expression_node(graph, arg);
+                graph.edge(id, child, Some("arg"));
+            }
+            id
+        }
+        EXPRESSION::DOT(e) => {
+            let id = graph.node(format!(".{}", e.name.value));
+            let left = expression_node(graph, &e.left);
+            graph.edge(id, left, None);
+            id
+        }
+        EXPRESSION::METHOD(e) => {
+            let id = graph.node(format!("Call .{}", e.method.value));
+            let receiver = expression_node(graph, &e.receiver);
+            graph.edge(id, receiver, Some("receiver"));
+            for arg in e.args.iter() {
+                let child = expression_node(graph, arg);
+                graph.edge(id, child, Some("arg"));
+            }
+            id
+        }
+        EXPRESSION::TEMPLATE(e) => {
+            let id = graph.node("Template".to_string());
+            let first = graph.node(format!("{:?}", e.strings[0]));
+            graph.edge(id, first, None);
+            for (exp, string) in e.expressions.iter().zip(e.strings[1..].iter()) {
+                let child = expression_node(graph, exp);
+                graph.edge(id, child, None);
+                let text = graph.node(format!("{:?}", string));
+                graph.edge(id, text, None);
+            }
+            id
+        }
+        EXPRESSION::ArrayLiteral(e) => {
+            let id = graph.node("Array".to_string());
+            for item in e.items.iter() {
+                let child = expression_node(graph, item);
+                graph.edge(id, child, None);
+            }
+            id
+        }
+        EXPRESSION::IndexExpression(e) => {
+            let id = graph.node("Index".to_string());
+            let left = expression_node(graph, &e.left);
+            graph.edge(id, left, None);
+            let index = expression_node(graph, &e.index);
+            graph.edge(id, index, Some("index"));
+            id
+        }
+        EXPRESSION::SLICE(e) => {
+            let id = graph.node("Slice".to_string());
+            let left = expression_node(graph, &e.left);
+            graph.edge(id, left, None);
+            for (label, bound) in [("start", &e.start), ("end", &e.end)] {
+                if let Some(bound) = bound {
+                    let child = expression_node(graph, bound);
+                    graph.edge(id, child, Some(label));
+                }
+            }
+            id
+        }
+        EXPRESSION::HashLiteral(e) => {
+            let id = graph.node("Hash".to_string());
+            for (k, v) in e.pairs.iter() {
+                let key = expression_node(graph, k);
+                graph.edge(id, key, Some("key"));
+                let value = expression_node(graph, v);
+                graph.edge(key, value, Some("value"));
+            }
+            id
+        }
+        EXPRESSION::MATCH(e) => {
+            let id = graph.node("Match".to_string());
+            let value = expression_node(graph, &e.value);
+            graph.edge(id, value, Some("value"));
+            for arm in e.arms.iter() {
+                let pattern = graph.node(arm.pattern.string());
+                graph.edge(id, pattern, Some("arm"));
+                if let Some(guard) = &arm.guard {
+                    let guard = expression_node(graph, guard);
+                    graph.edge(pattern, guard, Some("if"));
+                }
+                let body = expression_node(graph, &arm.body);
+                graph.edge(pattern, body, Some("then"));
+            }
+            id
+        }
+    }
+}
+
+/// Which `let`-bound functions call which, by name. Calls made outside of
+/// any such function come from a `<program>` node. Shadowed names are not
+/// told apart.
+pub fn call_graph(source: &str, format: GraphFormat) -> Result<String, String> {
+    let program = parse(source)?;
+
+    let mut functions = vec![];
+    collect_functions(&program.statements, &mut functions);
+
+    let mut graph = Graph::default();
+    let mut ids = HashMap::new();
+    for name in functions {
+        ids.entry(name.clone()).or_insert_with(|| graph.node(name));
+    }
+
+    let mut calls = vec![];
+    for stmt in program.statements.iter() {
+        statement_calls(stmt, "<program>", &ids, &mut calls);
+    }
+
+    let mut program_node = None;
+    for (caller, callee) in calls {
+        let from = match ids.get(&caller) {
+            Some(&id) => id,
+            None => *program_node.get_or_insert_with(|| graph.node(caller.clone())),
+        };
+        graph.edge(from, ids[&callee], None);
+    }
+
+    Ok(graph.render("calls", format))
+}
+
+fn collect_functions(stmts: &[Statement], functions: &mut Vec<String>) {
+    for stmt in stmts {
+        let s = match stmt {
+            Statement::LETSTATEMENT(s) => s,
+            Statement::EXPORTSTATEMENT(s) => &s.stmt,
+            _ => continue,
+        };
+        if let (Some(name), EXPRESSION::FN(f)) = (s.name(), &s.value) {
+            functions.push(name.value.clone());
+            collect_functions(&f.body.statements, functions);
+        }
+    }
+}
+
+fn statement_calls(
+    stmt: &Statement,
+    caller: &str,
+    functions: &HashMap<String, usize>,
+    calls: &mut Vec<(String, String)>,
+) {
+    match stmt {
+        Statement::LETSTATEMENT(s)
+        | Statement::EXPORTSTATEMENT(ExportStatement { stmt: s, .. }) => {
+            match (s.name(), &s.value) {
+                (Some(name), EXPRESSION::FN(f)) => {
+                    block_calls(&f.body, &name.value, functions, calls)
+                }
+                (_, value) => expression_calls(value, caller, functions, calls),
+            }
+        }
+        Statement::RETURNSTATEMENT(s) => {
+            expression_calls(&s.return_value, caller, functions, calls)
+        }
+        Statement::EXPRESSIONSTATEMENT(s) => {
+            expression_calls(&s.expression, caller, functions, calls)
+        }
+        Statement::IMPORTSTATEMENT(_) => {}
+    }
+}
+
+fn block_calls(
+    block: &BlockStatement,
+    caller: &str,
+    functions: &HashMap<String, usize>,
+    calls: &mut Vec<(String, String)>,
+) {
+    for stmt in block.statements.iter() {
+        statement_calls(stmt, caller, functions, calls);
+    }
+}
+
+fn if_calls(
+    e: &IfExpression,
+    caller: &str,
+    functions: &HashMap<String, usize>,
+    calls: &mut Vec<(String, String)>,
+) {
+    expression_calls(&e.condition, caller, functions, calls);
+    block_calls(&e.consequence, caller, functions, calls);
+    match &e.alternative {
+        Some(ElseBranch::BLOCK(alt)) => block_calls(alt, caller, functions, calls),
+        Some(ElseBranch::IF(alt)) => if_calls(alt, caller, functions, calls),
+        None => {}
+    }
+}
+
+fn expression_calls(
+    exp: &EXPRESSION,
+    caller: &str,
+    functions: &HashMap<String, usize>,
+    calls: &mut Vec<(String, String)>,
+) {
+    match exp {
+        EXPRESSION::IDENTIFIER(_)
+        | EXPRESSION::INTEGER(_)
+        | EXPRESSION::BOOLEAN(_)
+        | EXPRESSION::NULL(_)
+        | EXPRESSION::StringLiteral(_) => {}
+        EXPRESSION::PREFIX(e) => expression_calls(&e.right, caller, functions, calls),
+        EXPRESSION::INFIX(e) => {
+            expression_calls(&e.left, caller, functions, calls);
+            expression_calls(&e.right, caller, functions, calls);
+        }
+        EXPRESSION::IF(e) => if_calls(e, caller, functions, calls),
+        EXPRESSION::FN(e) => block_calls(&e.body, caller, functions, calls),
+        EXPRESSION::CALL(e) => {
+            if let EXPRESSION::IDENTIFIER(callee) = e.function.as_ref() {
+                if functions.contains_key(&callee.value) {
+                    calls.push((caller.to_string(), callee.value.clone()));
+                }
+            }
+            expression_calls(&e.function, caller, functions, calls);
+            for arg in e.args.iter() {
+                expression_calls(arg, caller, functions, calls);
+            }
+        }
+        EXPRESSION::DOT(e) => expression_calls(&e.left, caller, functions, calls),
+        EXPRESSION::METHOD(e) => {
+            if functions.contains_key(&e.method.value) {
+                calls.push((caller.to_string(), e.method.value.clone()));
+            }
+            expression_calls(&e.receiver, caller, functions, calls);
+            for arg in e.args.iter() {
+                expression_calls(arg, caller, functions, calls);
+            }
+        }
+        EXPRESSION::ArrayLiteral(e) => {
+            for item in e.items.iter() {
+                expression_calls(item, caller, functions, calls);
+            }
+        }
+        EXPRESSION::TEMPLATE(e) => {
+            for exp in e.expressions.iter() {
+                expression_calls(exp, caller, functions, calls);
+            }
+        }
+        EXPRESSION::IndexExpression(e) => {
+            expression_calls(&e.left, caller, functions, calls);
+            expression_calls(&e.index, caller, functions, calls);
+        }
+        EXPRESSION::SLICE(e) => {
+            expression_calls(&e.left, caller, functions, calls);
+            for bound in e.start.iter().chain(e.end.iter()) {
+                expression_calls(bound, caller, functions, calls);
+            }
+        }
+        EXPRESSION::HashLiteral(e) => {
+            for (k, v) in e.pairs.iter() {
+                expression_calls(k, caller, functions, calls);
+                expression_calls(v, caller, functions, calls);
+            }
+        }
+        EXPRESSION::MATCH(e) => {
+            expression_calls(&e.value, caller, functions, calls);
+            for arm in e.arms.iter() {
+                for exp in arm.guard.iter().chain([&arm.body]) {
+                    expression_calls(exp, caller, functions, calls);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{call_graph, syntax_tree, GraphFormat};
+
+    #[test]
+    fn test_syntax_tree() {
+        let dot = syntax_tree("1 + 2 * x;", GraphFormat::Dot).unwrap();
+        let expected = "digraph ast {
+    node [shape=box];
+    n0 [label=\"Program\"];
+    n1 [label=\"Expression\"];
+    n2 [label=\"+\"];
+    n3 [label=\"1\"];
+    n4 [label=\"*\"];
+    n5 [label=\"2\"];
+    n6 [label=\"x\"];
+    n2 -> n3 [label=\"left\"];
+    n4 -> n5 [label=\"left\"];
+    n4 -> n6 [label=\"right\"];
+    n2 -> n4 [label=\"right\"];
+    n1 -> n2;
+    n0 -> n1;
+}
+";
+        if dot != expected {
+            panic!("Expected\n{}\nGot\n{}", expected, dot);
+        }
+
+        let mermaid = syntax_tree("puts(\"hi\")", GraphFormat::Mermaid).unwrap();
+        let expected = "graph TD
+    n0[\"Program\"]
+    n1[\"Expression\"]
+    n2[\"Call\"]
+    n3[\"puts\"]
+    n4[\"#quot;hi#quot;\"]
+    n2 -->|function| n3
+    n2 -->|arg| n4
+    n1 --> n2
+    n0 --> n1
+";
+        if mermaid != expected {
+            panic!("Expected\n{}\nGot\n{}", expected, mermaid);
+        }
+
+        if syntax_tree("let x 1;", GraphFormat::Dot).is_ok() {
+            panic!("Expected parse errors");
+        }
+    }
+
+    #[test]
+    fn test_call_graph() {
+        let input = "let even = fn(n) { if (n == 0) { true } else { odd(n - 1) } };
+                     let odd = fn(n) { if (n == 0) { false } else { even(n - 1) } };
+                     let twice = fn(f, x) { f(f(x)) };
+                     let main = fn() { twice(fn(x) { odd(x) }, 3) };
+                     main();";
+
+        let mermaid = call_graph(input, GraphFormat::Mermaid).unwrap();
+        let expected = "graph TD
+    n0[\"even\"]
+    n1[\"odd\"]
+    n2[\"twice\"]
+    n3[\"main\"]
+    n4[\"#lt;program#gt;\"]
+    n0 --> n1
+    n1 --> n0
+    n3 --> n2
+    n3 --> n1
+    n4 --> n3
+";
+        if mermaid != expected {
+            panic!("Expected\n{}\nGot\n{}", expected, mermaid);
+        }
+
+        if GraphFormat::parse("svg").is_ok() {
+            panic!("Expected unknown formats to be rejected");
+        }
+    }
+}
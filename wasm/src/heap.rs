@@ -0,0 +1,83 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+};
+
+thread_local! {
+    static IN_USE: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Wraps the system allocator to count the bytes each thread holds, so that
+/// an evaluation can be stopped once the values it keeps alive grow too big.
+/// Memory freed by another thread than the one that allocated it is given
+/// back to the freeing thread, which only skews the count of threads that
+/// hand values over.
+struct CountingAllocator;
+
+fn record(grown: usize, shrunk: usize) {
+    // Accessing a const thread local without a destructor never allocates,
+    // but it fails once the thread is being torn down.
+    let _ = IN_USE.try_with(|n| n.set(n.get().saturating_add(grown).saturating_sub(shrunk)));
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            record(layout.size(), 0);
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            record(layout.size(), 0);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        record(0, layout.size());
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = System.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            record(new_size, layout.size());
+        }
+        new_ptr
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Bytes the current thread holds on the heap.
+pub fn in_use() -> usize {
+    IN_USE.with(Cell::get)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::in_use;
+
+    #[test]
+    fn test_in_use() {
+        let before = in_use();
+        let buffer: Vec<u8> = Vec::with_capacity(1 << 20);
+        if in_use() < before + (1 << 20) {
+            panic!("allocating 1 MB went from {} to {} bytes", before, in_use());
+        }
+
+        drop(buffer);
+        if in_use() != before {
+            panic!(
+                "freeing 1 MB went from {} back to {} bytes",
+                before,
+                in_use()
+            );
+        }
+    }
+}
@@ -5,6 +5,13 @@ pub struct Lexer {
     position: usize,
     read_position: usize,
     ch: u8,
+    line: usize,
+    line_start: usize,
+    comments: Vec<Comment>,
+    /// For each `${` still open, how many braces are open inside it.
+    templates: Vec<usize>,
+    /// A string or template ran into the end of the input.
+    unterminated: bool,
 }
 
 impl Lexer {
@@ -14,12 +21,29 @@ impl Lexer {
             position: 0,
             read_position: 0,
             ch: 0,
+            line: 1,
+            line_start: 0,
+            comments: vec![],
+            templates: vec![],
+            unterminated: false,
         };
         l.read_char();
+
+        // A `#!` line lets scripts run as executables, it isn't Monkey code.
+        if l.input.starts_with("#!") {
+            while l.ch != b'\n' && l.ch != 0 {
+                l.read_char();
+            }
+        }
+
         l
     }
 
     pub fn read_char(&mut self) {
+        if self.ch == b'\n' {
+            self.line += 1;
+            self.line_start = self.read_position;
+        }
         if self.read_position >= self.input.len() {
             self.ch = 0
         } else {
@@ -38,9 +62,48 @@ impl Lexer {
     }
 
     fn skip_whitespace(&mut self) {
-        while self.ch == b' ' || self.ch == b'\r' || self.ch == b'\n' || self.ch == b'\t' {
+        loop {
+            while self.ch == b' ' || self.ch == b'\r' || self.ch == b'\n' || self.ch == b'\t' {
+                self.read_char();
+            }
+
+            if self.ch == b'/' && self.peek_char() == b'/' {
+                self.read_comment();
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn read_comment(&mut self) {
+        let start = self.position;
+        let line = self.line;
+        let column = self.position - self.line_start + 1;
+
+        while self.ch != b'\n' && self.ch != 0 {
             self.read_char();
         }
+
+        let end = self.position.min(self.input.len());
+        self.comments.push(Comment {
+            text: self.input[start..end].trim_end().to_string(),
+            span: Span {
+                start,
+                end,
+                line,
+                column,
+            },
+        });
+    }
+
+    /// Whether the input ended inside a string, a template or a `${}`.
+    pub fn unterminated(&self) -> bool {
+        self.unterminated || !self.templates.is_empty()
+    }
+
+    /// The comments skipped so far.
+    pub fn comments(&self) -> &[Comment] {
+        &self.comments
     }
 
     fn peek_char(&mut self) -> u8 {
@@ -53,142 +116,176 @@ impl Lexer {
 
     pub fn next_token(&mut self) -> Token {
         self.skip_whitespace();
+        let start = self.position;
+        let line = self.line;
+        let column = self.position - self.line_start + 1;
         let mut skip = false;
-        let tok = match self.ch {
-            b';' => Token {
-                r#type: TokenType::SEMICOLON,
-                literal: ';'.to_string(),
-            },
-            b'(' => Token {
-                r#type: TokenType::LPAREN,
-                literal: '('.to_string(),
-            },
-            b')' => Token {
-                r#type: TokenType::RPAREN,
-                literal: ')'.to_string(),
-            },
-            b',' => Token {
-                r#type: TokenType::COMMA,
-                literal: ','.to_string(),
-            },
-            b'+' => Token {
-                r#type: TokenType::PLUS,
-                literal: '+'.to_string(),
-            },
-            b'{' => Token {
-                r#type: TokenType::LBRACE,
-                literal: '{'.to_string(),
-            },
-            b'}' => Token {
-                r#type: TokenType::RBRACE,
-                literal: '}'.to_string(),
-            },
-            b'[' => Token {
-                r#type: TokenType::LBRACKET,
-                literal: '['.to_string(),
-            },
-            b']' => Token {
-                r#type: TokenType::RBRACKET,
-                literal: ']'.to_string(),
-            },
-            b'-' => Token {
-                r#type: TokenType::MINUS,
-                literal: '-'.to_string(),
-            },
-            b'/' => Token {
-                r#type: TokenType::SLASH,
-                literal: '/'.to_string(),
-            },
-            b'*' => Token {
-                r#type: TokenType::ASTERISK,
-                literal: '*'.to_string(),
-            },
-            b'<' => Token {
-                r#type: TokenType::LT,
-                literal: '<'.to_string(),
-            },
-            b'>' => Token {
-                r#type: TokenType::GT,
-                literal: '>'.to_string(),
-            },
-            b':' => Token {
-                r#type: TokenType::COLON,
-                literal: ":".to_string(),
-            },
-            0 => Token {
-                r#type: TokenType::EOF,
-                literal: "".to_string(),
-            },
-            b'"' => Token {
-                r#type: TokenType::STRING,
-                literal: self.read_string(),
-            },
+        let (r#type, literal) = match self.ch {
+            b';' => (TokenType::SEMICOLON, ';'.to_string()),
+            b'(' => (TokenType::LPAREN, '('.to_string()),
+            b')' => (TokenType::RPAREN, ')'.to_string()),
+            b',' => (TokenType::COMMA, ','.to_string()),
+            b'+' => (TokenType::PLUS, '+'.to_string()),
+            b'{' => {
+                if let Some(depth) = self.templates.last_mut() {
+                    *depth += 1;
+                }
+                (TokenType::LBRACE, '{'.to_string())
+            }
+            b'}' => match self.templates.last_mut() {
+                Some(0) => {
+                    self.templates.pop();
+                    self.read_template(false)
+                }
+                Some(depth) => {
+                    *depth -= 1;
+                    (TokenType::RBRACE, '}'.to_string())
+                }
+                None => (TokenType::RBRACE, '}'.to_string()),
+            },
+            b'[' => (TokenType::LBRACKET, '['.to_string()),
+            b']' => (TokenType::RBRACKET, ']'.to_string()),
+            b'-' => (TokenType::MINUS, '-'.to_string()),
+            b'/' => (TokenType::SLASH, '/'.to_string()),
+            b'*' => (TokenType::ASTERISK, '*'.to_string()),
+            b'<' => (TokenType::LT, '<'.to_string()),
+            b'>' => {
+                if self.peek_char() == b'>' {
+                    self.read_char();
+                    (TokenType::COMPOSE, ">>".to_string())
+                } else {
+                    (TokenType::GT, '>'.to_string())
+                }
+            }
+            b':' => (TokenType::COLON, ":".to_string()),
+            0 => (TokenType::EOF, "".to_string()),
+            b'"' => (TokenType::STRING, self.read_string()),
+            b'`' => self.read_template(true),
             b'!' => {
                 if self.peek_char() == b'=' {
                     self.read_char();
-                    Token {
-                        r#type: TokenType::NOTEQ,
-                        literal: "!=".to_string(),
-                    }
+                    (TokenType::NOTEQ, "!=".to_string())
                 } else {
-                    Token {
-                        r#type: TokenType::BANG,
-                        literal: '!'.to_string(),
-                    }
+                    (TokenType::BANG, '!'.to_string())
+                }
+            }
+            b'&' | b'|' | b'?' => {
+                let r#type = match self.ch {
+                    b'&' => TokenType::AND,
+                    b'|' => TokenType::OR,
+                    _ => TokenType::COALESCE,
+                };
+                if self.peek_char() == self.ch {
+                    self.read_char();
+                    (r#type, self.input[start..=self.position].to_string())
+                } else if self.ch == b'|' && self.peek_char() == b'>' {
+                    self.read_char();
+                    (TokenType::PIPE, "|>".to_string())
+                } else {
+                    (TokenType::ILLEGAL, (self.ch as char).to_string())
+                }
+            }
+            b'.' => {
+                if self.input[self.position..].starts_with("...") {
+                    self.read_char();
+                    self.read_char();
+                    (TokenType::ELLIPSIS, "...".to_string())
+                } else if self.input[self.position..].starts_with("..=") {
+                    self.read_char();
+                    self.read_char();
+                    (TokenType::DOTDOTEQ, "..=".to_string())
+                } else if self.peek_char() == b'.' {
+                    self.read_char();
+                    (TokenType::DOTDOT, "..".to_string())
+                } else {
+                    (TokenType::DOT, '.'.to_string())
                 }
             }
             b'=' => {
-                if self.peek_char() == b'=' {
+                if self.peek_char() == b'>' {
                     self.read_char();
-                    Token {
-                        r#type: TokenType::EQ,
-                        literal: "==".to_string(),
-                    }
+                    (TokenType::ARROW, "=>".to_string())
+                } else if self.peek_char() == b'=' {
+                    self.read_char();
+                    (TokenType::EQ, "==".to_string())
                 } else {
-                    Token {
-                        r#type: TokenType::ASSIGN,
-                        literal: '='.to_string(),
-                    }
+                    (TokenType::ASSIGN, '='.to_string())
                 }
             }
             ch if b'_' == ch || ch.is_ascii_alphabetic() => {
                 skip = true;
                 let literal = self.read_identifier();
-                Token {
-                    r#type: Token::lookup_ident(&literal),
-                    literal,
-                }
+                (Token::lookup_ident(&literal), literal)
             }
             ch if ch.is_ascii_digit() => {
                 skip = true;
-                Token {
-                    r#type: TokenType::INT,
-                    literal: self.read_number(),
-                }
+                (TokenType::INT, self.read_number())
             }
-            _ => Token {
-                r#type: TokenType::ILLEGAL,
-                literal: self.ch.to_string(),
-            },
+            _ => (TokenType::ILLEGAL, (self.ch as char).to_string()),
         };
         if !skip {
             self.read_char();
         }
-        tok
+        Token {
+            r#type,
+            literal,
+            span: Span {
+                start,
+                end: self.position.min(self.input.len()),
+                line,
+                column,
+            },
+        }
     }
 
     fn read_string(&mut self) -> String {
         let initial_positon = self.position + 1;
         loop {
             self.read_char();
-            if self.ch == b'"' || self.ch == b'0' {
+            if self.ch == b'"' || self.ch == 0 {
                 break;
             }
         }
+        self.unterminated |= self.ch == 0;
 
         self.input[initial_positon..self.position].to_string()
     }
 
+    /// Reads template text from the opening backtick, or from the `}` closing
+    /// an expression, up to the closing backtick or the next `${`. A
+    /// backslash escapes a backtick, a `$` or another backslash.
+    fn read_template(&mut self, head: bool) -> (TokenType, String) {
+        let mut text = vec![];
+        loop {
+            self.read_char();
+            let next = self.peek_char();
+            match self.ch {
+                0 | b'`' => break,
+                b'$' if next == b'{' => {
+                    self.read_char();
+                    self.templates.push(0);
+                    let r#type = match head {
+                        true => TokenType::TEMPLATEHEAD,
+                        false => TokenType::TEMPLATEMIDDLE,
+                    };
+                    return (r#type, String::from_utf8_lossy(&text).into_owned());
+                }
+                b'\\' if matches!(next, b'`' | b'$' | b'\\') => {
+                    self.read_char();
+                    text.push(self.ch);
+                }
+                ch => text.push(ch),
+            }
+        }
+
+        self.unterminated |= self.ch == 0;
+        let r#type = match head {
+            true => TokenType::TEMPLATE,
+            false => TokenType::TEMPLATETAIL,
+        };
+        (r#type, String::from_utf8_lossy(&text).into_owned())
+    }
+
     pub fn read_identifier(&mut self) -> String {
         let initial_position = self.position;
         while b'_' == self.ch || self.ch.is_ascii_alphabetic() {
@@ -593,4 +690,185 @@ mod tests {
             assert_eq!(recieved_token.literal, test_token.expected_literal);
         }
     }
+
+    #[test]
+    fn test_token_spans() {
+        let input = "let x = 10;\n  x == \"ab\"";
+
+        let tests = [
+            (TokenType::LET, 0, 3, 1, 1),
+            (TokenType::IDENT, 4, 5, 1, 5),
+            (TokenType::ASSIGN, 6, 7, 1, 7),
+            (TokenType::INT, 8, 10, 1, 9),
+            (TokenType::SEMICOLON, 10, 11, 1, 11),
+            (TokenType::IDENT, 14, 15, 2, 3),
+            (TokenType::EQ, 16, 18, 2, 5),
+            (TokenType::STRING, 19, 23, 2, 8),
+            (TokenType::EOF, 23, 23, 2, 12),
+        ];
+
+        let mut l = Lexer::new(input.to_string());
+        for (expected_type, start, end, line, column) in tests {
+            let tok = l.next_token();
+            assert_eq!(tok.r#type, expected_type);
+            assert_eq!(
+                tok.span,
+                Span {
+                    start,
+                    end,
+                    line,
+                    column
+                },
+                "wrong span for {:?}",
+                tok
+            );
+        }
+    }
+
+    #[test]
+    fn test_logical_operators() {
+        let input = "a && b || null ?? c & d |> e >> f > g | ? @";
+        let tests = [
+            (TokenType::IDENT, "a"),
+            (TokenType::AND, "&&"),
+            (TokenType::IDENT, "b"),
+            (TokenType::OR, "||"),
+            (TokenType::NULL, "null"),
+            (TokenType::COALESCE, "??"),
+            (TokenType::IDENT, "c"),
+            (TokenType::ILLEGAL, "&"),
+            (TokenType::IDENT, "d"),
+            (TokenType::PIPE, "|>"),
+            (TokenType::IDENT, "e"),
+            (TokenType::COMPOSE, ">>"),
+            (TokenType::IDENT, "f"),
+            (TokenType::GT, ">"),
+            (TokenType::IDENT, "g"),
+            (TokenType::ILLEGAL, "|"),
+            (TokenType::ILLEGAL, "?"),
+            (TokenType::ILLEGAL, "@"),
+            (TokenType::EOF, ""),
+        ];
+
+        let mut l = Lexer::new(input.to_string());
+        for (expected_type, expected_literal) in tests {
+            let tok = l.next_token();
+            assert_eq!(tok.r#type, expected_type);
+            assert_eq!(tok.literal, expected_literal);
+        }
+    }
+
+    #[test]
+    fn test_dots() {
+        let input = "1..10 0..=n a.b [...r] 1...";
+        let tests = [
+            (TokenType::INT, "1"),
+            (TokenType::DOTDOT, ".."),
+            (TokenType::INT, "10"),
+            (TokenType::INT, "0"),
+            (TokenType::DOTDOTEQ, "..="),
+            (TokenType::IDENT, "n"),
+            (TokenType::IDENT, "a"),
+            (TokenType::DOT, "."),
+            (TokenType::IDENT, "b"),
+            (TokenType::LBRACKET, "["),
+            (TokenType::ELLIPSIS, "..."),
+            (TokenType::IDENT, "r"),
+            (TokenType::RBRACKET, "]"),
+            (TokenType::INT, "1"),
+            (TokenType::ELLIPSIS, "..."),
+            (TokenType::EOF, ""),
+        ];
+
+        let mut l = Lexer::new(input.to_string());
+        for (expected_type, expected_literal) in tests {
+            let tok = l.next_token();
+            assert_eq!(tok.r#type, expected_type);
+            assert_eq!(tok.literal, expected_literal);
+        }
+    }
+
+    #[test]
+    fn test_template_strings() {
+        let input = "`plain` `a ${x} b ${ {1: `in${y}`}[1] } c` `\\`\\${\\\\`\n`two\nlines`";
+        let tests = [
+            (TokenType::TEMPLATE, "plain"),
+            (TokenType::TEMPLATEHEAD, "a "),
+            (TokenType::IDENT, "x"),
+            (TokenType::TEMPLATEMIDDLE, " b "),
+            (TokenType::LBRACE, "{"),
+            (TokenType::INT, "1"),
+            (TokenType::COLON, ":"),
+            (TokenType::TEMPLATEHEAD, "in"),
+            (TokenType::IDENT, "y"),
+            (TokenType::TEMPLATETAIL, ""),
+            (TokenType::RBRACE, "}"),
+            (TokenType::LBRACKET, "["),
+            (TokenType::INT, "1"),
+            (TokenType::RBRACKET, "]"),
+            (TokenType::TEMPLATETAIL, " c"),
+            (TokenType::TEMPLATE, "`${\\"),
+            (TokenType::TEMPLATE, "two\nlines"),
+            (TokenType::EOF, ""),
+        ];
+
+        let mut l = Lexer::new(input.to_string());
+        for (expected_type, expected_literal) in tests {
+            let tok = l.next_token();
+            assert_eq!(tok.r#type, expected_type);
+            assert_eq!(tok.literal, expected_literal);
+        }
+        assert_eq!(l.line, 3);
+    }
+
+    #[test]
+    fn test_shebang_line() {
+        let input = "#!/usr/bin/env monkey run\nlet x = 1;";
+        let tests = [
+            (TokenType::LET, "let", 2),
+            (TokenType::IDENT, "x", 2),
+            (TokenType::ASSIGN, "=", 2),
+            (TokenType::INT, "1", 2),
+            (TokenType::SEMICOLON, ";", 2),
+            (TokenType::EOF, "", 2),
+        ];
+
+        let mut l = Lexer::new(input.to_string());
+        for (expected_type, expected_literal, line) in tests {
+            let tok = l.next_token();
+            assert_eq!(tok.r#type, expected_type);
+            assert_eq!(tok.literal, expected_literal);
+            assert_eq!(tok.span.line, line);
+        }
+    }
+
+    #[test]
+    fn test_comments() {
+        let input = "// header\nlet x = 5; // five\n// end";
+        let tests = [
+            (TokenType::LET, "let"),
+            (TokenType::IDENT, "x"),
+            (TokenType::ASSIGN, "="),
+            (TokenType::INT, "5"),
+            (TokenType::SEMICOLON, ";"),
+            (TokenType::EOF, ""),
+        ];
+
+        let mut l = Lexer::new(input.to_string());
+        for (expected_type, expected_literal) in tests {
+            let tok = l.next_token();
+            assert_eq!(tok.r#type, expected_type);
+            assert_eq!(tok.literal, expected_literal);
+        }
+
+        let comments: Vec<(&str, usize, usize)> = l
+            .comments()
+            .iter()
+            .map(|c| (c.text.as_str(), c.span.line, c.span.column))
+            .collect();
+        assert_eq!(
+            comments,
+            vec![("// header", 1, 1), ("// five", 2, 12), ("// end", 3, 1)]
+        );
+    }
 }
@@ -1,20 +1,159 @@
-mod ast;
-mod eval;
-mod lexer;
-mod object;
-mod parser;
-mod repl;
-mod token;
+#![allow(clippy::upper_case_acronyms)]
+
+pub mod analysis;
+pub mod ast;
+pub mod ast_json;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod cli;
+pub mod complete;
+pub mod editor;
+pub mod eval;
+pub mod formatter;
+pub mod gc;
+pub mod graph;
+pub mod heap;
+pub mod lexer;
+pub mod lint;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod lsp;
+pub mod module;
+pub mod object;
+pub mod optimize;
+pub mod parser;
+pub mod prelude;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod repl;
+pub mod resolver;
+pub mod token;
 mod utils;
 
-use eval::eval;
+use std::rc::Rc;
+
+use eval::{eval_with_limits, Limits};
 use lexer::Lexer;
 use object::ObjectTrait;
+use optimize::optimize;
 use parser::Parser;
 use wasm_bindgen::prelude::*;
 
+/// Call depth that fits in the 8 MB stack `.cargo/config.toml` gives the
+/// wasm build, at a few KB of stack per call.
+const MAX_DEPTH: usize = 1000;
+
+fn default_limits() -> Limits {
+    Limits {
+        max_depth: Some(MAX_DEPTH),
+        ..Limits::default()
+    }
+}
+
 #[wasm_bindgen]
 pub fn interpret(input: String) -> String {
+    run(input, default_limits())
+}
+
+/// Like `interpret`, but stops with an "execution limit exceeded" error once
+/// the program runs for more than `max_steps` steps, nests calls deeper than
+/// `max_depth`, runs longer than `max_time_ms`, or `should_interrupt` returns
+/// `true`, and with a "memory limit exceeded" error once the values it holds
+/// take up more than `max_allocated_bytes` of memory at once.
+#[wasm_bindgen]
+pub fn interpret_with_limits(
+    input: String,
+    max_steps: Option<u32>,
+    max_depth: Option<u32>,
+    max_time_ms: Option<f64>,
+    max_allocated_bytes: Option<u32>,
+    should_interrupt: Option<js_sys::Function>,
+) -> String {
+    let limits = Limits {
+        max_steps: max_steps.map(u64::from),
+        max_depth: max_depth.map(|d| d as usize),
+        max_time_ms,
+        clock: max_time_ms.map(|_| Rc::new(js_sys::Date::now) as Rc<dyn Fn() -> f64>),
+        interrupt: should_interrupt.map(|f| {
+            Rc::new(move || f.call0(&JsValue::NULL).is_ok_and(|v| v.is_truthy()))
+                as Rc<dyn Fn() -> bool>
+        }),
+        max_allocated_bytes: max_allocated_bytes.map(|b| b as usize),
+    };
+
+    run(input, limits)
+}
+
+/// Adds or replaces a file in the in-memory file system `import` reads from.
+#[wasm_bindgen]
+pub fn register_module(path: String, source: String) {
+    module::register_module(&path, source)
+}
+
+#[wasm_bindgen]
+pub fn clear_modules() {
+    module::clear_modules()
+}
+
+/// Replaces the Monkey prelude loaded before every program, or disables it
+/// when `source` is undefined. Returns the parse errors of a rejected prelude.
+#[wasm_bindgen]
+pub fn set_prelude(source: Option<String>) -> Option<String> {
+    prelude::set_prelude(source.as_deref()).err()
+}
+
+#[wasm_bindgen]
+pub fn reset_prelude() {
+    prelude::reset_prelude()
+}
+
+/// Formats Monkey source, throwing the parser errors if it doesn't parse.
+#[wasm_bindgen]
+pub fn format_code(input: String) -> Result<String, String> {
+    formatter::format_source(&input).map_err(|errors| errors.join("\n"))
+}
+
+/// The syntax tree of `input` as JSON, see `ast_json` for the schema.
+/// Throws the parser errors if it doesn't parse.
+#[wasm_bindgen]
+pub fn parse_to_json(input: String) -> Result<String, String> {
+    utils::set_panic_hook();
+
+    let mut l = Lexer::new(input);
+    let mut p = Parser::new(&mut l);
+    let program = p.parse_program();
+
+    let errors = p.errors();
+    match program {
+        Some(program) if errors.is_empty() => Ok(ast_json::to_json(&program)),
+        _ => Err(errors.join("\n")),
+    }
+}
+
+/// Evaluates a syntax tree in the JSON form `parse_to_json` returns.
+#[wasm_bindgen]
+pub fn interpret_json(json: String) -> String {
+    utils::set_panic_hook();
+
+    match ast_json::from_json(&json) {
+        Ok(program) => eval_with_limits(optimize(program), default_limits()).inspect(),
+        Err(err) => err,
+    }
+}
+
+/// The syntax tree of `input` as a graph in `format`, "dot" or "mermaid".
+#[wasm_bindgen]
+pub fn ast_graph(input: String, format: String) -> Result<String, String> {
+    graph::syntax_tree(&input, graph::GraphFormat::parse(&format)?)
+}
+
+/// The calls between the `let`-bound functions of `input` as a graph in
+/// `format`, "dot" or "mermaid".
+#[wasm_bindgen]
+pub fn call_graph(input: String, format: String) -> Result<String, String> {
+    graph::call_graph(&input, graph::GraphFormat::parse(&format)?)
+}
+
+fn run(input: String, limits: Limits) -> String {
+    utils::set_panic_hook();
+
     let mut l = Lexer::new(input);
     let mut p = Parser::new(&mut l);
     let program = p.parse_program();
@@ -26,9 +165,44 @@ pub fn interpret(input: String) -> String {
 
     let errors = p.errors();
 
-    if errors.len() > 0 {
+    if !errors.is_empty() {
         return errors.join("\n");
     }
 
-    eval(program).inspect()
+    eval_with_limits(optimize(program), limits).inspect()
+}
+
+/// The tokens and comments of `input` as a JSON array of
+/// `{ "start", "end", "kind" }`, offsets in UTF-16 code units. `kind` is one
+/// of "keyword", "identifier", "builtin", "number", "string", "operator",
+/// "punctuation", "comment" or "invalid".
+#[wasm_bindgen]
+pub fn semantic_tokens(input: String) -> String {
+    serde_json::to_string(&editor::semantic_tokens(&input)).unwrap_or_default()
+}
+
+/// The parse errors of `input`, or its lint warnings, as a JSON array of
+/// `{ "start", "end", "line", "column", "severity", "message", "rule" }`.
+#[wasm_bindgen]
+pub fn diagnostics(input: String) -> String {
+    serde_json::to_string(&editor::diagnostics(&input)).unwrap_or_default()
+}
+
+/// The completions of the word before `offset` as a JSON array of
+/// `{ "label", "kind", "detail" }`.
+#[wasm_bindgen]
+pub fn completions(input: String, offset: u32) -> String {
+    serde_json::to_string(&editor::completions(&input, offset as usize)).unwrap_or_default()
+}
+
+/// A description of the identifier at `offset`.
+#[wasm_bindgen]
+pub fn hover(input: String, offset: u32) -> Option<String> {
+    editor::hover(&input, offset as usize)
+}
+
+/// The offset of the bracket matching the one at, or just before, `offset`.
+#[wasm_bindgen]
+pub fn matching_bracket(input: String, offset: u32) -> Option<u32> {
+    editor::matching_bracket(&input, offset as usize).map(|o| o as u32)
 }
@@ -0,0 +1,643 @@
+//! Reports suspicious but valid code. Every diagnostic carries the ID of the
+//! rule that found it; a file turns rules off with a comment such as
+//! `// monkey-lint: disable unused-let, shadowing`, or all of them with
+//! `// monkey-lint: disable`.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    ast::{
+        BlockStatement, CallExpression, ElseBranch, ExportStatement, Identifier, IfExpression,
+        MatchExpression, Node, Pattern, Statement, EXPRESSION,
+    },
+    lexer::Lexer,
+    object::BUILTINS,
+    parser::Parser,
+    token::{Comment, Span},
+};
+
+/// Every rule ID with what it reports.
+pub const RULES: [(&str, &str); 7] = [
+    (
+        "unused-let",
+        "`let` and `match` bindings that are never used",
+    ),
+    ("unused-param", "function parameters that are never used"),
+    (
+        "shadowing",
+        "bindings that hide a binding of an enclosing function",
+    ),
+    ("unreachable", "statements after a `return`"),
+    (
+        "builtin-arity",
+        "builtin calls with the wrong number of arguments",
+    ),
+    (
+        "self-comparison",
+        "comparisons of an expression with itself",
+    ),
+    ("constant-condition", "`if` conditions that never change"),
+];
+
+const DIRECTIVE: &str = "monkey-lint:";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub rule: &'static str,
+    pub message: String,
+    pub span: Span,
+}
+
+/// Lints `source`, skipping the rules in `disabled` and the ones the file
+/// disables. Returns the parse errors if `source` doesn't parse.
+pub fn lint(source: &str, disabled: &[String]) -> Result<Vec<Diagnostic>, Vec<String>> {
+    let mut l = Lexer::new(source.to_string());
+    let mut p = Parser::new(&mut l);
+    let program = p.parse_program();
+
+    let errors = p.errors();
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    let program = match program {
+        Some(program) => program,
+        None => return Err(vec!["cannot parse program".to_string()]),
+    };
+
+    let mut disabled: HashSet<String> = disabled.iter().cloned().collect();
+    for comment in l.comments() {
+        disabled.extend(disabled_rules(comment));
+    }
+    if disabled.contains("all") {
+        return Ok(vec![]);
+    }
+
+    let mut linter = Linter {
+        frames: vec![],
+        diagnostics: vec![],
+    };
+    linter.lint_frame(&[], &program.statements);
+
+    let mut diagnostics: Vec<Diagnostic> = linter
+        .diagnostics
+        .into_iter()
+        .filter(|d| !disabled.contains(d.rule))
+        .collect();
+    diagnostics.sort_by_key(|d| d.span.start);
+    Ok(diagnostics)
+}
+
+/// The rules a `// monkey-lint: disable ...` comment turns off, `all` if it
+/// doesn't name any.
+fn disabled_rules(comment: &Comment) -> Vec<String> {
+    let rules = comment
+        .text
+        .trim_start_matches('/')
+        .trim()
+        .strip_prefix(DIRECTIVE)
+        .and_then(|rest| rest.trim().strip_prefix("disable"));
+
+    match rules.map(str::trim) {
+        Some("") => vec!["all".to_string()],
+        Some(rules) => rules
+            .split(',')
+            .map(|rule| rule.trim().to_string())
+            .filter(|rule| !rule.is_empty())
+            .collect(),
+        None => vec![],
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum BindingKind {
+    Let,
+    Param,
+    /// Imported and exported bindings, which are never reported.
+    Module,
+}
+
+struct Binding {
+    kind: BindingKind,
+    span: Span,
+    used: bool,
+}
+
+/// Like the resolver, one frame per function or `match` arm: `let` bindings
+/// are visible in the whole function, blocks included.
+struct Linter {
+    frames: Vec<HashMap<String, Binding>>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Linter {
+    fn report(&mut self, rule: &'static str, message: String, span: Span) {
+        self.diagnostics.push(Diagnostic {
+            rule,
+            message,
+            span,
+        });
+    }
+
+    fn lint_frame(&mut self, params: &[Pattern], stmts: &[Statement]) {
+        self.frames.push(HashMap::new());
+
+        for name in params.iter().flat_map(Pattern::bindings) {
+            self.declare(name, BindingKind::Param);
+        }
+        self.declare_statements(stmts);
+        self.lint_statements(stmts);
+        self.pop_frame();
+    }
+
+    fn lint_match(&mut self, e: &MatchExpression) {
+        self.lint_expression(&e.value);
+
+        for arm in e.arms.iter() {
+            self.frames.push(HashMap::new());
+
+            for name in arm.pattern.bindings() {
+                self.declare(name, BindingKind::Let);
+            }
+            for exp in arm.guard.iter().chain([&arm.body]) {
+                self.declare_expression(exp);
+                self.lint_expression(exp);
+            }
+
+            self.pop_frame();
+        }
+    }
+
+    /// Reports the unused bindings of the innermost frame.
+    fn pop_frame(&mut self) {
+        let frame = self.frames.pop().unwrap_or_default();
+        let mut unused: Vec<(String, Binding)> = frame
+            .into_iter()
+            .filter(|(name, binding)| !binding.used && !name.starts_with('_'))
+            .collect();
+        unused.sort_by_key(|(_, binding)| binding.span.start);
+
+        for (name, binding) in unused {
+            match binding.kind {
+                BindingKind::Let => self.report(
+                    "unused-let",
+                    format!("{} is never used", name),
+                    binding.span,
+                ),
+                BindingKind::Param => self.report(
+                    "unused-param",
+                    format!("parameter {} is never used", name),
+                    binding.span,
+                ),
+                BindingKind::Module => {}
+            }
+        }
+    }
+
+    fn declare(&mut self, ident: &Identifier, kind: BindingKind) {
+        let outer = self.frames[..self.frames.len() - 1]
+            .iter()
+            .rev()
+            .find_map(|frame| frame.get(&ident.value));
+        if let Some(outer) = outer {
+            let message = format!(
+                "{} shadows the binding at line {}, column {}",
+                ident.value, outer.span.line, outer.span.column
+            );
+            self.report("shadowing", message, ident.token.span);
+        }
+
+        if let Some(frame) = self.frames.last_mut() {
+            frame.entry(ident.value.clone()).or_insert(Binding {
+                kind,
+                span: ident.token.span,
+                used: false,
+            });
+        }
+    }
+
+    fn declare_statements(&mut self, stmts: &[Statement]) {
+        for stmt in stmts {
+            match stmt {
+                Statement::LETSTATEMENT(s) => {
+                    for name in s.pattern.bindings() {
+                        self.declare(name, BindingKind::Let);
+                    }
+                    self.declare_expression(&s.value);
+                }
+                Statement::EXPORTSTATEMENT(ExportStatement { stmt: s, .. }) => {
+                    for name in s.pattern.bindings() {
+                        self.declare(name, BindingKind::Module);
+                    }
+                    self.declare_expression(&s.value);
+                }
+                Statement::RETURNSTATEMENT(s) => self.declare_expression(&s.return_value),
+                Statement::EXPRESSIONSTATEMENT(s) => self.declare_expression(&s.expression),
+                Statement::IMPORTSTATEMENT(s) => {
+                    for name in s.alias.iter().chain(s.names.iter()) {
+                        self.declare(name, BindingKind::Module);
+                    }
+                }
+            }
+        }
+    }
+
+    fn declare_if(&mut self, e: &IfExpression) {
+        self.declare_expression(&e.condition);
+        self.declare_statements(&e.consequence.statements);
+        match &e.alternative {
+            Some(ElseBranch::BLOCK(alt)) => self.declare_statements(&alt.statements),
+            Some(ElseBranch::IF(alt)) => self.declare_if(alt),
+            None => {}
+        }
+    }
+
+    fn declare_expression(&mut self, exp: &EXPRESSION) {
+        match exp {
+            EXPRESSION::IF(e) => self.declare_if(e),
+            EXPRESSION::MATCH(e) => self.declare_expression(&e.value),
+            EXPRESSION::PREFIX(e) => self.declare_expression(&e.right),
+            EXPRESSION::INFIX(e) => {
+                self.declare_expression(&e.left);
+                self.declare_expression(&e.right);
+            }
+            EXPRESSION::CALL(e) => {
+                self.declare_expression(&e.function);
+                for arg in e.args.iter() {
+                    self.declare_expression(arg);
+                }
+            }
+            EXPRESSION::DOT(e) => self.declare_expression(&e.left),
+            EXPRESSION::METHOD(e) => {
+                self.declare_expression(&e.receiver);
+                for arg in e.args.iter() {
+                    self.declare_expression(arg);
+                }
+            }
+            EXPRESSION::ArrayLiteral(e) => {
+                for item in e.items.iter() {
+                    self.declare_expression(item);
+                }
+            }
+            EXPRESSION::TEMPLATE(e) => {
+                for exp in e.expressions.iter() {
+                    self.declare_expression(exp);
+                }
+            }
+            EXPRESSION::IndexExpression(e) => {
+                self.declare_expression(&e.left);
+                self.declare_expression(&e.index);
+            }
+            EXPRESSION::SLICE(e) => {
+                self.declare_expression(&e.left);
+                for bound in e.start.iter().chain(e.end.iter()) {
+                    self.declare_expression(bound);
+                }
+            }
+            EXPRESSION::HashLiteral(e) => {
+                for (k, v) in e.pairs.iter() {
+                    self.declare_expression(k);
+                    self.declare_expression(v);
+                }
+            }
+            EXPRESSION::IDENTIFIER(_)
+            | EXPRESSION::INTEGER(_)
+            | EXPRESSION::BOOLEAN(_)
+            | EXPRESSION::NULL(_)
+            | EXPRESSION::FN(_)
+            | EXPRESSION::StringLiteral(_) => {}
+        }
+    }
+
+    fn lint_statements(&mut self, stmts: &[Statement]) {
+        let mut returned = false;
+
+        for stmt in stmts {
+            if returned {
+                self.report(
+                    "unreachable",
+                    "unreachable code after return".to_string(),
+                    statement_span(stmt),
+                );
+                returned = false;
+            }
+
+            match stmt {
+                Statement::LETSTATEMENT(s)
+                | Statement::EXPORTSTATEMENT(ExportStatement { stmt: s, .. }) => {
+                    self.lint_expression(&s.value)
+                }
+                Statement::RETURNSTATEMENT(s) => {
+                    self.lint_expression(&s.return_value);
+                    returned = true;
+                }
+                Statement::EXPRESSIONSTATEMENT(s) => self.lint_expression(&s.expression),
+                Statement::IMPORTSTATEMENT(_) => {}
+            }
+        }
+    }
+
+    fn lint_block(&mut self, block: &BlockStatement) {
+        self.lint_statements(&block.statements);
+    }
+
+    fn lint_if(&mut self, e: &IfExpression) {
+        if is_constant(&e.condition) {
+            let message = format!("condition {} is constant", e.condition.string());
+            self.report("constant-condition", message, e.token.span);
+        }
+
+        self.lint_expression(&e.condition);
+        self.lint_block(&e.consequence);
+        match &e.alternative {
+            Some(ElseBranch::BLOCK(alt)) => self.lint_block(alt),
+            Some(ElseBranch::IF(alt)) => self.lint_if(alt),
+            None => {}
+        }
+    }
+
+    fn lint_expression(&mut self, exp: &EXPRESSION) {
+        match exp {
+            EXPRESSION::IDENTIFIER(ident) => {
+                self.lookup(&ident.value);
+            }
+            EXPRESSION::INTEGER(_)
+            | EXPRESSION::BOOLEAN(_)
+            | EXPRESSION::NULL(_)
+            | EXPRESSION::StringLiteral(_) => {}
+            EXPRESSION::PREFIX(e) => self.lint_expression(&e.right),
+            EXPRESSION::INFIX(e) => {
+                if matches!(e.operator.as_str(), "==" | "!=" | "<" | ">")
+                    && is_pure(&e.left)
+                    && e.left.string() == e.right.string()
+                {
+                    let always = e.operator == "==";
+                    let message = format!(
+                        "{} is always {}",
+                        exp.string(),
+                        if always { "true" } else { "false" }
+                    );
+                    self.report("self-comparison", message, e.token.span);
+                }
+
+                self.lint_expression(&e.left);
+                match (e.operator.as_str(), e.right.as_ref()) {
+                    ("|>", EXPRESSION::CALL(call)) => self.lint_call(call, 1),
+                    ("|>", EXPRESSION::IDENTIFIER(ident)) => {
+                        self.check_arity(ident, 1);
+                        self.lint_expression(&e.right);
+                    }
+                    _ => self.lint_expression(&e.right),
+                }
+            }
+            EXPRESSION::IF(e) => self.lint_if(e),
+            EXPRESSION::MATCH(e) => self.lint_match(e),
+            EXPRESSION::FN(e) => self.lint_frame(&e.parameters, &e.body.statements),
+            EXPRESSION::CALL(e) => self.lint_call(e, 0),
+            EXPRESSION::DOT(e) => self.lint_expression(&e.left),
+            EXPRESSION::METHOD(e) => {
+                self.check_arity(&e.method, e.args.len() + 1);
+
+                self.lint_expression(&e.receiver);
+                self.lookup(&e.method.value);
+                for arg in e.args.iter() {
+                    self.lint_expression(arg);
+                }
+            }
+            EXPRESSION::ArrayLiteral(e) => {
+                for item in e.items.iter() {
+                    self.lint_expression(item);
+                }
+            }
+            EXPRESSION::TEMPLATE(e) => {
+                for exp in e.expressions.iter() {
+                    self.lint_expression(exp);
+                }
+            }
+            EXPRESSION::IndexExpression(e) => {
+                self.lint_expression(&e.left);
+                self.lint_expression(&e.index);
+            }
+            EXPRESSION::SLICE(e) => {
+                self.lint_expression(&e.left);
+                for bound in e.start.iter().chain(e.end.iter()) {
+                    self.lint_expression(bound);
+                }
+            }
+            EXPRESSION::HashLiteral(e) => {
+                for (k, v) in e.pairs.iter() {
+                    self.lint_expression(k);
+                    self.lint_expression(v);
+                }
+            }
+        }
+    }
+
+    /// `piped` counts the arguments a `|>` passes on top of `e.args`.
+    fn lint_call(&mut self, e: &CallExpression, piped: usize) {
+        if let EXPRESSION::IDENTIFIER(ident) = e.function.as_ref() {
+            self.check_arity(ident, e.args.len() + piped);
+        }
+
+        self.lint_expression(&e.function);
+        for arg in e.args.iter() {
+            self.lint_expression(arg);
+        }
+    }
+
+    /// Marks the binding `name` refers to as used. Returns whether there is
+    /// one.
+    fn lookup(&mut self, name: &str) -> bool {
+        for frame in self.frames.iter_mut().rev() {
+            if let Some(binding) = frame.get_mut(name) {
+                binding.used = true;
+                return true;
+            }
+        }
+        false
+    }
+
+    fn check_arity(&mut self, ident: &Identifier, got: usize) {
+        let bound = self.frames.iter().any(|f| f.contains_key(&ident.value));
+        let want = match BUILTINS.get(ident.value.as_str()) {
+            Some(builtin) if !bound => builtin.arity,
+            _ => None,
+        };
+
+        if let Some(want) = want.filter(|want| *want != got) {
+            let message = format!(
+                "{} takes {} argument{}, got {}",
+                ident.value,
+                want,
+                if want == 1 { "" } else { "s" },
+                got
+            );
+            self.report("builtin-arity", message, ident.token.span);
+        }
+    }
+}
+
+fn statement_span(stmt: &Statement) -> Span {
+    match stmt {
+        Statement::LETSTATEMENT(s) => s.token.span,
+        Statement::RETURNSTATEMENT(s) => s.token.span,
+        Statement::EXPRESSIONSTATEMENT(s) => s.token.span,
+        Statement::IMPORTSTATEMENT(s) => s.token.span,
+        Statement::EXPORTSTATEMENT(s) => s.token.span,
+    }
+}
+
+/// Whether evaluating `exp` twice gives the same value: it calls nothing.
+fn is_pure(exp: &EXPRESSION) -> bool {
+    match exp {
+        EXPRESSION::CALL(_)
+        | EXPRESSION::METHOD(_)
+        | EXPRESSION::FN(_)
+        | EXPRESSION::IF(_)
+        | EXPRESSION::MATCH(_) => false,
+        EXPRESSION::IDENTIFIER(_)
+        | EXPRESSION::INTEGER(_)
+        | EXPRESSION::BOOLEAN(_)
+        | EXPRESSION::NULL(_)
+        | EXPRESSION::StringLiteral(_) => true,
+        EXPRESSION::PREFIX(e) => is_pure(&e.right),
+        EXPRESSION::INFIX(e) => is_pure(&e.left) && is_pure(&e.right),
+        EXPRESSION::IndexExpression(e) => is_pure(&e.left) && is_pure(&e.index),
+        EXPRESSION::SLICE(e) => {
+            is_pure(&e.left) && e.start.iter().chain(e.end.iter()).all(|b| is_pure(b))
+        }
+        EXPRESSION::DOT(e) => is_pure(&e.left),
+        EXPRESSION::ArrayLiteral(e) => e.items.iter().all(is_pure),
+        EXPRESSION::TEMPLATE(e) => e.expressions.iter().all(is_pure),
+        EXPRESSION::HashLiteral(e) => e.pairs.iter().all(|(k, v)| is_pure(k) && is_pure(v)),
+    }
+}
+
+/// Whether `exp` is made of literals only.
+fn is_constant(exp: &EXPRESSION) -> bool {
+    match exp {
+        EXPRESSION::INTEGER(_)
+        | EXPRESSION::BOOLEAN(_)
+        | EXPRESSION::NULL(_)
+        | EXPRESSION::StringLiteral(_) => true,
+        EXPRESSION::PREFIX(e) => is_constant(&e.right),
+        EXPRESSION::INFIX(e) => is_constant(&e.left) && is_constant(&e.right),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{lint, RULES};
+
+    #[test]
+    fn test_lint() {
+        struct Test {
+            input: &'static str,
+            expected: Vec<(&'static str, usize, usize)>,
+        }
+
+        let tests = vec![
+            Test {
+                input: "let x = 1; let y = x; y;",
+                expected: vec![],
+            },
+            Test {
+                input: "let x = 1;",
+                expected: vec![("unused-let", 1, 5)],
+            },
+            Test {
+                input: "let _x = 1; export let y = 2;",
+                expected: vec![],
+            },
+            Test {
+                input: "let f = fn(a, b) { a }; f(1, 2);",
+                expected: vec![("unused-param", 1, 15)],
+            },
+            Test {
+                input:
+                    "[1].len(2); [1].first(); [1] |> len(); [1] |> first(2); [1] |> push(2) |> len",
+                expected: vec![("builtin-arity", 1, 5), ("builtin-arity", 1, 47)],
+            },
+            Test {
+                input: "let [a, b] = [1, 2]; let f = fn({c, d}) { c }; f(a);",
+                expected: vec![("unused-let", 1, 9), ("unused-param", 1, 37)],
+            },
+            Test {
+                input: "let x = 1; let f = fn(x) { x }; f(x);",
+                expected: vec![("shadowing", 1, 23)],
+            },
+            Test {
+                input: "let f = fn() { let y = g(); y }; let g = fn() { 1 }; f();",
+                expected: vec![],
+            },
+            Test {
+                input: "let f = fn() { return 1; puts(2); }; f();",
+                expected: vec![("unreachable", 1, 26)],
+            },
+            Test {
+                input: "len(1, 2); push([]); puts(1, 2, 3); gc_stats();",
+                expected: vec![("builtin-arity", 1, 1), ("builtin-arity", 1, 12)],
+            },
+            Test {
+                input: "let len = fn(a, b) { a + b }; len(1, 2);",
+                expected: vec![],
+            },
+            Test {
+                input: "let x = 1; x == x; x != 1; f() == f();",
+                expected: vec![("self-comparison", 1, 14)],
+            },
+            Test {
+                input: "if (1 < 2) { 1 }; let x = true; if (x) { 2 };",
+                expected: vec![("constant-condition", 1, 1)],
+            },
+            Test {
+                input: "// monkey-lint: disable unused-let, shadowing
+                        let x = 1; let f = fn(x) { x }; if (true) { 1 };",
+                expected: vec![("constant-condition", 2, 57)],
+            },
+            Test {
+                input: "let x = 1; match x { [a, ...b] if a => 1, {\"k\": x} => x, _ => 0 };",
+                expected: vec![("unused-let", 1, 29), ("shadowing", 1, 49)],
+            },
+            Test {
+                input: "let x = 1; // monkey-lint: disable",
+                expected: vec![],
+            },
+        ];
+
+        for test in tests {
+            let diagnostics = match lint(test.input, &[]) {
+                Ok(diagnostics) => diagnostics,
+                Err(errors) => panic!("{:?} does not parse: {:?}", test.input, errors),
+            };
+            let actual: Vec<(&str, usize, usize)> = diagnostics
+                .iter()
+                .map(|d| (d.rule, d.span.line, d.span.column))
+                .collect();
+
+            if actual != test.expected {
+                panic!(
+                    "lint({:?}) = {:?}, want {:?}",
+                    test.input, diagnostics, test.expected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_lint_disabled_and_errors() {
+        let disabled = vec!["unused-let".to_string()];
+        match lint("let x = 1;", &disabled) {
+            Ok(diagnostics) if diagnostics.is_empty() => {}
+            other => panic!("expected no diagnostics, got {:?}", other),
+        }
+
+        if lint("let x 1;", &[]).is_ok() {
+            panic!("expected parse errors");
+        }
+
+        if RULES.iter().any(|(rule, _)| rule.contains(' ')) {
+            panic!("rule IDs must not contain spaces");
+        }
+    }
+}
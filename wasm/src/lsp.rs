@@ -0,0 +1,630 @@
+//! A Language Server Protocol server speaking JSON-RPC over stdio, used by
+//! the `monkey-lsp` binary. Documents are synced in full and re-parsed on
+//! every request.
+
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Read, Write},
+};
+
+use serde_json::{json, Value};
+
+use crate::{
+    analysis::{analyze, completions, diagnostics, parse, DefinitionKind, Severity},
+    complete::CompletionKind,
+    formatter::format_source,
+    token::Span,
+};
+
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_REQUEST: i64 = -32600;
+
+/// Longest message body read, anything longer is skipped. Documents are sent
+/// in full, so this leaves plenty of room for them.
+const MAX_CONTENT_LENGTH: usize = 64 * 1024 * 1024;
+
+// https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/
+const SEVERITY_ERROR: u8 = 1;
+const SEVERITY_WARNING: u8 = 2;
+const COMPLETION_FUNCTION: u8 = 3;
+const COMPLETION_VARIABLE: u8 = 6;
+const COMPLETION_KEYWORD: u8 = 14;
+const SYMBOL_FUNCTION: u8 = 12;
+const SYMBOL_VARIABLE: u8 = 13;
+
+/// Serves the client on `input` and `output` until it sends `exit`.
+/// Returns the process exit code: 0 if the client asked to shut down
+/// first, 1 otherwise.
+pub fn serve(input: &mut dyn BufRead, output: &mut dyn Write) -> i32 {
+    let mut server = Server::default();
+
+    loop {
+        let message = match read_message(input) {
+            Ok(Some(Ok(message))) => message,
+            Ok(Some(Err((code, msg)))) => {
+                // The id is unknown, the reply can only say what went wrong.
+                let reply = json!({
+                    "jsonrpc": "2.0",
+                    "id": null,
+                    "error": { "code": code, "message": msg },
+                });
+                if let Err(e) = write_message(output, &reply) {
+                    eprintln!("monkey-lsp: {}", e);
+                    return 1;
+                }
+                continue;
+            }
+            Ok(None) => return 1,
+            Err(e) => {
+                eprintln!("monkey-lsp: {}", e);
+                return 1;
+            }
+        };
+
+        if message["method"] == "exit" {
+            return if server.shutdown { 0 } else { 1 };
+        }
+
+        for reply in server.handle(&message) {
+            if let Err(e) = write_message(output, &reply) {
+                eprintln!("monkey-lsp: {}", e);
+                return 1;
+            }
+        }
+    }
+}
+
+/// Reads one `Content-Length` framed message, `None` at the end of input.
+/// A body that is too long or isn't JSON is skipped, and the error to reply
+/// with is returned instead.
+fn read_message(input: &mut dyn BufRead) -> io::Result<Option<Result<Value, (i64, String)>>> {
+    let mut length = None;
+
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let length = length
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length"))?;
+    if length > MAX_CONTENT_LENGTH {
+        io::copy(&mut Read::take(&mut *input, length as u64), &mut io::sink())?;
+        return Ok(Some(Err((
+            INVALID_REQUEST,
+            format!(
+                "message of {} bytes is longer than the {} bytes allowed",
+                length, MAX_CONTENT_LENGTH
+            ),
+        ))));
+    }
+
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+
+    Ok(Some(
+        serde_json::from_slice(&body).map_err(|e| (PARSE_ERROR, format!("parse error: {}", e))),
+    ))
+}
+
+fn write_message(output: &mut dyn Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+/// The LSP position of byte `offset`, in UTF-16 code units.
+fn position(text: &str, offset: usize) -> Value {
+    let mut offset = offset.min(text.len());
+    while !text.is_char_boundary(offset) {
+        offset -= 1;
+    }
+
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    json!({
+        "line": before.matches('\n').count(),
+        "character": before[line_start..].encode_utf16().count(),
+    })
+}
+
+/// The byte offset of the LSP `position`.
+fn offset(text: &str, position: &Value) -> usize {
+    let line = position["line"].as_u64().unwrap_or(0);
+    let character = position["character"].as_u64().unwrap_or(0) as usize;
+
+    let mut start = 0;
+    for _ in 0..line {
+        match text[start..].find('\n') {
+            Some(i) => start += i + 1,
+            None => return text.len(),
+        }
+    }
+
+    let mut units = 0;
+    for (i, c) in text[start..].char_indices() {
+        if units >= character || c == '\n' {
+            return start + i;
+        }
+        units += c.len_utf16();
+    }
+    text.len()
+}
+
+fn range(text: &str, span: Span) -> Value {
+    json!({ "start": position(text, span.start), "end": position(text, span.end) })
+}
+
+#[derive(Default)]
+struct Server {
+    documents: HashMap<String, String>,
+    shutdown: bool,
+}
+
+impl Server {
+    /// The responses and notifications to send for `message`.
+    fn handle(&mut self, message: &Value) -> Vec<Value> {
+        let method = message["method"].as_str().unwrap_or_default();
+        let params = &message["params"];
+
+        match message.get("id") {
+            // A response to a request of ours; we don't send any.
+            Some(_) if method.is_empty() => vec![],
+            Some(id) => {
+                let reply = match self.request(method, params) {
+                    Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                    Err((code, msg)) => json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": { "code": code, "message": msg },
+                    }),
+                };
+                vec![reply]
+            }
+            None => self.notification(method, params),
+        }
+    }
+
+    fn request(&mut self, method: &str, params: &Value) -> Result<Value, (i64, String)> {
+        if self.shutdown {
+            return Err((INVALID_REQUEST, "server is shutting down".to_string()));
+        }
+
+        match method {
+            "initialize" => Ok(json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "definitionProvider": true,
+                    "referencesProvider": true,
+                    "hoverProvider": true,
+                    "documentSymbolProvider": true,
+                    "completionProvider": {},
+                    "documentFormattingProvider": true,
+                },
+                "serverInfo": { "name": "monkey-lsp", "version": env!("CARGO_PKG_VERSION") },
+            })),
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Value::Null)
+            }
+            "textDocument/definition" => Ok(self.definition(params)),
+            "textDocument/references" => Ok(self.references(params)),
+            "textDocument/hover" => Ok(self.hover(params)),
+            "textDocument/documentSymbol" => Ok(self.document_symbols(params)),
+            "textDocument/completion" => Ok(self.completion(params)),
+            "textDocument/formatting" => Ok(self.formatting(params)),
+            _ => Err((METHOD_NOT_FOUND, format!("method not found: {}", method))),
+        }
+    }
+
+    fn notification(&mut self, method: &str, params: &Value) -> Vec<Value> {
+        let uri = params["textDocument"]["uri"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string();
+
+        match method {
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                self.documents.insert(uri.clone(), text.to_string());
+                vec![self.publish_diagnostics(&uri)]
+            }
+            "textDocument/didChange" => {
+                let changes = params["contentChanges"].as_array();
+                if let Some(text) = changes.and_then(|c| c.last()?["text"].as_str()) {
+                    self.documents.insert(uri.clone(), text.to_string());
+                }
+                vec![self.publish_diagnostics(&uri)]
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                vec![self.publish_diagnostics(&uri)]
+            }
+            _ => vec![],
+        }
+    }
+
+    /// The text of the document `params` refer to and the byte offset of
+    /// their position in it.
+    fn document<'a>(&'a self, params: &'a Value) -> Option<(&'a str, &'a str, usize)> {
+        let uri = params["textDocument"]["uri"].as_str()?;
+        let text = self.documents.get(uri)?;
+        Some((uri, text, offset(text, &params["position"])))
+    }
+
+    fn publish_diagnostics(&self, uri: &str) -> Value {
+        let text = self.documents.get(uri).map_or("", String::as_str);
+
+        let diagnostics: Vec<Value> = diagnostics(text)
+            .into_iter()
+            .map(|d| {
+                let severity = match d.severity {
+                    Severity::Error => SEVERITY_ERROR,
+                    Severity::Warning => SEVERITY_WARNING,
+                };
+                json!({
+                    "range": range(text, d.span),
+                    "severity": severity,
+                    "code": d.rule,
+                    "source": "monkey",
+                    "message": d.message,
+                })
+            })
+            .collect();
+
+        json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": { "uri": uri, "diagnostics": diagnostics },
+        })
+    }
+
+    fn definition(&self, params: &Value) -> Value {
+        let (uri, text, offset) = match self.document(params) {
+            Some(document) => document,
+            None => return Value::Null,
+        };
+        let analysis = match parse(text) {
+            Ok(program) => analyze(&program),
+            Err(_) => return Value::Null,
+        };
+
+        match analysis.definition_at(offset) {
+            Some(index) => json!({
+                "uri": uri,
+                "range": range(text, analysis.definitions[index].span),
+            }),
+            None => Value::Null,
+        }
+    }
+
+    fn references(&self, params: &Value) -> Value {
+        let (uri, text, offset) = match self.document(params) {
+            Some(document) => document,
+            None => return Value::Null,
+        };
+        let analysis = match parse(text) {
+            Ok(program) => analyze(&program),
+            Err(_) => return Value::Null,
+        };
+        let index = match analysis.definition_at(offset) {
+            Some(index) => index,
+            None => return json!([]),
+        };
+
+        let mut spans = vec![];
+        if params["context"]["includeDeclaration"] == true {
+            spans.push(analysis.definitions[index].span);
+        }
+        spans.extend(analysis.references_to(index));
+
+        spans
+            .into_iter()
+            .map(|span| json!({ "uri": uri, "range": range(text, span) }))
+            .collect()
+    }
+
+    fn hover(&self, params: &Value) -> Value {
+        let (_, text, offset) = match self.document(params) {
+            Some(document) => document,
+            None => return Value::Null,
+        };
+        let analysis = match parse(text) {
+            Ok(program) => analyze(&program),
+            Err(_) => return Value::Null,
+        };
+
+        match analysis.hover(offset) {
+            Some(detail) => json!({
+                "contents": {
+                    "kind": "markdown",
+                    "value": format!("```monkey\n{}\n```", detail),
+                },
+            }),
+            None => Value::Null,
+        }
+    }
+
+    fn document_symbols(&self, params: &Value) -> Value {
+        let text = match params["textDocument"]["uri"]
+            .as_str()
+            .and_then(|uri| self.documents.get(uri))
+        {
+            Some(text) => text,
+            None => return Value::Null,
+        };
+        let analysis = match parse(text) {
+            Ok(program) => analyze(&program),
+            Err(_) => return Value::Null,
+        };
+
+        analysis
+            .definitions
+            .iter()
+            .filter(|d| d.top_level && d.kind == DefinitionKind::Let)
+            .map(|d| {
+                let kind = match d.params {
+                    Some(_) => SYMBOL_FUNCTION,
+                    None => SYMBOL_VARIABLE,
+                };
+                json!({
+                    "name": d.name,
+                    "detail": d.detail,
+                    "kind": kind,
+                    "range": range(text, d.span),
+                    "selectionRange": range(text, d.span),
+                })
+            })
+            .collect()
+    }
+
+    fn completion(&self, params: &Value) -> Value {
+        let (_, text, offset) = match self.document(params) {
+            Some(document) => document,
+            None => return Value::Null,
+        };
+
+        completions(text, offset)
+            .into_iter()
+            .map(|item| {
+                let kind = match item.kind {
+                    CompletionKind::Binding => COMPLETION_VARIABLE,
+                    CompletionKind::Function | CompletionKind::Builtin => COMPLETION_FUNCTION,
+                    CompletionKind::Keyword => COMPLETION_KEYWORD,
+                };
+                json!({ "label": item.label, "kind": kind, "detail": item.detail })
+            })
+            .collect()
+    }
+
+    fn formatting(&self, params: &Value) -> Value {
+        let text = match params["textDocument"]["uri"]
+            .as_str()
+            .and_then(|uri| self.documents.get(uri))
+        {
+            Some(text) => text,
+            None => return Value::Null,
+        };
+
+        match format_source(text) {
+            Ok(formatted) if formatted == *text => json!([]),
+            Ok(formatted) => json!([{
+                "range": {
+                    "start": { "line": 0, "character": 0 },
+                    "end": position(text, text.len()),
+                },
+                "newText": formatted,
+            }]),
+            Err(_) => Value::Null,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::serve;
+    use serde_json::{json, Value};
+    use std::io::{BufRead, Cursor};
+
+    /// Runs a session of `messages` and returns the exit code and every
+    /// message the server sent.
+    fn run_session(messages: &[Value]) -> (i32, Vec<Value>) {
+        let bodies: Vec<String> = messages.iter().map(Value::to_string).collect();
+        run_raw_session(&bodies)
+    }
+
+    /// Like `run_session`, with message bodies that may not be JSON.
+    fn run_raw_session(bodies: &[String]) -> (i32, Vec<Value>) {
+        let mut input = String::new();
+        for body in bodies {
+            input.push_str(&format!("Content-Length: {}\r\n\r\n{}", body.len(), body));
+        }
+
+        let mut output = vec![];
+        let code = serve(&mut Cursor::new(input.into_bytes()), &mut output);
+
+        let mut replies = vec![];
+        let mut output = Cursor::new(output);
+        while let Ok(Some(Ok(reply))) = super::read_message(&mut output as &mut dyn BufRead) {
+            replies.push(reply);
+        }
+        (code, replies)
+    }
+
+    fn request(id: u64, method: &str, params: Value) -> Value {
+        json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params })
+    }
+
+    fn notification(method: &str, params: Value) -> Value {
+        json!({ "jsonrpc": "2.0", "method": method, "params": params })
+    }
+
+    fn at(line: u64, character: u64) -> Value {
+        json!({
+            "textDocument": { "uri": "file:///a.monkey" },
+            "position": { "line": line, "character": character },
+            "context": { "includeDeclaration": true },
+        })
+    }
+
+    #[test]
+    fn test_session() {
+        let text = "let add = fn(a, b) { a + b };\nlet x  = add(1, 2);\nputs(x);\n";
+        let doc = json!({ "textDocument": { "uri": "file:///a.monkey" } });
+
+        let (code, replies) = run_session(&[
+            request(1, "initialize", json!({ "capabilities": {} })),
+            notification("initialized", json!({})),
+            notification(
+                "textDocument/didOpen",
+                json!({ "textDocument": {
+                    "uri": "file:///a.monkey",
+                    "languageId": "monkey",
+                    "version": 1,
+                    "text": text,
+                } }),
+            ),
+            request(2, "textDocument/definition", at(2, 5)),
+            request(3, "textDocument/references", at(0, 5)),
+            request(4, "textDocument/hover", at(1, 10)),
+            request(5, "textDocument/documentSymbol", doc.clone()),
+            request(6, "textDocument/completion", at(2, 2)),
+            request(7, "textDocument/formatting", doc.clone()),
+            notification(
+                "textDocument/didChange",
+                json!({
+                    "textDocument": { "uri": "file:///a.monkey", "version": 2 },
+                    "contentChanges": [{ "text": "let x = ;" }],
+                }),
+            ),
+            request(8, "textDocument/unknown", json!({})),
+            request(9, "shutdown", Value::Null),
+            notification("exit", Value::Null),
+        ]);
+
+        if code != 0 {
+            panic!("server exited with {}", code);
+        }
+
+        let reply = |id: u64| -> &Value {
+            match replies.iter().find(|r| r["id"] == id) {
+                Some(reply) => reply,
+                None => panic!("no reply to request {}", id),
+            }
+        };
+        let diagnostics: Vec<&Value> = replies
+            .iter()
+            .filter(|r| r["method"] == "textDocument/publishDiagnostics")
+            .collect();
+
+        let tests = [
+            (
+                reply(1)["result"]["capabilities"]["hoverProvider"].clone(),
+                json!(true),
+            ),
+            (diagnostics[0]["params"]["diagnostics"].clone(), json!([])),
+            (
+                reply(2)["result"]["range"].clone(),
+                json!({
+                    "start": { "line": 1, "character": 4 },
+                    "end": { "line": 1, "character": 5 },
+                }),
+            ),
+            (
+                reply(3)["result"]
+                    .as_array()
+                    .map(|refs| refs.iter().map(|r| r["range"]["start"].clone()).collect())
+                    .unwrap_or_default(),
+                json!([
+                    { "line": 0, "character": 4 },
+                    { "line": 1, "character": 9 },
+                ]),
+            ),
+            (
+                reply(4)["result"]["contents"]["value"].clone(),
+                json!("```monkey\nlet add = fn(a, b)\n```"),
+            ),
+            (
+                reply(5)["result"]
+                    .as_array()
+                    .map(|s| s.iter().map(|s| json!([s["name"], s["kind"]])).collect())
+                    .unwrap_or_default(),
+                json!([["add", 12], ["x", 13]]),
+            ),
+            (
+                reply(6)["result"]
+                    .as_array()
+                    .map(|items| items.iter().map(|i| i["label"].clone()).collect())
+                    .unwrap_or_default(),
+                json!(["push", "puts"]),
+            ),
+            (
+                reply(7)["result"][0]["newText"].clone(),
+                json!("let add = fn(a, b) { a + b };\nlet x = add(1, 2);\nputs(x);\n"),
+            ),
+            (
+                diagnostics[1]["params"]["diagnostics"][0]["range"]["start"].clone(),
+                json!({ "line": 0, "character": 8 }),
+            ),
+            (reply(8)["error"]["code"].clone(), json!(-32601)),
+            (reply(9)["result"].clone(), Value::Null),
+        ];
+
+        for (i, (actual, expected)) in tests.iter().enumerate() {
+            if actual != expected {
+                panic!("check {}: expected {}, got {}", i, expected, actual);
+            }
+        }
+    }
+
+    #[test]
+    fn test_malformed_messages() {
+        let (code, replies) = run_raw_session(&[
+            "{\"jsonrpc\": \"2.0\", \"id\": 1,".to_string(),
+            request(2, "shutdown", Value::Null).to_string(),
+            notification("exit", Value::Null).to_string(),
+        ]);
+        if code != 0 || replies.len() != 2 {
+            panic!(
+                "expected two replies and exit code 0, got {} {:?}",
+                code, replies
+            );
+        }
+        if replies[0]["error"]["code"] != -32700 || replies[0]["id"] != Value::Null {
+            panic!("expected a parse error, got {}", replies[0]);
+        }
+        if replies[1]["id"] != 2 {
+            panic!("expected the shutdown reply, got {}", replies[1]);
+        }
+
+        let mut input = "Content-Length: 18446744073709551615\r\n\r\n{}".as_bytes();
+        let message = super::read_message(&mut input).unwrap();
+        match message {
+            Some(Err((-32600, _))) => {}
+            other => panic!("expected the message to be refused, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_exit_without_shutdown() {
+        let (code, replies) = run_session(&[notification("exit", Value::Null)]);
+        if code != 1 || !replies.is_empty() {
+            panic!(
+                "expected exit code 1 and no replies, got {} {:?}",
+                code, replies
+            );
+        }
+    }
+}
@@ -1,11 +1,17 @@
-mod ast;
-mod eval;
-mod lexer;
-mod object;
-mod parser;
-mod repl;
-mod token;
+use std::{env, io, panic, process, thread};
+
+use monkey_interpreter::{cli, eval::STACK_SIZE};
 
 fn main() {
-    repl::start()
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    // The interpreter recurses on the native stack, so give it more than the
+    // main thread has.
+    let code = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(move || cli::run(&args, &mut io::stdout(), &mut io::stderr()))
+        .expect("cannot start the interpreter thread")
+        .join()
+        .unwrap_or_else(|err| panic::resume_unwind(err));
+    process::exit(code)
 }
@@ -0,0 +1,309 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use crate::object::Object;
+
+/// Finds the source of imported modules.
+pub trait ModuleResolver {
+    /// Returns a canonical name and the source for `path` imported from the
+    /// module named `importer`, or from the main program when `None`. Two
+    /// imports of the same module must produce the same name.
+    fn load(&self, path: &str, importer: Option<&str>) -> Result<(String, String), String>;
+}
+
+/// Reads modules from disk, relative to the importing file or to `root` for
+/// imports of the main program.
+pub struct FileResolver {
+    pub root: PathBuf,
+}
+
+impl ModuleResolver for FileResolver {
+    fn load(&self, path: &str, importer: Option<&str>) -> Result<(String, String), String> {
+        let base = match importer {
+            Some(importer) => Path::new(importer)
+                .parent()
+                .map_or(self.root.clone(), Path::to_path_buf),
+            None => self.root.clone(),
+        };
+
+        let full_path = base.join(path);
+        let name = fs::canonicalize(&full_path)
+            .map_err(|err| format!("cannot load module {:?}: {}", path, err))?;
+        let source = fs::read_to_string(&name)
+            .map_err(|err| format!("cannot load module {:?}: {}", path, err))?;
+
+        Ok((name.to_string_lossy().into_owned(), source))
+    }
+}
+
+/// Resolves modules from an in-memory file system filled by the host, which
+/// is how the wasm build gets its modules from JS.
+pub struct VirtualResolver;
+
+thread_local! {
+    static VIRTUAL_FILES: RefCell<HashMap<String, String>> = RefCell::new(HashMap::new());
+}
+
+/// Adds or replaces a file of the virtual file system.
+pub fn register_module(path: &str, source: String) {
+    VIRTUAL_FILES.with(|files| files.borrow_mut().insert(normalize(path), source));
+}
+
+pub fn clear_modules() {
+    VIRTUAL_FILES.with(|files| files.borrow_mut().clear());
+}
+
+impl ModuleResolver for VirtualResolver {
+    fn load(&self, path: &str, importer: Option<&str>) -> Result<(String, String), String> {
+        let full_path = match importer {
+            Some(importer) if !path.starts_with('/') => match importer.rsplit_once('/') {
+                Some((dir, _)) => format!("{}/{}", dir, path),
+                None => path.to_string(),
+            },
+            _ => path.to_string(),
+        };
+        let name = normalize(&full_path);
+
+        VIRTUAL_FILES.with(|files| match files.borrow().get(&name) {
+            Some(source) => Ok((name, source.clone())),
+            None => Err(format!("cannot load module {:?}: no such module", path)),
+        })
+    }
+}
+
+/// Collapses `.` and `..` segments and leading slashes of a virtual path.
+fn normalize(path: &str) -> String {
+    let mut segments: Vec<&str> = vec![];
+
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            other => segments.push(other),
+        }
+    }
+
+    segments.join("/")
+}
+
+struct Modules {
+    resolver: Rc<dyn ModuleResolver>,
+    cache: HashMap<String, Object>,
+    loading: Vec<String>,
+}
+
+fn default_resolver() -> Rc<dyn ModuleResolver> {
+    if cfg!(target_arch = "wasm32") {
+        Rc::new(VirtualResolver)
+    } else {
+        Rc::new(FileResolver {
+            root: PathBuf::from("."),
+        })
+    }
+}
+
+thread_local! {
+    static MODULES: RefCell<Modules> = RefCell::new(Modules {
+        resolver: default_resolver(),
+        cache: HashMap::new(),
+        loading: vec![],
+    });
+}
+
+/// Replaces the resolver used by `import` on this thread.
+pub fn set_resolver(resolver: Rc<dyn ModuleResolver>) {
+    MODULES.with(|m| m.borrow_mut().resolver = resolver);
+}
+
+/// Loads the source of `path`, imported from the module currently being
+/// evaluated.
+pub fn load(path: &str) -> Result<(String, String), String> {
+    let (resolver, importer) = MODULES.with(|m| {
+        let m = m.borrow();
+        (Rc::clone(&m.resolver), m.loading.last().cloned())
+    });
+
+    resolver.load(path, importer.as_deref())
+}
+
+pub fn cached(name: &str) -> Option<Object> {
+    MODULES.with(|m| m.borrow().cache.get(name).cloned())
+}
+
+/// Marks `name` as being evaluated. Fails if it is already being evaluated
+/// further up the import chain.
+pub fn begin(name: &str) -> Result<(), String> {
+    MODULES.with(|m| {
+        let mut m = m.borrow_mut();
+
+        if let Some(start) = m.loading.iter().position(|loading| loading == name) {
+            let mut chain = m.loading[start..].to_vec();
+            chain.push(name.to_string());
+            return Err(format!("import cycle: {}", chain.join(" -> ")));
+        }
+
+        m.loading.push(name.to_string());
+        Ok(())
+    })
+}
+
+/// Ends the evaluation of the innermost module, caching its exports unless it
+/// failed.
+pub fn finish(exports: Option<Object>) {
+    MODULES.with(|m| {
+        let mut m = m.borrow_mut();
+        let name = m.loading.pop().expect("no module being evaluated");
+
+        if let Some(exports) = exports {
+            m.cache.insert(name, exports);
+        }
+    })
+}
+
+/// Exports of every module evaluated so far. They stay alive between imports,
+/// so the garbage collector treats them as roots.
+pub fn cached_exports() -> Vec<Object> {
+    MODULES.with(|m| m.borrow().cache.values().cloned().collect())
+}
+
+/// Forgets every evaluated module, the next import evaluates them again.
+pub fn reset() {
+    MODULES.with(|m| {
+        let mut m = m.borrow_mut();
+        m.cache.clear();
+        m.loading.clear();
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, rc::Rc};
+
+    use super::{register_module, set_resolver, FileResolver, VirtualResolver};
+    use crate::eval::eval;
+    use crate::lexer::Lexer;
+    use crate::object::{Object, ObjectTrait};
+    use crate::parser::Parser;
+
+    fn test_eval(input: &str) -> Object {
+        let mut l = Lexer::new(input.to_string());
+        let mut p = Parser::new(&mut l);
+        let program = p.parse_program().unwrap();
+        if !p.errors().is_empty() {
+            panic!("parser errors: {:?}", p.errors());
+        }
+        eval(program)
+    }
+
+    #[test]
+    fn test_virtual_imports() {
+        set_resolver(Rc::new(VirtualResolver));
+        register_module(
+            "lib/math.monkey",
+            "import { twice } from \"./util.monkey\";
+             export let add = fn(x, y) { x + y };
+             export let double = fn(x) { twice(fn(y) { y + y }, x) / 2 };
+             let hidden = 1;"
+                .to_string(),
+        );
+        register_module(
+            "/lib/util.monkey",
+            "export let twice = fn(f, x) { f(f(x)) };".to_string(),
+        );
+        register_module("a.monkey", "import \"b.monkey\" as b;".to_string());
+        register_module("b.monkey", "import \"a.monkey\" as a;".to_string());
+        register_module("broken.monkey", "export let x = y;".to_string());
+
+        let tests = [
+            (
+                "import \"lib/math.monkey\" as math; math[\"add\"](1, 2)",
+                "3",
+            ),
+            (
+                "import { add, double } from \"lib/math.monkey\"; double(add(1, 2))",
+                "6",
+            ),
+            (
+                "import \"lib/math.monkey\" as math; math[\"hidden\"]",
+                "null",
+            ),
+            (
+                "import { hidden } from \"lib/math.monkey\";",
+                "Error: module \"lib/math.monkey\" has no export named hidden",
+            ),
+            (
+                "import \"a.monkey\" as a;",
+                "Error: in module a.monkey: in module b.monkey: import cycle: a.monkey -> b.monkey -> a.monkey",
+            ),
+            (
+                "import \"missing.monkey\" as m;",
+                "Error: cannot load module \"missing.monkey\": no such module",
+            ),
+            (
+                "import \"broken.monkey\" as m;",
+                "Error: in module broken.monkey: identifier not found: y at line 1, column 16",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let evaluated_val = test_eval(input);
+            if evaluated_val.inspect() != expected {
+                panic!(
+                    "{} evaluated wrong. Expected {}, got {}",
+                    input,
+                    expected,
+                    evaluated_val.inspect()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_modules_evaluated_once() {
+        set_resolver(Rc::new(VirtualResolver));
+        register_module("counter.monkey", "export let f = fn() { 1 };".to_string());
+
+        let evaluated_val = test_eval(
+            "import \"counter.monkey\" as a; import \"./counter.monkey\" as b; [a[\"f\"], b[\"f\"]]",
+        );
+
+        let (a, b) = match evaluated_val {
+            Object::ARRAY(arr) => match (&arr.elements[0], &arr.elements[1]) {
+                (Object::FN(a), Object::FN(b)) => (a.clone(), b.clone()),
+                other => panic!("Expected functions. Got: {:?}", other),
+            },
+            other => panic!("Expected array. Got: {:?}", other),
+        };
+
+        if !Rc::ptr_eq(&a.env, &b.env) {
+            panic!("module was evaluated twice");
+        }
+    }
+
+    #[test]
+    fn test_file_imports() {
+        let dir = std::env::temp_dir().join(format!("monkey-modules-{}", std::process::id()));
+        fs::create_dir_all(dir.join("lib")).unwrap();
+        fs::write(
+            dir.join("lib/greet.monkey"),
+            "import { name } from \"name.monkey\"; export let greeting = \"Hello \" + name;",
+        )
+        .unwrap();
+        fs::write(dir.join("lib/name.monkey"), "export let name = \"monkey\";").unwrap();
+
+        set_resolver(Rc::new(FileResolver { root: dir.clone() }));
+        let evaluated_val = test_eval("import { greeting } from \"lib/greet.monkey\"; greeting");
+        fs::remove_dir_all(&dir).unwrap();
+
+        if evaluated_val.inspect() != "Hello monkey" {
+            panic!("Expected Hello monkey. Got: {}", evaluated_val.inspect());
+        }
+    }
+}
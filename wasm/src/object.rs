@@ -8,7 +8,7 @@ use std::{
 
 use lazy_static::lazy_static;
 
-use crate::ast::{BlockStatement, Identifier, Node};
+use crate::ast::{BlockStatement, Node, Pattern};
 
 #[derive(PartialEq, Debug, Clone, Hash, Eq)]
 pub enum ObjectType {
@@ -22,6 +22,7 @@ pub enum ObjectType {
     BUILTINFUNC,
     ARRAY,
     HASH,
+    RANGE,
 }
 
 pub trait ObjectTrait {
@@ -41,6 +42,8 @@ pub enum Object {
     BUILTINFUNC(BuiltInFunc),
     ARRAY(Array),
     HashLitearl(HashObject),
+    COMPOSED(Composition),
+    RANGE(Range),
 }
 
 impl ObjectTrait for Object {
@@ -56,6 +59,8 @@ impl ObjectTrait for Object {
             Self::BUILTINFUNC(o) => o.r#type(),
             Self::ARRAY(o) => o.r#type(),
             Self::HashLitearl(o) => o.r#type(),
+            Self::COMPOSED(o) => o.r#type(),
+            Self::RANGE(o) => o.r#type(),
         }
     }
 
@@ -71,6 +76,8 @@ impl ObjectTrait for Object {
             Self::BUILTINFUNC(o) => o.inspect(),
             Self::ARRAY(o) => o.inspect(),
             Self::HashLitearl(o) => o.inspect(),
+            Self::COMPOSED(o) => o.inspect(),
+            Self::RANGE(o) => o.inspect(),
         }
     }
 }
@@ -160,6 +167,12 @@ pub struct Array {
     pub elements: Vec<Object>,
 }
 
+impl Array {
+    pub fn new(elements: Vec<Object>) -> Array {
+        Array { elements }
+    }
+}
+
 impl ObjectTrait for Array {
     fn r#type(&self) -> ObjectType {
         ObjectType::ARRAY
@@ -175,9 +188,9 @@ impl ObjectTrait for Array {
             .collect::<Vec<String>>()
             .join(", ");
 
-        str.push_str("[");
+        str.push('[');
         str.push_str(&items);
-        str.push_str("]");
+        str.push(']');
 
         str
     }
@@ -228,8 +241,8 @@ impl ObjectTrait for Error {
 
 #[derive(Debug, Clone)]
 pub struct Function {
-    pub params: Vec<Identifier>,
-    pub body: BlockStatement,
+    pub params: Rc<Vec<Pattern>>,
+    pub body: Rc<BlockStatement>,
     pub env: Rc<RefCell<Environment>>,
 }
 
@@ -257,23 +270,34 @@ impl ObjectTrait for Function {
     }
 }
 
+/// A frame of bindings. Values live in `slots` so that resolved identifiers
+/// can be looked up by index, `names` maps each binding to its slot for
+/// lookups by name.
 #[derive(Debug)]
 pub struct Environment {
-    store: HashMap<String, Object>,
+    names: HashMap<String, usize>,
+    slots: Vec<Option<Object>>,
     outer: Option<Rc<RefCell<Environment>>>,
 }
 
+impl Default for Environment {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Environment {
     pub fn new() -> Environment {
         Environment {
-            store: HashMap::new(),
+            names: HashMap::new(),
+            slots: vec![],
             outer: None,
         }
     }
 
     pub fn get(&self, name: &String) -> Option<Object> {
-        match self.store.get(name) {
-            Some(obj) => Some(obj.clone()),
+        match self.names.get(name) {
+            Some(&index) => self.slots[index].clone(),
             None => match &self.outer {
                 Some(outer_env) => outer_env.borrow().get(name),
                 None => None,
@@ -281,14 +305,68 @@ impl Environment {
         }
     }
 
+    pub fn get_at(&self, depth: usize, index: usize) -> Option<Object> {
+        if depth == 0 {
+            return self.slots.get(index).cloned().flatten();
+        }
+
+        match &self.outer {
+            Some(outer_env) => outer_env.borrow().get_at(depth - 1, index),
+            None => None,
+        }
+    }
+
     pub fn set(&mut self, name: String, obj: Object) {
-        self.store.insert(name, obj.clone());
+        let index = match self.names.get(&name) {
+            Some(&index) => index,
+            None => self.slots.len(),
+        };
+
+        self.define(index, name, obj);
+    }
+
+    pub fn define(&mut self, index: usize, name: String, obj: Object) {
+        self.reserve(index + 1);
+        self.slots[index] = Some(obj);
+        self.names.insert(name, index);
+    }
+
+    /// Grows the frame to at least `size` slots, so that bindings set by name
+    /// never land on a slot the resolver handed out.
+    pub fn reserve(&mut self, size: usize) {
+        if self.slots.len() < size {
+            self.slots.resize(size, None);
+        }
+    }
+
+    pub fn frame_size(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = (&String, usize)> {
+        self.names.iter().map(|(name, &index)| (name, index))
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &Object> {
+        self.slots.iter().flatten()
+    }
+
+    pub fn outer(&self) -> Option<&Rc<RefCell<Environment>>> {
+        self.outer.as_ref()
+    }
+
+    /// Drops every binding and the link to the outer environment.
+    pub fn clear(&mut self) {
+        self.names.clear();
+        self.slots.clear();
+        self.outer = None;
     }
 }
 
 pub fn enclosed_environment(outer_env: &Rc<RefCell<Environment>>) -> Environment {
     Environment {
-        store: HashMap::new(),
+        names: HashMap::new(),
+        slots: vec![],
         outer: Some(Rc::clone(outer_env)),
     }
 }
@@ -296,6 +374,8 @@ pub fn enclosed_environment(outer_env: &Rc<RefCell<Environment>>) -> Environment
 #[derive(Debug, Clone)]
 pub struct BuiltInFunc {
     pub func: fn(arg: Vec<Object>) -> Object,
+    /// Number of arguments the builtin takes, `None` if it takes any number.
+    pub arity: Option<usize>,
 }
 impl ObjectTrait for BuiltInFunc {
     fn r#type(&self) -> ObjectType {
@@ -307,19 +387,119 @@ impl ObjectTrait for BuiltInFunc {
     }
 }
 
+/// `first >> second`: calls `first` with the arguments, then `second` with
+/// its result.
+#[derive(Debug, Clone)]
+pub struct Composition {
+    pub first: Box<Object>,
+    pub second: Box<Object>,
+}
+
+impl ObjectTrait for Composition {
+    fn r#type(&self) -> ObjectType {
+        ObjectType::FUNCTION
+    }
+
+    fn inspect(&self) -> String {
+        format!("{} >> {}", self.first.inspect(), self.second.inspect())
+    }
+}
+
+/// `start..end`, the integers from `start` up to but not including `end`.
+/// `a..=b` is stored as `a..b+1`. Elements are never materialized.
+#[derive(Debug, Clone)]
+pub struct Range {
+    pub start: i64,
+    pub end: i64,
+}
+
+impl Range {
+    pub fn len(&self) -> i64 {
+        // `..` refuses to build a range whose length doesn't fit in an i64.
+        (self.end as i128 - self.start as i128).clamp(0, i64::MAX as i128) as i64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl ObjectTrait for Range {
+    fn r#type(&self) -> ObjectType {
+        ObjectType::RANGE
+    }
+
+    fn inspect(&self) -> String {
+        format!("{}..{}", self.start, self.end)
+    }
+}
+
 lazy_static! {
     pub static ref BUILTINS: HashMap<&'static str, BuiltInFunc> = {
         let mut builtins = HashMap::new();
-        builtins.insert("len", BuiltInFunc { func: monkey_len });
-        builtins.insert("first", BuiltInFunc { func: monkey_first });
-        builtins.insert("last", BuiltInFunc { func: monkey_last });
-        builtins.insert("rest", BuiltInFunc { func: monkey_rest });
-        builtins.insert("push", BuiltInFunc { func: monkey_push });
-        builtins.insert("puts", BuiltInFunc { func: monkey_puts });
+        builtins.insert(
+            "len",
+            BuiltInFunc {
+                func: monkey_len,
+                arity: Some(1),
+            },
+        );
+        builtins.insert(
+            "first",
+            BuiltInFunc {
+                func: monkey_first,
+                arity: Some(1),
+            },
+        );
+        builtins.insert(
+            "last",
+            BuiltInFunc {
+                func: monkey_last,
+                arity: Some(1),
+            },
+        );
+        builtins.insert(
+            "rest",
+            BuiltInFunc {
+                func: monkey_rest,
+                arity: Some(1),
+            },
+        );
+        builtins.insert(
+            "push",
+            BuiltInFunc {
+                func: monkey_push,
+                arity: Some(2),
+            },
+        );
+        builtins.insert(
+            "puts",
+            BuiltInFunc {
+                func: monkey_puts,
+                arity: None,
+            },
+        );
+        builtins.insert(
+            "gc_stats",
+            BuiltInFunc {
+                func: monkey_gc_stats,
+                arity: Some(0),
+            },
+        );
         builtins
     };
 }
 
+fn unsupported(builtin: &str, arg: &Object) -> Object {
+    Object::ERROR(Error {
+        msg: format!(
+            "argument to `{}` not supported, got {:?}",
+            builtin,
+            arg.r#type()
+        ),
+    })
+}
+
 fn monkey_len(args: Vec<Object>) -> Object {
     if args.len() != 1 {
         return Object::ERROR(Error {
@@ -329,18 +509,13 @@ fn monkey_len(args: Vec<Object>) -> Object {
 
     match &args[0] {
         Object::STRING(str) => Object::INTEGER(Integer {
-            value: str.value.len() as i64,
-        }),
-        Object::INTEGER(_) => Object::ERROR(Error {
-            msg: "argument to `len` not supported, got INTEGER".to_string(),
+            value: str.value.chars().count() as i64,
         }),
         Object::ARRAY(arr) => Object::INTEGER(Integer {
             value: arr.elements.len() as i64,
         }),
-        other => panic!(
-            "expected `monkey_len` args[0] to be string. Got: {:?}",
-            other
-        ),
+        Object::RANGE(range) => Object::INTEGER(Integer { value: range.len() }),
+        other => unsupported("len", other),
     }
 }
 
@@ -353,12 +528,16 @@ fn monkey_first(args: Vec<Object>) -> Object {
 
     match &args[0] {
         Object::ARRAY(arr) => {
-            if arr.elements.len() > 0 {
+            if !arr.elements.is_empty() {
                 return arr.elements[0].clone();
             }
             Object::NULL(Null {})
         }
-        other => panic!("expected `first` args[0] to be arr. Got: {:?}", other),
+        Object::RANGE(range) if !range.is_empty() => {
+            Object::INTEGER(Integer { value: range.start })
+        }
+        Object::RANGE(_) => Object::NULL(Null {}),
+        other => unsupported("first", other),
     }
 }
 
@@ -371,12 +550,16 @@ fn monkey_last(args: Vec<Object>) -> Object {
 
     match &args[0] {
         Object::ARRAY(arr) => {
-            if arr.elements.len() > 0 {
+            if !arr.elements.is_empty() {
                 return arr.elements[arr.elements.len() - 1].clone();
             }
             Object::NULL(Null {})
         }
-        other => panic!("expected `first` args[0] to be arr. Got: {:?}", other),
+        Object::RANGE(range) if !range.is_empty() => Object::INTEGER(Integer {
+            value: range.end - 1,
+        }),
+        Object::RANGE(_) => Object::NULL(Null {}),
+        other => unsupported("last", other),
     }
 }
 
@@ -389,14 +572,19 @@ fn monkey_rest(args: Vec<Object>) -> Object {
 
     match &args[0] {
         Object::ARRAY(arr) => {
-            if arr.elements.len() > 0 {
-                return Object::ARRAY(Array {
-                    elements: arr.elements[1..=arr.elements.len() - 1].to_vec(),
-                });
+            if !arr.elements.is_empty() {
+                return Object::ARRAY(Array::new(
+                    arr.elements[1..=arr.elements.len() - 1].to_vec(),
+                ));
             }
             Object::NULL(Null {})
         }
-        other => panic!("expected `first` args[0] to be arr. Got: {:?}", other),
+        Object::RANGE(range) if !range.is_empty() => Object::RANGE(Range {
+            start: range.start + 1,
+            end: range.end,
+        }),
+        Object::RANGE(_) => Object::NULL(Null {}),
+        other => unsupported("rest", other),
     }
 }
 
@@ -409,11 +597,12 @@ fn monkey_push(args: Vec<Object>) -> Object {
 
     match &args[0] {
         Object::ARRAY(arr) => {
-            let mut new_arr = arr.elements.clone();
+            let mut new_arr = Vec::with_capacity(arr.elements.len() + 1);
+            new_arr.extend_from_slice(&arr.elements);
             new_arr.push(args[1].clone());
-            Object::ARRAY(Array { elements: new_arr })
+            Object::ARRAY(Array::new(new_arr))
         }
-        other => panic!("expected `first` args[0] to be arr. Got: {:?}", other),
+        other => unsupported("push", other),
     }
 }
 
@@ -425,11 +614,47 @@ fn monkey_puts(args: Vec<Object>) -> Object {
     Object::NULL(Null {})
 }
 
+fn monkey_gc_stats(args: Vec<Object>) -> Object {
+    if !args.is_empty() {
+        return Object::ERROR(Error {
+            msg: format!("wrong number of arguments. got={}, want={}", args.len(), 0),
+        });
+    }
+
+    let stats = crate::gc::stats();
+    let mut pairs = HashMap::new();
+
+    for (key, value) in [
+        ("live", stats.live as i64),
+        ("collections", stats.collections as i64),
+        ("freed", stats.freed as i64),
+    ] {
+        let key = StringLiteral {
+            value: key.to_string(),
+        };
+        pairs.insert(
+            key.hash_key(),
+            HashPair {
+                key: Object::STRING(key),
+                value: Object::INTEGER(Integer { value }),
+            },
+        );
+    }
+
+    Object::HashLitearl(HashObject::new(pairs))
+}
+
 #[derive(Debug, Clone)]
 pub struct HashObject {
     pub pairs: HashMap<HashKey, HashPair>,
 }
 
+impl HashObject {
+    pub fn new(pairs: HashMap<HashKey, HashPair>) -> HashObject {
+        HashObject { pairs }
+    }
+}
+
 impl ObjectTrait for HashObject {
     fn r#type(&self) -> ObjectType {
         ObjectType::HASH
@@ -439,14 +664,14 @@ impl ObjectTrait for HashObject {
         let mut str = String::new();
         let pairs = self
             .pairs
-            .iter()
-            .map(|(_, v)| format!("{}: {}", v.key.inspect(), v.value.inspect()))
+            .values()
+            .map(|v| format!("{}: {}", v.key.inspect(), v.value.inspect()))
             .collect::<Vec<String>>()
             .join(", ");
 
-        str.push_str("{");
+        str.push('{');
         str.push_str(&pairs);
-        str.push_str("}");
+        str.push('}');
 
         str
     }
@@ -0,0 +1,529 @@
+use crate::{
+    ast::{
+        BlockStatement, BooleanExpression, CallExpression, ElseBranch, ExpressionStatement,
+        FnExpression, IfExpression, IntegerLiteral, Pattern, Program, Statement, StringLiteral,
+        EXPRESSION,
+    },
+    eval::{eval_infix_expression, eval_prefix_expression, left_decides},
+    object::{Boolean, Integer, Null, Object, StringLiteral as StringObject},
+    token::{Token, TokenType},
+};
+
+/// Rewrites `program` into an equivalent, cheaper one: constant prefix and
+/// infix expressions are folded with the evaluator's own operators (so
+/// overflow and division by zero are left for runtime to report), `if`
+/// branches that can never run are dropped, statements after a `return` are
+/// removed and immediately invoked functions with constant arguments are
+/// inlined.
+pub fn optimize(program: Program) -> Program {
+    Program {
+        statements: optimize_statements(program.statements),
+    }
+}
+
+fn optimize_statements(stmts: Vec<Statement>) -> Vec<Statement> {
+    let mut optimized = vec![];
+    let count = stmts.len();
+
+    for (i, stmt) in stmts.into_iter().enumerate() {
+        let is_last = i + 1 == count;
+
+        match optimize_statement(stmt) {
+            Statement::EXPRESSIONSTATEMENT(ExpressionStatement {
+                token,
+                expression: EXPRESSION::IF(e),
+            }) if constant(&e.condition).is_some() => {
+                let taken = branch_is_consequence(&e);
+                let branch = match taken {
+                    true => Some(e.consequence.clone()),
+                    false => e.alternative.clone().map(ElseBranch::into_block),
+                };
+
+                // Blocks don't open a scope, so the taken branch can replace
+                // the `if`. When it's the last statement its value is the
+                // value of the whole list, which only carries over if the
+                // branch ends in an expression.
+                if !is_last || ends_in_value(branch.as_ref()) {
+                    optimized.extend(branch.map_or(vec![], |b| b.statements));
+                } else {
+                    optimized.push(Statement::EXPRESSIONSTATEMENT(ExpressionStatement {
+                        token,
+                        expression: EXPRESSION::IF(e),
+                    }));
+                }
+            }
+            other => optimized.push(other),
+        }
+
+        if let Some(Statement::RETURNSTATEMENT(_)) = optimized.last() {
+            break;
+        }
+    }
+
+    optimized
+}
+
+fn optimize_statement(stmt: Statement) -> Statement {
+    match stmt {
+        Statement::LETSTATEMENT(mut s) => {
+            s.value = optimize_expression(s.value);
+            Statement::LETSTATEMENT(s)
+        }
+        Statement::RETURNSTATEMENT(mut s) => {
+            s.return_value = optimize_expression(s.return_value);
+            Statement::RETURNSTATEMENT(s)
+        }
+        Statement::EXPRESSIONSTATEMENT(mut s) => {
+            s.expression = optimize_expression(s.expression);
+            Statement::EXPRESSIONSTATEMENT(s)
+        }
+        Statement::EXPORTSTATEMENT(mut s) => {
+            s.stmt.value = optimize_expression(s.stmt.value);
+            Statement::EXPORTSTATEMENT(s)
+        }
+        Statement::IMPORTSTATEMENT(s) => Statement::IMPORTSTATEMENT(s),
+    }
+}
+
+fn optimize_block(block: BlockStatement) -> BlockStatement {
+    BlockStatement {
+        token: block.token,
+        statements: optimize_statements(block.statements),
+        end: block.end,
+    }
+}
+
+fn optimize_expression(exp: EXPRESSION) -> EXPRESSION {
+    match exp {
+        EXPRESSION::PREFIX(mut e) => {
+            e.right = Box::new(optimize_expression(*e.right));
+
+            match constant(&e.right) {
+                Some(right) => fold(eval_prefix_expression(&e.operator, right), &e.token)
+                    .unwrap_or(EXPRESSION::PREFIX(e)),
+                None => EXPRESSION::PREFIX(e),
+            }
+        }
+        EXPRESSION::INFIX(mut e) => {
+            e.left = Box::new(optimize_expression(*e.left));
+            e.right = match *e.right {
+                // The call gets the piped value as well, so it can't be inlined.
+                EXPRESSION::CALL(mut call) if e.operator == "|>" => {
+                    call.function = Box::new(optimize_expression(*call.function));
+                    call.args = call.args.into_iter().map(optimize_expression).collect();
+                    Box::new(EXPRESSION::CALL(call))
+                }
+                right => Box::new(optimize_expression(right)),
+            };
+
+            if let Some(left) = constant(&e.left) {
+                match left_decides(&e.operator, &left) {
+                    Some(true) => return *e.left,
+                    Some(false) => return *e.right,
+                    None => {}
+                }
+            }
+
+            match (constant(&e.left), constant(&e.right)) {
+                (Some(left), Some(right)) => {
+                    fold(eval_infix_expression(&e.operator, left, right), &e.token)
+                        .unwrap_or(EXPRESSION::INFIX(e))
+                }
+                _ => EXPRESSION::INFIX(e),
+            }
+        }
+        EXPRESSION::IF(e) => optimize_if_expression(e),
+        EXPRESSION::MATCH(mut e) => {
+            e.value = Box::new(optimize_expression(*e.value));
+            e.arms = e
+                .arms
+                .into_iter()
+                .map(|mut arm| {
+                    arm.guard = arm.guard.map(optimize_expression);
+                    arm.body = optimize_expression(arm.body);
+                    arm
+                })
+                .collect();
+            EXPRESSION::MATCH(e)
+        }
+        EXPRESSION::FN(mut e) => {
+            e.body = optimize_block(e.body);
+            EXPRESSION::FN(e)
+        }
+        EXPRESSION::CALL(e) => optimize_call_expression(e),
+        EXPRESSION::ArrayLiteral(mut e) => {
+            e.items = e.items.into_iter().map(optimize_expression).collect();
+            EXPRESSION::ArrayLiteral(e)
+        }
+        EXPRESSION::TEMPLATE(mut e) => {
+            e.expressions = e.expressions.into_iter().map(optimize_expression).collect();
+            EXPRESSION::TEMPLATE(e)
+        }
+        EXPRESSION::DOT(mut e) => {
+            e.left = Box::new(optimize_expression(*e.left));
+            EXPRESSION::DOT(e)
+        }
+        EXPRESSION::METHOD(mut e) => {
+            e.receiver = Box::new(optimize_expression(*e.receiver));
+            e.args = e.args.into_iter().map(optimize_expression).collect();
+            EXPRESSION::METHOD(e)
+        }
+        EXPRESSION::IndexExpression(mut e) => {
+            e.left = Box::new(optimize_expression(*e.left));
+            e.index = Box::new(optimize_expression(*e.index));
+            EXPRESSION::IndexExpression(e)
+        }
+        EXPRESSION::SLICE(mut e) => {
+            e.left = Box::new(optimize_expression(*e.left));
+            e.start = e.start.map(|b| Box::new(optimize_expression(*b)));
+            e.end = e.end.map(|b| Box::new(optimize_expression(*b)));
+            EXPRESSION::SLICE(e)
+        }
+        EXPRESSION::HashLiteral(mut e) => {
+            e.pairs = e
+                .pairs
+                .into_iter()
+                .map(|(k, v)| (optimize_expression(k), optimize_expression(v)))
+                .collect();
+            EXPRESSION::HashLiteral(e)
+        }
+        other => other,
+    }
+}
+
+fn optimize_if_expression(mut e: IfExpression) -> EXPRESSION {
+    e.condition = Box::new(optimize_expression(*e.condition));
+    e.consequence = optimize_block(e.consequence);
+    e.alternative = e.alternative.map(optimize_else);
+
+    let taken = match constant(&e.condition) {
+        Some(condition) => is_truthy(&condition),
+        None => return EXPRESSION::IF(e),
+    };
+    let alternative = e.alternative.take().map(ElseBranch::into_block);
+
+    // A branch made of a single expression can stand in for the whole `if`.
+    let branch = match taken {
+        true => Some(&e.consequence),
+        false => alternative.as_ref(),
+    };
+    if let Some(branch) = branch {
+        if let [Statement::EXPRESSIONSTATEMENT(s)] = branch.statements.as_slice() {
+            return s.expression.clone();
+        }
+    }
+
+    // Otherwise keep an `if (true)` around the live branch.
+    match (taken, alternative) {
+        (true, _) => EXPRESSION::IF(IfExpression {
+            condition: Box::new(boolean_literal(true, &e.token)),
+            alternative: None,
+            ..e
+        }),
+        (false, Some(alt)) => EXPRESSION::IF(IfExpression {
+            condition: Box::new(boolean_literal(true, &e.token)),
+            consequence: alt,
+            alternative: None,
+            ..e
+        }),
+        (false, None) => EXPRESSION::IF(e),
+    }
+}
+
+/// An `else if` stays one as long as its own condition isn't folded away.
+fn optimize_else(alt: ElseBranch) -> ElseBranch {
+    match alt {
+        ElseBranch::BLOCK(block) => ElseBranch::BLOCK(optimize_block(block)),
+        ElseBranch::IF(e) => {
+            let (token, end) = (e.token.clone(), e.end());
+            match optimize_if_expression(*e) {
+                EXPRESSION::IF(e) => ElseBranch::IF(Box::new(e)),
+                expression => ElseBranch::BLOCK(BlockStatement {
+                    token: token.clone(),
+                    statements: vec![Statement::EXPRESSIONSTATEMENT(ExpressionStatement {
+                        token,
+                        expression,
+                    })],
+                    end,
+                }),
+            }
+        }
+    }
+}
+
+/// `fn(x, y) { x + y }(1, 2)` becomes `1 + 2` and is folded further. Only
+/// bodies made of a single expression without nested functions are inlined,
+/// so parameters can't be captured or shadowed.
+fn optimize_call_expression(mut e: CallExpression) -> EXPRESSION {
+    e.function = Box::new(optimize_expression(*e.function));
+    e.args = e.args.into_iter().map(optimize_expression).collect();
+
+    let inlined = match e.function.as_ref() {
+        EXPRESSION::FN(f) if f.parameters.len() == e.args.len() => inline(f, &e.args),
+        _ => None,
+    };
+
+    match inlined {
+        Some(exp) => optimize_expression(exp),
+        None => EXPRESSION::CALL(e),
+    }
+}
+
+fn inline(f: &FnExpression, args: &[EXPRESSION]) -> Option<EXPRESSION> {
+    if args.iter().any(|arg| constant(arg).is_none()) {
+        return None;
+    }
+
+    let body = match f.body.statements.as_slice() {
+        [Statement::EXPRESSIONSTATEMENT(s)] => &s.expression,
+        [Statement::RETURNSTATEMENT(s)] => &s.return_value,
+        _ => return None,
+    };
+
+    let params = f
+        .parameters
+        .iter()
+        .map(|p| match p {
+            Pattern::BINDING(name) => Some(name.value.as_str()),
+            _ => None,
+        })
+        .collect::<Option<Vec<&str>>>()?;
+    substitute(body, &params, args)
+}
+
+fn substitute(exp: &EXPRESSION, params: &[&str], args: &[EXPRESSION]) -> Option<EXPRESSION> {
+    let sub = |e: &EXPRESSION| substitute(e, params, args).map(Box::new);
+
+    Some(match exp {
+        EXPRESSION::IDENTIFIER(ident) => match params.iter().position(|p| *p == ident.value) {
+            Some(i) => args[i].clone(),
+            None => exp.clone(),
+        },
+        EXPRESSION::INTEGER(_)
+        | EXPRESSION::BOOLEAN(_)
+        | EXPRESSION::NULL(_)
+        | EXPRESSION::StringLiteral(_) => exp.clone(),
+        EXPRESSION::PREFIX(e) => {
+            let mut e = e.clone();
+            e.right = sub(&e.right)?;
+            EXPRESSION::PREFIX(e)
+        }
+        EXPRESSION::INFIX(e) => {
+            let mut e = e.clone();
+            e.left = sub(&e.left)?;
+            e.right = sub(&e.right)?;
+            EXPRESSION::INFIX(e)
+        }
+        EXPRESSION::DOT(e) => {
+            let mut e = e.clone();
+            e.left = sub(&e.left)?;
+            EXPRESSION::DOT(e)
+        }
+        // A parameter named like the method would have to become a call.
+        EXPRESSION::METHOD(e) if params.contains(&e.method.value.as_str()) => return None,
+        EXPRESSION::METHOD(e) => {
+            let mut e = e.clone();
+            e.receiver = sub(&e.receiver)?;
+            e.args = e
+                .args
+                .iter()
+                .map(|arg| substitute(arg, params, args))
+                .collect::<Option<_>>()?;
+            EXPRESSION::METHOD(e)
+        }
+        EXPRESSION::CALL(e) => {
+            let mut e = e.clone();
+            e.function = sub(&e.function)?;
+            e.args = e
+                .args
+                .iter()
+                .map(|arg| substitute(arg, params, args))
+                .collect::<Option<_>>()?;
+            EXPRESSION::CALL(e)
+        }
+        EXPRESSION::ArrayLiteral(e) => {
+            let mut e = e.clone();
+            e.items = e
+                .items
+                .iter()
+                .map(|item| substitute(item, params, args))
+                .collect::<Option<_>>()?;
+            EXPRESSION::ArrayLiteral(e)
+        }
+        EXPRESSION::TEMPLATE(e) => {
+            let mut e = e.clone();
+            e.expressions = e
+                .expressions
+                .iter()
+                .map(|exp| substitute(exp, params, args))
+                .collect::<Option<_>>()?;
+            EXPRESSION::TEMPLATE(e)
+        }
+        EXPRESSION::IndexExpression(e) => {
+            let mut e = e.clone();
+            e.left = sub(&e.left)?;
+            e.index = sub(&e.index)?;
+            EXPRESSION::IndexExpression(e)
+        }
+        EXPRESSION::SLICE(e) => {
+            let mut e = e.clone();
+            e.left = sub(&e.left)?;
+            if let Some(start) = &e.start {
+                e.start = Some(sub(start)?);
+            }
+            if let Some(end) = &e.end {
+                e.end = Some(sub(end)?);
+            }
+            EXPRESSION::SLICE(e)
+        }
+        EXPRESSION::HashLiteral(e) => {
+            let mut e = e.clone();
+            e.pairs = e
+                .pairs
+                .iter()
+                .map(|(k, v)| Some((substitute(k, params, args)?, substitute(v, params, args)?)))
+                .collect::<Option<_>>()?;
+            EXPRESSION::HashLiteral(e)
+        }
+        // Blocks may bind names with `let`, functions may capture the
+        // parameters and patterns may shadow them, none is safe to rewrite.
+        EXPRESSION::IF(_) | EXPRESSION::FN(_) | EXPRESSION::MATCH(_) => return None,
+    })
+}
+
+/// The value of a literal expression, `None` for anything that needs the
+/// environment to evaluate.
+fn constant(exp: &EXPRESSION) -> Option<Object> {
+    match exp {
+        EXPRESSION::INTEGER(e) => Some(Object::INTEGER(Integer { value: e.value })),
+        EXPRESSION::BOOLEAN(e) => Some(Object::BOOLEAN(Boolean { value: e.value })),
+        EXPRESSION::NULL(_) => Some(Object::NULL(Null {})),
+        EXPRESSION::StringLiteral(e) => Some(Object::STRING(StringObject {
+            value: e.value.clone(),
+        })),
+        _ => None,
+    }
+}
+
+/// Turns a folded value back into a literal. Errors are not folded so they
+/// are still raised at runtime.
+fn fold(obj: Object, token: &Token) -> Option<EXPRESSION> {
+    match obj {
+        Object::INTEGER(o) => Some(EXPRESSION::INTEGER(IntegerLiteral {
+            token: Token {
+                r#type: TokenType::INT,
+                literal: o.value.to_string(),
+                span: token.span,
+            },
+            value: o.value,
+        })),
+        Object::BOOLEAN(o) => Some(boolean_literal(o.value, token)),
+        Object::STRING(o) => Some(EXPRESSION::StringLiteral(StringLiteral {
+            token: Token {
+                r#type: TokenType::STRING,
+                literal: o.value.clone(),
+                span: token.span,
+            },
+            value: o.value,
+        })),
+        _ => None,
+    }
+}
+
+fn boolean_literal(value: bool, token: &Token) -> EXPRESSION {
+    EXPRESSION::BOOLEAN(BooleanExpression {
+        token: Token {
+            r#type: if value {
+                TokenType::TRUE
+            } else {
+                TokenType::FALSE
+            },
+            literal: value.to_string(),
+            span: token.span,
+        },
+        value,
+    })
+}
+
+fn is_truthy(obj: &Object) -> bool {
+    match obj {
+        Object::NULL(_) => false,
+        Object::BOOLEAN(o) => o.value,
+        _ => true,
+    }
+}
+
+fn branch_is_consequence(e: &IfExpression) -> bool {
+    constant(&e.condition).is_some_and(|c| is_truthy(&c))
+}
+
+fn ends_in_value(branch: Option<&BlockStatement>) -> bool {
+    matches!(
+        branch.and_then(|b| b.statements.last()),
+        Some(Statement::EXPRESSIONSTATEMENT(_)) | Some(Statement::RETURNSTATEMENT(_))
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::optimize;
+    use crate::ast::Node;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    #[test]
+    fn test_optimize() {
+        let tests = [
+            ("1 + 2 * 3", "7"),
+            ("-(2 - 5)", "3"),
+            ("!(1 < 2)", "false"),
+            ("\"foo\" + \"bar\" == \"foobar\"", "(foobar == foobar)"),
+            ("x + 2 * 3", "(x + 6)"),
+            ("9223372036854775807 + 1", "(9223372036854775807 + 1)"),
+            ("10 / (5 - 5)", "(10 / 0)"),
+            ("let a = if (true) { 1 } else { 2 };", "let a = 1;"),
+            ("let a = if (1 > 2) { 1 } else { 2 };", "let a = 2;"),
+            ("if (false) { x } y", "y"),
+            ("true && x", "x"),
+            ("1 > 2 && x", "false"),
+            ("x || 1 > 2", "(x || false)"),
+            ("null ?? x", "x"),
+            ("\"a\" ?? x", "a"),
+            (
+                "let a = if x { 1 } else if 1 > 2 { 2 } else { 3 };",
+                "let a = ifx 1else 3;",
+            ),
+            (
+                "let a = if x { 1 } else if y { 2 } else if true { 3 };",
+                "let a = ifx 1else ify 2else 3;",
+            ),
+            ("if (true) { let a = 1; a } ", "let a = 1;a"),
+            ("if (true) { let a = 1; }", "iftrue let a = 1;"),
+            (
+                "let f = fn(x) { return x; x + 1; };",
+                "let f = fn(x)return ;;",
+            ),
+            ("fn(x, y) { x * y }(3, 4) + 1", "13"),
+            ("fn(x) { x + y }(3)", "(3 + y)"),
+            ("fn(x) { fn(y) { x } }(3)", "fn(x)fn(y)x(3)"),
+            ("fn(x) { x }(y)", "fn(x)x(y)"),
+            ("1 |> fn(x) { x }(2 + 3)", "(1 |> fn(x)x(5))"),
+            ("1 + 1 |> f", "(2 |> f)"),
+        ];
+
+        for (input, expected) in tests {
+            let mut l = Lexer::new(input.to_string());
+            let mut p = Parser::new(&mut l);
+            let program = optimize(p.parse_program().unwrap());
+
+            if program.string() != expected {
+                panic!(
+                    "optimize({}) wrong. Expected {}, got {}",
+                    input,
+                    expected,
+                    program.string()
+                );
+            }
+        }
+    }
+}
@@ -9,10 +9,16 @@ use crate::token::*;
 #[derive(PartialEq, PartialOrd, Clone)]
 enum PrecedenceType {
     LOWEST,
+    RANGE,
+    COALESCE,
+    OR,
+    AND,
     EQUALS,
     LESSGREATER,
+    PIPE,
     SUM,
     PRODUCT,
+    COMPOSE,
     PREFIX,
     CALL,
     INDEX,
@@ -21,16 +27,24 @@ enum PrecedenceType {
 lazy_static! {
     static ref PRECEDENCES: HashMap<TokenType, PrecedenceType> = {
         let mut keywords = HashMap::new();
+        keywords.insert(TokenType::DOTDOT, PrecedenceType::RANGE);
+        keywords.insert(TokenType::DOTDOTEQ, PrecedenceType::RANGE);
+        keywords.insert(TokenType::COALESCE, PrecedenceType::COALESCE);
+        keywords.insert(TokenType::OR, PrecedenceType::OR);
+        keywords.insert(TokenType::AND, PrecedenceType::AND);
         keywords.insert(TokenType::EQ, PrecedenceType::EQUALS);
         keywords.insert(TokenType::NOTEQ, PrecedenceType::EQUALS);
         keywords.insert(TokenType::LT, PrecedenceType::LESSGREATER);
         keywords.insert(TokenType::GT, PrecedenceType::LESSGREATER);
+        keywords.insert(TokenType::PIPE, PrecedenceType::PIPE);
         keywords.insert(TokenType::PLUS, PrecedenceType::SUM);
         keywords.insert(TokenType::MINUS, PrecedenceType::SUM);
         keywords.insert(TokenType::SLASH, PrecedenceType::PRODUCT);
         keywords.insert(TokenType::ASTERISK, PrecedenceType::PRODUCT);
+        keywords.insert(TokenType::COMPOSE, PrecedenceType::COMPOSE);
         keywords.insert(TokenType::LPAREN, PrecedenceType::CALL);
         keywords.insert(TokenType::LBRACKET, PrecedenceType::INDEX);
+        keywords.insert(TokenType::DOT, PrecedenceType::INDEX);
         keywords
     };
 }
@@ -39,20 +53,29 @@ pub struct Parser<'a> {
     l: &'a mut Lexer,
     cur_token: Token,
     peek_token: Token,
-    errors: Vec<String>,
+    errors: Vec<ParseError>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub message: String,
+    /// The token the parser was looking at.
+    pub span: Span,
 }
 
 impl Parser<'_> {
-    pub fn new(l: &mut Lexer) -> Parser {
+    pub fn new(l: &mut Lexer) -> Parser<'_> {
         let mut p = Parser {
             l,
             cur_token: Token {
                 r#type: TokenType::EOF,
                 literal: "".to_string(),
+                span: Span::default(),
             },
             peek_token: Token {
                 r#type: TokenType::EOF,
                 literal: "".to_string(),
+                span: Span::default(),
             },
             errors: vec![],
         };
@@ -91,6 +114,18 @@ impl Parser<'_> {
                 }
                 None
             }
+            TokenType::IMPORT => {
+                if let Some(stmt) = self.parse_import_statement() {
+                    return Some(Statement::IMPORTSTATEMENT(stmt));
+                }
+                None
+            }
+            TokenType::EXPORT => {
+                if let Some(stmt) = self.parse_export_statement() {
+                    return Some(Statement::EXPORTSTATEMENT(stmt));
+                }
+                None
+            }
             _ => {
                 if let Some(stmt) = self.parse_exp_statement() {
                     return Some(Statement::EXPRESSIONSTATEMENT(stmt));
@@ -103,7 +138,7 @@ impl Parser<'_> {
     fn parse_exp_statement(&mut self) -> Option<ExpressionStatement> {
         let stmt = ExpressionStatement {
             token: self.cur_token.clone(),
-            expression: self.parse_expression(PrecedenceType::LOWEST),
+            expression: self.parse_expression(PrecedenceType::LOWEST)?,
         };
 
         if self.peek_token_is(TokenType::SEMICOLON) {
@@ -115,15 +150,7 @@ impl Parser<'_> {
 
     fn parse_let_statement(&mut self) -> Option<LetStatement> {
         let token = self.cur_token.clone();
-
-        if !self.expect_peek(TokenType::IDENT) {
-            return None;
-        }
-
-        let identifier = Identifier {
-            token: self.cur_token.clone(),
-            value: self.cur_token.literal.clone(),
-        };
+        let pattern = self.parse_binding_pattern()?;
 
         if !self.expect_peek(TokenType::ASSIGN) {
             return None;
@@ -133,8 +160,8 @@ impl Parser<'_> {
 
         let stmt = LetStatement {
             token,
-            name: identifier,
-            value: self.parse_expression(PrecedenceType::LOWEST),
+            pattern,
+            value: self.parse_expression(PrecedenceType::LOWEST)?,
         };
 
         if self.peek_token_is(TokenType::SEMICOLON) {
@@ -151,7 +178,7 @@ impl Parser<'_> {
 
         let stmt = ReturnStatement {
             token,
-            return_value: self.parse_expression(PrecedenceType::LOWEST),
+            return_value: self.parse_expression(PrecedenceType::LOWEST)?,
         };
 
         if self.peek_token_is(TokenType::SEMICOLON) {
@@ -161,30 +188,129 @@ impl Parser<'_> {
         Some(stmt)
     }
 
-    fn parse_grouped_expression(&mut self) -> EXPRESSION {
-        self.next_token();
+    fn parse_import_statement(&mut self) -> Option<ImportStatement> {
+        let token = self.cur_token.clone();
+        let mut alias = None;
+        let mut names = vec![];
+
+        if self.peek_token_is(TokenType::LBRACE) {
+            self.next_token();
+
+            while !self.peek_token_is(TokenType::RBRACE) {
+                if !self.expect_peek(TokenType::IDENT) {
+                    return None;
+                }
+                names.push(self.cur_identifier());
 
-        let exp = self.parse_expression(PrecedenceType::LOWEST);
+                if !self.peek_token_is(TokenType::RBRACE) && !self.expect_peek(TokenType::COMMA) {
+                    return None;
+                }
+            }
+            self.next_token();
 
+            if !self.expect_contextual_keyword("from") || !self.expect_peek(TokenType::STRING) {
+                return None;
+            }
+        } else if !self.expect_peek(TokenType::STRING) {
+            return None;
+        }
+
+        let path = self.cur_token.literal.clone();
+
+        if names.is_empty() {
+            if !self.expect_contextual_keyword("as") || !self.expect_peek(TokenType::IDENT) {
+                return None;
+            }
+            alias = Some(self.cur_identifier());
+        }
+
+        if self.peek_token_is(TokenType::SEMICOLON) {
+            self.next_token();
+        }
+
+        Some(ImportStatement {
+            token,
+            path,
+            alias,
+            names,
+        })
+    }
+
+    fn parse_export_statement(&mut self) -> Option<ExportStatement> {
+        let token = self.cur_token.clone();
+
+        if !self.expect_peek(TokenType::LET) {
+            return None;
+        }
+
+        Some(ExportStatement {
+            token,
+            stmt: self.parse_let_statement()?,
+        })
+    }
+
+    /// `as` and `from` are only keywords inside an import, elsewhere they are
+    /// plain identifiers.
+    fn expect_contextual_keyword(&mut self, keyword: &str) -> bool {
+        if self.peek_token_is(TokenType::IDENT) && self.peek_token.literal == keyword {
+            self.next_token();
+            true
+        } else {
+            self.error_at_peek(format!(
+                "Expected next token to be {:?}, got {:?}",
+                keyword, self.peek_token.literal
+            ));
+            false
+        }
+    }
+
+    fn cur_identifier(&self) -> Identifier {
+        Identifier {
+            token: self.cur_token.clone(),
+            value: self.cur_token.literal.clone(),
+            slot: None,
+        }
+    }
+
+    fn parse_grouped_expression(&mut self) -> Option<EXPRESSION> {
         self.next_token();
 
-        exp
+        let exp = self.parse_expression(PrecedenceType::LOWEST)?;
+
+        if !self.expect_peek(TokenType::RPAREN) {
+            return None;
+        }
+
+        Some(exp)
     }
 
-    fn parse_expression(&mut self, precedence: PrecedenceType) -> EXPRESSION {
+    fn parse_expression(&mut self, precedence: PrecedenceType) -> Option<EXPRESSION> {
         let mut left = match self.cur_token.r#type {
             TokenType::IDENT => self.pares_identifier(),
             TokenType::INT => self.parse_integer(),
             TokenType::BANG | TokenType::MINUS => self.parse_prefix_expression(),
             TokenType::TRUE | TokenType::FALSE => self.parse_boolean(),
+            TokenType::NULL => self.parse_null(),
             TokenType::LPAREN => self.parse_grouped_expression(),
             TokenType::IF => self.parse_if_expression(),
+            TokenType::MATCH => self.parse_match_expression(),
             TokenType::FUNCTION => self.parse_fn_literal(),
             TokenType::STRING => self.parse_string_literal(),
+            TokenType::TEMPLATE | TokenType::TEMPLATEHEAD => self.parse_template_literal(),
             TokenType::LBRACKET => self.parse_array_literal(),
             TokenType::LBRACE => self.parse_hash_literal(),
-            other => panic!("no prefix parse fn for {:?} defined", other),
-        };
+            other => {
+                let message = match other {
+                    TokenType::EOF => "Unexpected end of input".to_string(),
+                    _ => format!("No prefix parse function for {:?} found", other),
+                };
+                self.errors.push(ParseError {
+                    message,
+                    span: self.cur_token.span,
+                });
+                None
+            }
+        }?;
 
         while !self.peek_token_is(TokenType::SEMICOLON) && precedence < self.peek_precedence() {
             left = match self.peek_token.r#type {
@@ -195,108 +321,178 @@ impl Parser<'_> {
                 | TokenType::EQ
                 | TokenType::NOTEQ
                 | TokenType::LT
-                | TokenType::GT => {
+                | TokenType::GT
+                | TokenType::AND
+                | TokenType::OR
+                | TokenType::COALESCE
+                | TokenType::PIPE
+                | TokenType::COMPOSE
+                | TokenType::DOTDOT
+                | TokenType::DOTDOTEQ => {
                     self.next_token();
-                    self.parse_infix_expression(left)
+                    self.parse_infix_expression(left)?
                 }
                 TokenType::LPAREN => {
                     self.next_token();
-                    self.parse_call_expression(left)
+                    self.parse_call_expression(left)?
                 }
                 TokenType::LBRACKET => {
                     self.next_token();
-                    self.parse_index_expression(left)
+                    self.parse_index_expression(left)?
+                }
+                TokenType::DOT => {
+                    self.next_token();
+                    self.parse_dot_expression(left)?
                 }
                 _ => left,
             }
         }
 
-        left
+        Some(left)
+    }
+
+    fn parse_dot_expression(&mut self, left: EXPRESSION) -> Option<EXPRESSION> {
+        let token = self.cur_token.clone();
+
+        if !self.expect_peek(TokenType::IDENT) {
+            return None;
+        }
+
+        if !self.peek_token_is(TokenType::LPAREN) {
+            return Some(EXPRESSION::DOT(DotExpression {
+                token,
+                left: Box::new(left),
+                name: StringLiteral {
+                    token: self.cur_token.clone(),
+                    value: self.cur_token.literal.clone(),
+                },
+            }));
+        }
+
+        let method = self.cur_identifier();
+        self.next_token();
+
+        Some(EXPRESSION::METHOD(MethodCallExpression {
+            token,
+            receiver: Box::new(left),
+            method,
+            args: self.parse_expression_list(TokenType::RPAREN)?,
+        }))
     }
 
-    fn parse_hash_literal(&mut self) -> EXPRESSION {
+    fn parse_hash_literal(&mut self) -> Option<EXPRESSION> {
         let cur_token = self.cur_token.clone();
         let mut pairs = vec![];
 
         while !self.peek_token_is(TokenType::RBRACE) {
             self.next_token();
 
-            let key = self.parse_expression(PrecedenceType::LOWEST);
+            let key = self.parse_expression(PrecedenceType::LOWEST)?;
 
             if !self.expect_peek(TokenType::COLON) {
-                panic!("Expected colon here. Got: {:?}", self.cur_token);
+                return None;
             };
 
             self.next_token();
 
-            let val = self.parse_expression(PrecedenceType::LOWEST);
+            let val = self.parse_expression(PrecedenceType::LOWEST)?;
 
             pairs.push((key, val));
 
             if !self.peek_token_is(TokenType::RBRACE) && !self.expect_peek(TokenType::COMMA) {
-                panic!("Expected COMMA or RBRACE here while parsing hash literal");
+                return None;
             }
         }
 
         if !self.expect_peek(TokenType::RBRACE) {
-            panic!("Expected RBRACE here while parsing hash literal");
+            return None;
         }
 
-        EXPRESSION::HashLiteral(HashLiteral {
+        Some(EXPRESSION::HashLiteral(HashLiteral {
             token: cur_token,
             pairs,
-        })
+        }))
     }
 
-    fn parse_index_expression(&mut self, left: EXPRESSION) -> EXPRESSION {
+    /// `left[index]`, or a slice `left[start:end]` when there is a colon.
+    fn parse_index_expression(&mut self, left: EXPRESSION) -> Option<EXPRESSION> {
         let cur_token = self.cur_token.clone();
 
-        self.next_token();
+        let start = match self.peek_token_is(TokenType::COLON) {
+            true => None,
+            false => {
+                self.next_token();
+                Some(Box::new(self.parse_expression(PrecedenceType::LOWEST)?))
+            }
+        };
 
-        let index = self.parse_expression(PrecedenceType::LOWEST);
+        if self.peek_token_is(TokenType::COLON) {
+            self.next_token();
+            let end = match self.peek_token_is(TokenType::RBRACKET) {
+                true => None,
+                false => {
+                    self.next_token();
+                    Some(Box::new(self.parse_expression(PrecedenceType::LOWEST)?))
+                }
+            };
+
+            if !self.expect_peek(TokenType::RBRACKET) {
+                return None;
+            }
+
+            return Some(EXPRESSION::SLICE(SliceExpression {
+                token: cur_token,
+                left: Box::new(left),
+                start,
+                end,
+            }));
+        }
 
         if !self.expect_peek(TokenType::RBRACKET) {
-            panic!("Expected RBracket while parsing array index expression");
+            return None;
         }
+        // Without a colon there always is an index.
+        let index = start?;
 
-        EXPRESSION::IndexExpression(IndexExpression {
+        Some(EXPRESSION::IndexExpression(IndexExpression {
             token: cur_token,
             left: Box::new(left),
-            index: Box::new(index),
-        })
+            index,
+        }))
     }
 
-    fn parse_if_expression(&mut self) -> EXPRESSION {
-        let token = self.cur_token.clone();
+    fn parse_if_expression(&mut self) -> Option<EXPRESSION> {
+        Some(EXPRESSION::IF(self.parse_if()?))
+    }
 
-        if !self.expect_peek(TokenType::LPAREN) {
-            panic!("Left paran not found")
-        }
+    /// The parentheses around the condition are optional, `if x > 1 { x }`
+    /// is the same as `if (x > 1) { x }`.
+    fn parse_if(&mut self) -> Option<IfExpression> {
+        let token = self.cur_token.clone();
 
         self.next_token();
 
-        let condition = Box::new(self.parse_expression(PrecedenceType::LOWEST));
-
-        if !self.expect_peek(TokenType::RPAREN) {
-            panic!("Right paran not found")
-        }
+        let condition = Box::new(self.parse_expression(PrecedenceType::LOWEST)?);
 
         if !self.expect_peek(TokenType::LBRACE) {
-            panic!("Left brace not found")
+            return None;
         }
 
-        let consequence = self.parse_block_statement();
-        let mut alternative: Option<BlockStatement> = None;
+        let consequence = self.parse_block_statement()?;
+        let mut alternative: Option<ElseBranch> = None;
         if self.peek_token_is(TokenType::ELSE) {
             self.next_token();
 
-            if !self.expect_peek(TokenType::LBRACE) {
-                panic!("left brace not found while parsing else part");
+            if self.peek_token_is(TokenType::IF) {
+                self.next_token();
+                alternative = Some(ElseBranch::IF(Box::new(self.parse_if()?)));
+            } else if self.expect_peek(TokenType::LBRACE) {
+                alternative = Some(ElseBranch::BLOCK(self.parse_block_statement()?));
+            } else {
+                return None;
             }
-
-            alternative = Some(self.parse_block_statement())
         }
-        EXPRESSION::IF(IfExpression {
+        Some(IfExpression {
             token,
             condition,
             consequence,
@@ -304,207 +500,451 @@ impl Parser<'_> {
         })
     }
 
-    fn parse_block_statement(&mut self) -> BlockStatement {
+    /// `match (value) { pattern if guard => body, ... }`, where the guard is
+    /// optional and so are the parentheses around the value.
+    fn parse_match_expression(&mut self) -> Option<EXPRESSION> {
+        let token = self.cur_token.clone();
+
+        self.next_token();
+        let value = Box::new(self.parse_expression(PrecedenceType::LOWEST)?);
+
+        if !self.expect_peek(TokenType::LBRACE) {
+            return None;
+        }
+
+        let mut arms = vec![];
+        while !self.peek_token_is(TokenType::RBRACE) {
+            self.next_token();
+
+            let arm_token = self.cur_token.clone();
+            let pattern = self.parse_pattern()?;
+            let guard = if self.peek_token_is(TokenType::IF) {
+                self.next_token();
+                self.next_token();
+                Some(self.parse_expression(PrecedenceType::LOWEST)?)
+            } else {
+                None
+            };
+
+            if !self.expect_peek(TokenType::ARROW) {
+                return None;
+            }
+            self.next_token();
+            let body = self.parse_expression(PrecedenceType::LOWEST)?;
+
+            arms.push(MatchArm {
+                token: arm_token,
+                pattern,
+                guard,
+                body,
+            });
+
+            if !self.peek_token_is(TokenType::RBRACE) && !self.expect_peek(TokenType::COMMA) {
+                return None;
+            }
+        }
+
+        self.next_token();
+
+        Some(EXPRESSION::MATCH(MatchExpression {
+            token,
+            value,
+            arms,
+            end: self.cur_token.span,
+        }))
+    }
+
+    fn parse_pattern(&mut self) -> Option<Pattern> {
+        match self.cur_token.r#type {
+            TokenType::IDENT if self.cur_token.literal == "_" => {
+                Some(Pattern::WILDCARD(self.cur_token.clone()))
+            }
+            TokenType::IDENT => Some(Pattern::BINDING(self.cur_identifier())),
+            TokenType::INT
+            | TokenType::STRING
+            | TokenType::TRUE
+            | TokenType::FALSE
+            | TokenType::NULL => Some(Pattern::LITERAL(self.parse_literal_pattern()?)),
+            TokenType::MINUS if self.peek_token_is(TokenType::INT) => {
+                Some(Pattern::LITERAL(self.parse_literal_pattern()?))
+            }
+            TokenType::LBRACKET => self.parse_array_pattern(),
+            TokenType::LBRACE => self.parse_hash_pattern(),
+            other => {
+                self.errors.push(ParseError {
+                    message: format!("Expected a pattern, got {:?}", other),
+                    span: self.cur_token.span,
+                });
+                None
+            }
+        }
+    }
+
+    /// What `let` and function parameters bind: a name, `_`, or an array or
+    /// hash pattern to destructure the value with.
+    fn parse_binding_pattern(&mut self) -> Option<Pattern> {
+        if self.peek_token_is(TokenType::LBRACKET) || self.peek_token_is(TokenType::LBRACE) {
+            self.next_token();
+            return self.parse_pattern();
+        }
+
+        if !self.expect_peek(TokenType::IDENT) {
+            return None;
+        }
+        self.parse_pattern()
+    }
+
+    fn parse_literal_pattern(&mut self) -> Option<EXPRESSION> {
+        match self.cur_token.r#type {
+            TokenType::INT => self.parse_integer(),
+            TokenType::STRING => self.parse_string_literal(),
+            TokenType::TRUE | TokenType::FALSE => self.parse_boolean(),
+            TokenType::NULL => self.parse_null(),
+            _ => {
+                let token = self.cur_token.clone();
+                self.next_token();
+                Some(EXPRESSION::PREFIX(PrefixExpression {
+                    operator: token.literal.clone(),
+                    token,
+                    right: Box::new(self.parse_integer()?),
+                }))
+            }
+        }
+    }
+
+    fn parse_array_pattern(&mut self) -> Option<Pattern> {
+        let token = self.cur_token.clone();
+        let mut items = vec![];
+        let mut rest = None;
+
+        while !self.peek_token_is(TokenType::RBRACKET) {
+            self.next_token();
+
+            if self.cur_token_is(TokenType::ELLIPSIS) {
+                self.next_token();
+                match self.parse_pattern()? {
+                    p @ (Pattern::BINDING(_) | Pattern::WILDCARD(_)) => rest = Some(Box::new(p)),
+                    _ => {
+                        self.errors.push(ParseError {
+                            message: "Expected a name after ...".to_string(),
+                            span: self.cur_token.span,
+                        });
+                        return None;
+                    }
+                }
+                // The rest pattern comes last.
+                if !self.expect_peek(TokenType::RBRACKET) {
+                    return None;
+                }
+                return Some(Pattern::ARRAY(ArrayPattern { token, items, rest }));
+            }
+
+            items.push(self.parse_pattern()?);
+
+            if !self.peek_token_is(TokenType::RBRACKET) && !self.expect_peek(TokenType::COMMA) {
+                return None;
+            }
+        }
+
+        self.next_token();
+        Some(Pattern::ARRAY(ArrayPattern { token, items, rest }))
+    }
+
+    fn parse_hash_pattern(&mut self) -> Option<Pattern> {
+        let token = self.cur_token.clone();
+        let mut pairs = vec![];
+
+        while !self.peek_token_is(TokenType::RBRACE) {
+            self.next_token();
+
+            if self.cur_token_is(TokenType::IDENT)
+                && (self.peek_token_is(TokenType::COMMA) || self.peek_token_is(TokenType::RBRACE))
+            {
+                let name = self.cur_identifier();
+                let key = EXPRESSION::StringLiteral(StringLiteral {
+                    token: name.token.clone(),
+                    value: name.value.clone(),
+                });
+                pairs.push((key, Pattern::BINDING(name)));
+
+                if self.peek_token_is(TokenType::COMMA) {
+                    self.next_token();
+                }
+                continue;
+            }
+
+            let key = match self.cur_token.r#type {
+                TokenType::INT | TokenType::STRING | TokenType::TRUE | TokenType::FALSE => {
+                    self.parse_literal_pattern()?
+                }
+                other => {
+                    self.errors.push(ParseError {
+                        message: format!("Expected a hash key, got {:?}", other),
+                        span: self.cur_token.span,
+                    });
+                    return None;
+                }
+            };
+
+            if !self.expect_peek(TokenType::COLON) {
+                return None;
+            }
+            self.next_token();
+            pairs.push((key, self.parse_pattern()?));
+
+            if !self.peek_token_is(TokenType::RBRACE) && !self.expect_peek(TokenType::COMMA) {
+                return None;
+            }
+        }
+
+        self.next_token();
+        Some(Pattern::HASH(HashPattern { token, pairs }))
+    }
+
+    fn parse_block_statement(&mut self) -> Option<BlockStatement> {
         let mut block_stmt = BlockStatement {
             token: self.cur_token.clone(),
             statements: vec![],
+            end: Span::default(),
         };
 
         self.next_token();
 
-        while !self.cur_token_is(TokenType::RBRACE) && !self.cur_token_is(TokenType::EOF) {
-            if let Some(stmt) = self.parse_statement() {
-                block_stmt.statements.push(stmt);
-                self.next_token();
+        while !self.cur_token_is(TokenType::RBRACE) {
+            if self.cur_token_is(TokenType::EOF) {
+                self.errors.push(ParseError {
+                    message: "Expected RBRACE, got end of input".to_string(),
+                    span: self.cur_token.span,
+                });
+                return None;
             }
+
+            let stmt = self.parse_statement()?;
+            block_stmt.statements.push(stmt);
+            self.next_token();
         }
 
-        block_stmt
+        block_stmt.end = self.cur_token.span;
+        Some(block_stmt)
     }
 
-    fn pares_identifier(&self) -> EXPRESSION {
-        EXPRESSION::IDENTIFIER(Identifier {
+    fn pares_identifier(&self) -> Option<EXPRESSION> {
+        Some(EXPRESSION::IDENTIFIER(Identifier {
             token: self.cur_token.clone(),
             value: self.cur_token.literal.clone(),
-        })
+            slot: None,
+        }))
     }
 
-    fn parse_integer(&mut self) -> EXPRESSION {
+    fn parse_integer(&mut self) -> Option<EXPRESSION> {
         let literal_val = match self.cur_token.literal.parse() {
             Ok(val) => val,
             Err(_) => {
-                self.errors.push(format!(
-                    "Could not parse {} as integer",
-                    self.cur_token.literal
-                ));
+                self.errors.push(ParseError {
+                    message: format!("Could not parse {} as integer", self.cur_token.literal),
+                    span: self.cur_token.span,
+                });
                 0
             }
         };
 
-        EXPRESSION::INTEGER(IntegerLiteral {
+        Some(EXPRESSION::INTEGER(IntegerLiteral {
             token: self.cur_token.clone(),
             value: literal_val,
-        })
+        }))
     }
 
-    fn parse_boolean(&self) -> EXPRESSION {
-        EXPRESSION::BOOLEAN(BooleanExpression {
+    fn parse_boolean(&self) -> Option<EXPRESSION> {
+        Some(EXPRESSION::BOOLEAN(BooleanExpression {
             token: self.cur_token.clone(),
             value: self.cur_token_is(TokenType::TRUE),
-        })
+        }))
     }
 
-    fn parse_string_literal(&self) -> EXPRESSION {
-        EXPRESSION::StringLiteral(StringLiteral {
+    fn parse_null(&self) -> Option<EXPRESSION> {
+        Some(EXPRESSION::NULL(NullLiteral {
+            token: self.cur_token.clone(),
+        }))
+    }
+
+    fn parse_string_literal(&self) -> Option<EXPRESSION> {
+        Some(EXPRESSION::StringLiteral(StringLiteral {
             token: self.cur_token.clone(),
             value: self.cur_token.literal.clone(),
-        })
+        }))
+    }
+
+    fn parse_template_literal(&mut self) -> Option<EXPRESSION> {
+        let token = self.cur_token.clone();
+        let mut strings = vec![token.literal.clone()];
+        let mut expressions = vec![];
+
+        while !self.cur_token_is(TokenType::TEMPLATE) && !self.cur_token_is(TokenType::TEMPLATETAIL)
+        {
+            self.next_token();
+            expressions.push(self.parse_expression(PrecedenceType::LOWEST)?);
+
+            if self.peek_token_is(TokenType::TEMPLATEMIDDLE) {
+                self.next_token();
+            } else if !self.expect_peek(TokenType::TEMPLATETAIL) {
+                return None;
+            }
+            strings.push(self.cur_token.literal.clone());
+        }
+
+        Some(EXPRESSION::TEMPLATE(TemplateLiteral {
+            token,
+            strings,
+            expressions,
+        }))
     }
 
-    fn parse_array_literal(&mut self) -> EXPRESSION {
-        return EXPRESSION::ArrayLiteral(ArrayLitearl {
+    fn parse_array_literal(&mut self) -> Option<EXPRESSION> {
+        Some(EXPRESSION::ArrayLiteral(ArrayLitearl {
             token: self.cur_token.clone(),
-            items: self.parse_expression_list(TokenType::RBRACKET),
-        });
+            items: self.parse_expression_list(TokenType::RBRACKET)?,
+        }))
     }
 
-    fn parse_expression_list(&mut self, end: TokenType) -> Vec<EXPRESSION> {
+    fn parse_expression_list(&mut self, end: TokenType) -> Option<Vec<EXPRESSION>> {
         let mut list: Vec<EXPRESSION> = vec![];
 
         if self.peek_token_is(end) {
             self.next_token();
-            return list;
+            return Some(list);
         }
 
         self.next_token();
 
-        list.push(self.parse_expression(PrecedenceType::LOWEST));
+        list.push(self.parse_expression(PrecedenceType::LOWEST)?);
 
         while self.peek_token_is(TokenType::COMMA) {
             self.next_token();
             self.next_token();
 
-            list.push(self.parse_expression(PrecedenceType::LOWEST));
+            list.push(self.parse_expression(PrecedenceType::LOWEST)?);
         }
 
         if !self.expect_peek(end) {
-            panic!("Expected {:?} while parsing parameter list", end)
+            return None;
         }
 
-        list
+        Some(list)
     }
 
-    fn parse_prefix_expression(&mut self) -> EXPRESSION {
+    fn parse_prefix_expression(&mut self) -> Option<EXPRESSION> {
         let token = self.cur_token.clone();
         let operator = self.cur_token.literal.clone();
         self.next_token();
         let exp = PrefixExpression {
             token,
             operator,
-            right: Box::new(self.parse_expression(PrecedenceType::PREFIX)),
+            right: Box::new(self.parse_expression(PrecedenceType::PREFIX)?),
         };
 
-        EXPRESSION::PREFIX(exp)
+        Some(EXPRESSION::PREFIX(exp))
     }
 
-    fn parse_infix_expression(&mut self, left: EXPRESSION) -> EXPRESSION {
+    fn parse_infix_expression(&mut self, left: EXPRESSION) -> Option<EXPRESSION> {
         let precedence = self.cur_precedence();
         let cur_token = self.cur_token.clone();
 
         self.next_token();
 
-        EXPRESSION::INFIX(InfixExpression {
+        Some(EXPRESSION::INFIX(InfixExpression {
             token: cur_token.clone(),
             operator: cur_token.literal.clone(),
             left: Box::new(left),
-            right: Box::new(self.parse_expression(precedence)),
-        })
+            right: Box::new(self.parse_expression(precedence)?),
+        }))
     }
 
-    fn parse_fn_literal(&mut self) -> EXPRESSION {
+    fn parse_fn_literal(&mut self) -> Option<EXPRESSION> {
         let token = self.cur_token.clone();
 
         if !self.expect_peek(TokenType::LPAREN) {
-            panic!("Expected LPAREN not found while parsing fn literal");
+            return None;
         }
 
-        let parameters = self.parse_fn_params();
+        let parameters = self.parse_fn_params()?;
 
         if !self.expect_peek(TokenType::LBRACE) {
-            panic!("Expected LBRACE not found while parsing fn literal");
+            return None;
         };
 
-        EXPRESSION::FN(FnExpression {
+        Some(EXPRESSION::FN(FnExpression {
             token,
             parameters,
-            body: self.parse_block_statement(),
-        })
+            body: self.parse_block_statement()?,
+        }))
     }
 
-    fn parse_fn_params(&mut self) -> Vec<Identifier> {
-        let mut params: Vec<Identifier> = vec![];
+    fn parse_fn_params(&mut self) -> Option<Vec<Pattern>> {
+        let mut params: Vec<Pattern> = vec![];
 
         if self.peek_token_is(TokenType::RPAREN) {
             self.next_token();
-            return params;
+            return Some(params);
         }
 
-        self.next_token();
-
-        params.push(Identifier {
-            token: self.cur_token.clone(),
-            value: self.cur_token.literal.clone(),
-        });
+        params.push(self.parse_binding_pattern()?);
 
         while self.peek_token_is(TokenType::COMMA) {
             self.next_token();
-            self.next_token();
-
-            params.push(Identifier {
-                token: self.cur_token.clone(),
-                value: self.cur_token.literal.clone(),
-            });
+            params.push(self.parse_binding_pattern()?);
         }
 
         if !self.expect_peek(TokenType::RPAREN) {
-            panic!("Expected RPAREN not found while parsing fn literal");
+            return None;
         }
 
-        params
+        Some(params)
     }
 
-    fn parse_call_expression(&mut self, function: EXPRESSION) -> EXPRESSION {
-        EXPRESSION::CALL(CallExpression {
+    fn parse_call_expression(&mut self, function: EXPRESSION) -> Option<EXPRESSION> {
+        Some(EXPRESSION::CALL(CallExpression {
             token: self.cur_token.clone(),
             function: Box::new(function),
-            args: self.parse_expression_list(TokenType::RPAREN),
-        })
+            args: self.parse_expression_list(TokenType::RPAREN)?,
+        }))
     }
 
     fn cur_token_is(&self, token: TokenType) -> bool {
-        return self.cur_token.r#type == token;
+        self.cur_token.r#type == token
     }
 
     fn peek_token_is(&self, token: TokenType) -> bool {
-        return self.peek_token.r#type == token;
+        self.peek_token.r#type == token
     }
 
     fn expect_peek(&mut self, token: TokenType) -> bool {
-        if self.peek_token_is(token.clone()) {
+        if self.peek_token_is(token) {
             self.next_token();
-            return true;
+            true
         } else {
             self.peek_errors(token);
-            return false;
+            false
         }
     }
 
     pub fn errors(&self) -> Vec<String> {
-        self.errors.clone()
+        self.errors.iter().map(|e| e.message.clone()).collect()
+    }
+
+    /// The errors with the position they were found at.
+    pub fn parse_errors(&self) -> &[ParseError] {
+        &self.errors
+    }
+
+    fn error_at_peek(&mut self, message: String) {
+        self.errors.push(ParseError {
+            message,
+            span: self.peek_token.span,
+        })
     }
 
     fn peek_errors(&mut self, expected_token_type: TokenType) {
-        self.errors.push(format!(
+        self.error_at_peek(format!(
             "Expected next token to be {:?}, got {:?}",
             expected_token_type, self.peek_token.r#type
         ))
@@ -526,6 +966,13 @@ impl Parser<'_> {
 }
 
 #[cfg(test)]
+#[allow(
+    clippy::bool_comparison,
+    clippy::cmp_owned,
+    clippy::len_zero,
+    clippy::needless_borrow,
+    clippy::ptr_arg
+)]
 mod tests {
     use super::*;
     use crate::ast::Node;
@@ -599,13 +1046,24 @@ mod tests {
         }
     }
 
-    fn test_let_statement(statement: &Statement, expected_identifier: &String) -> bool {
+    fn binding(pattern: &Pattern) -> EXPRESSION {
+        match pattern {
+            Pattern::BINDING(name) => EXPRESSION::IDENTIFIER(name.clone()),
+            other => panic!("expected a binding pattern, got {:?}", other),
+        }
+    }
+
+    fn test_let_statement(statement: &Statement, expected_identifier: &str) -> bool {
         match statement {
             Statement::LETSTATEMENT(stmt) => {
-                if stmt.name.value != expected_identifier.clone() {
+                let name = match stmt.name() {
+                    Some(name) => name,
+                    None => return false,
+                };
+                if name.value != expected_identifier {
                     return false;
                 }
-                if stmt.name.token_literal() != expected_identifier.clone() {
+                if name.token_literal() != expected_identifier {
                     return false;
                 }
             }
@@ -1073,6 +1531,62 @@ mod tests {
                 input: "add(a * b[2], b[1], 2 * [1, 2][1])".to_string(),
                 expected: "add((a * (b[2])), (b[1]), (2 * ([1, 2][1])))".to_string(),
             },
+            Test {
+                input: "a || b && c == d".to_string(),
+                expected: "(a || (b && (c == d)))".to_string(),
+            },
+            Test {
+                input: "a ?? b || !c && null".to_string(),
+                expected: "(a ?? (b || ((!c) && null)))".to_string(),
+            },
+            Test {
+                input: "a && b && c ?? d".to_string(),
+                expected: "(((a && b) && c) ?? d)".to_string(),
+            },
+            Test {
+                input: "xs |> map(f) |> sum() == 3 ?? x".to_string(),
+                expected: "((((xs |> map(f)) |> sum()) == 3) ?? x)".to_string(),
+            },
+            Test {
+                input: "xs |> len == 3 && ok".to_string(),
+                expected: "(((xs |> len) == 3) && ok)".to_string(),
+            },
+            Test {
+                input: "a + b |> f".to_string(),
+                expected: "((a + b) |> f)".to_string(),
+            },
+            Test {
+                input: "x |> f >> g >> h * 2".to_string(),
+                expected: "(x |> (((f >> g) >> h) * 2))".to_string(),
+            },
+            Test {
+                input: "1..n + 1 |> map(f)".to_string(),
+                expected: "(1 .. ((n + 1) |> map(f)))".to_string(),
+            },
+            Test {
+                input: "a ?? 0..=len(xs) - 1".to_string(),
+                expected: "((a ?? 0) ..= (len(xs) - 1))".to_string(),
+            },
+            Test {
+                input: "xs[1:3] + xs[:-1] + s[2:] + s[:]".to_string(),
+                expected: "((((xs[1:3]) + (xs[:(-1)])) + (s[2:])) + (s[:]))".to_string(),
+            },
+            Test {
+                input: "a.b[i + 1:][0]".to_string(),
+                expected: "(((a.b)[(i + 1):])[0])".to_string(),
+            },
+            Test {
+                input: "-person.age + a.b[0].c".to_string(),
+                expected: "((-(person.age)) + (((a.b)[0]).c))".to_string(),
+            },
+            Test {
+                input: "arr.push(4).map(f)[1] * x.len()".to_string(),
+                expected: "((arr.push(4).map(f)[1]) * x.len())".to_string(),
+            },
+            Test {
+                input: "f(a).b(c.d, 1 + 2)".to_string(),
+                expected: "f(a).b((c.d), (1 + 2))".to_string(),
+            },
         ];
 
         for test in tests {
@@ -1095,6 +1609,171 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_import_export_statements() {
+        struct Test {
+            input: String,
+            expected: String,
+        }
+
+        let tests = [
+            Test {
+                input: "import \"lib/math.monkey\" as math;".to_string(),
+                expected: "import \"lib/math.monkey\" as math;".to_string(),
+            },
+            Test {
+                input: "import { add, double } from \"math.monkey\"".to_string(),
+                expected: "import { add, double } from \"math.monkey\";".to_string(),
+            },
+            Test {
+                input: "export let add = fn(x, y) { x + y };".to_string(),
+                expected: "export let add = fn(x, y)(x + y);".to_string(),
+            },
+        ];
+
+        for test in tests {
+            let mut l = Lexer::new(test.input);
+            let mut p = Parser::new(&mut l);
+            let program = p.parse_program();
+
+            check_parser_errors(&p);
+
+            let program = match program {
+                Some(p) => p,
+                None => panic!("parse_program returned nil"),
+            };
+
+            let actual = program.string();
+            if actual != test.expected {
+                panic!("Expected {}, Got {}", test.expected, actual)
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        struct Test {
+            input: String,
+            expected: (String, usize, usize),
+        }
+
+        let tests = [
+            Test {
+                input: "let x = ".to_string(),
+                expected: ("Unexpected end of input".to_string(), 1, 9),
+            },
+            Test {
+                input: "let f = fn(x) {\n  x +".to_string(),
+                expected: ("Unexpected end of input".to_string(), 2, 6),
+            },
+            Test {
+                input: "if (x { 1 }".to_string(),
+                expected: (
+                    "Expected next token to be RPAREN, got LBRACE".to_string(),
+                    1,
+                    7,
+                ),
+            },
+            Test {
+                input: "{\"a\" 1}".to_string(),
+                expected: ("Expected next token to be COLON, got INT".to_string(), 1, 6),
+            },
+            Test {
+                input: "let f = fn(x, 1) { x };".to_string(),
+                expected: (
+                    "Expected next token to be IDENT, got INT".to_string(),
+                    1,
+                    15,
+                ),
+            },
+            Test {
+                input: "let g = fn() { let 1 };".to_string(),
+                expected: (
+                    "Expected next token to be IDENT, got INT".to_string(),
+                    1,
+                    20,
+                ),
+            },
+            Test {
+                input: "match x { 1 2 }".to_string(),
+                expected: (
+                    "Expected next token to be ARROW, got INT".to_string(),
+                    1,
+                    13,
+                ),
+            },
+            Test {
+                input: "match x { [...a, b] => 1 }".to_string(),
+                expected: (
+                    "Expected next token to be RBRACKET, got COMMA".to_string(),
+                    1,
+                    16,
+                ),
+            },
+            Test {
+                input: "match x { a + 1 => 1 }".to_string(),
+                expected: (
+                    "Expected next token to be ARROW, got PLUS".to_string(),
+                    1,
+                    13,
+                ),
+            },
+            Test {
+                input: "match x { {a: 1} => 1 }".to_string(),
+                expected: ("Expected a hash key, got IDENT".to_string(), 1, 12),
+            },
+            Test {
+                input: "`a ${x y}`".to_string(),
+                expected: (
+                    "Expected next token to be TEMPLATETAIL, got IDENT".to_string(),
+                    1,
+                    8,
+                ),
+            },
+            Test {
+                input: "person.1".to_string(),
+                expected: ("Expected next token to be IDENT, got INT".to_string(), 1, 8),
+            },
+            Test {
+                input: "xs[1:2:3]".to_string(),
+                expected: (
+                    "Expected next token to be RBRACKET, got COLON".to_string(),
+                    1,
+                    7,
+                ),
+            },
+            Test {
+                input: "xs[]".to_string(),
+                expected: (
+                    "No prefix parse function for RBRACKET found".to_string(),
+                    1,
+                    4,
+                ),
+            },
+            Test {
+                input: "let {a b} = x;".to_string(),
+                expected: ("Expected a hash key, got IDENT".to_string(), 1, 6),
+            },
+        ];
+
+        for test in tests {
+            let mut l = Lexer::new(test.input.clone());
+            let mut p = Parser::new(&mut l);
+            p.parse_program();
+
+            let actual = match p.parse_errors().first() {
+                Some(e) => (e.message.clone(), e.span.line, e.span.column),
+                None => panic!("{:?} parsed without errors", test.input),
+            };
+            if actual != test.expected {
+                panic!(
+                    "{:?}: expected {:?}, got {:?}",
+                    test.input, test.expected, actual
+                );
+            }
+        }
+    }
+
     #[test]
     fn test_boolean_expression() {
         let input = "true;";
@@ -1265,7 +1944,7 @@ mod tests {
         test_identifier(&consequence.expression, "x".to_string());
 
         match &exp.alternative {
-            Some(obj) => {
+            Some(ElseBranch::BLOCK(obj)) => {
                 if obj.statements.len() != 1 {
                     panic!(
                         "alternative is not 1 statements. Got {}",
@@ -1283,10 +1962,122 @@ mod tests {
 
                 test_identifier(&alt.expression, "y".to_string());
             }
+            Some(ElseBranch::IF(_)) => panic!("exp.alternative is an else if"),
             None => panic!("exp.alternative is none"),
         }
     }
 
+    #[test]
+    fn test_match_expression() {
+        let tests = [
+            (
+                "match (x) { 0 => a, -1 => b, n if n > 1 => c, _ => d }",
+                "match x {0 => a, (-1) => b, n if (n > 1) => c, _ => d}",
+            ),
+            (
+                "match x { [a, [b], ...rest] => a, [..._] => 0, [] => 1, }",
+                "match x {[a, [b], ...rest] => a, [..._] => 0, [] => 1}",
+            ),
+            (
+                "match x { {\"type\": \"add\", 1: [y]} => y, {} => null }",
+                "match x {{type : add, 1 : [y]} => y, {} => null}",
+            ),
+            ("match x {}", "match x {}"),
+        ];
+
+        for (input, expected) in tests {
+            let mut l = Lexer::new(input.to_string());
+            let mut p = Parser::new(&mut l);
+            let program = p.parse_program();
+            check_parser_errors(&p);
+
+            let program = program.unwrap();
+            let arms = match &program.statements[0] {
+                Statement::EXPRESSIONSTATEMENT(s) => match &s.expression {
+                    EXPRESSION::MATCH(e) => e.arms.len(),
+                    other => panic!("expected a match expression, got {:?}", other),
+                },
+                other => panic!("expected an expression statement, got {:?}", other),
+            };
+            if program.string() != expected {
+                panic!(
+                    "Expected {:?}. Got {:?} with {} arms",
+                    expected,
+                    program.string(),
+                    arms
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_template_literal() {
+        let tests = [
+            ("`hi`", "`hi`"),
+            ("`a ${x + 1} b ${`c${y}`}`", "`a ${(x + 1)} b ${`c${y}`}`"),
+            ("`\\` \\${ \\\\ \\n $`", "`\\` \\${ \\ \\n $`"),
+            ("`${ {\"k\": 1}[\"k\"] }`", "`${({k : 1}[k])}`"),
+        ];
+
+        for (input, expected) in tests {
+            let mut l = Lexer::new(input.to_string());
+            let mut p = Parser::new(&mut l);
+            let program = p.parse_program();
+            check_parser_errors(&p);
+
+            let actual = program.unwrap().string();
+            if actual != expected {
+                panic!("Expected {:?}. Got {:?}", expected, actual);
+            }
+        }
+    }
+
+    #[test]
+    fn test_destructuring() {
+        let tests = [
+            ("let [a, ...rest] = xs;", "let [a, ...rest] = xs;"),
+            ("let {name, age} = person;", "let {name, age} = person;"),
+            ("let {\"x\": [x, _], y} = p;", "let {x : [x, _], y} = p;"),
+            ("fn([a, b], {c}) { a }", "fn([a, b], {c})a"),
+        ];
+
+        for (input, expected) in tests {
+            let mut l = Lexer::new(input.to_string());
+            let mut p = Parser::new(&mut l);
+            let program = p.parse_program();
+            check_parser_errors(&p);
+
+            let actual = program.unwrap().string();
+            if actual != expected {
+                panic!("Expected {:?}. Got {:?}", expected, actual);
+            }
+        }
+    }
+
+    #[test]
+    fn test_else_if_expression() {
+        let tests = [
+            (
+                "if x > 1 { a } else if x < 0 { b } else { c }",
+                "if(x > 1) aelse if(x < 0) belse c",
+            ),
+            ("if (x) { a } else if y { b }", "ifx aelse ify b"),
+            ("if !x { a }", "if(!x) a"),
+            ("if (a) + b { c }", "if(a + b) c"),
+        ];
+
+        for (input, expected) in tests {
+            let mut l = Lexer::new(input.to_string());
+            let mut p = Parser::new(&mut l);
+            let program = p.parse_program();
+            check_parser_errors(&p);
+
+            let actual = program.unwrap().string();
+            if actual != expected {
+                panic!("Expected {:?}. Got {:?}", expected, actual);
+            }
+        }
+    }
     #[test]
     fn test_fn_literal_parsing() {
         let input = "fn (x, y) { x + y; }".to_string();
@@ -1329,14 +2120,8 @@ mod tests {
             )
         }
 
-        test_identifier(
-            &EXPRESSION::IDENTIFIER(exp.parameters[0].clone()),
-            "x".to_string(),
-        );
-        test_identifier(
-            &EXPRESSION::IDENTIFIER(exp.parameters[1].clone()),
-            "y".to_string(),
-        );
+        test_identifier(&binding(&exp.parameters[0]), "x".to_string());
+        test_identifier(&binding(&exp.parameters[1]), "y".to_string());
 
         if exp.body.statements.len() != 1 {
             panic!(
@@ -1417,10 +2202,7 @@ mod tests {
             }
 
             for (i, param) in test.expected.into_iter().enumerate() {
-                test_literal_expression(
-                    &EXPRESSION::IDENTIFIER(fn_literal.parameters[i].clone()),
-                    param,
-                )
+                test_literal_expression(&binding(&fn_literal.parameters[i]), param)
             }
         }
     }
@@ -1918,7 +2700,7 @@ mod tests {
     fn test_boolean_literal(exp: &EXPRESSION, expected: String) {
         match exp {
             EXPRESSION::BOOLEAN(obj) => {
-                if obj.value != expected.parse().unwrap() {
+                if obj.value != expected.parse::<bool>().unwrap() {
                     panic!(
                         "booleanExpression value not {}. Got {}",
                         expected, obj.value
@@ -0,0 +1,134 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{ast::Program, lexer::Lexer, parser::Parser};
+
+/// Library functions written in Monkey, loaded into every fresh environment.
+pub const DEFAULT_PRELUDE: &str = include_str!("prelude.monkey");
+
+thread_local! {
+    static PRELUDE: RefCell<Option<Rc<Program>>> = RefCell::new(Some(Rc::new(
+        parse(DEFAULT_PRELUDE).expect("default prelude should parse"),
+    )));
+}
+
+fn parse(source: &str) -> Result<Program, String> {
+    let mut l = Lexer::new(source.to_string());
+    let mut p = Parser::new(&mut l);
+
+    match p.parse_program() {
+        Some(program) if p.errors().is_empty() => Ok(program),
+        _ => Err(format!("in prelude: {}", p.errors().join("; "))),
+    }
+}
+
+/// Replaces the prelude of this thread with `source`, or disables it when
+/// `None`. The previous prelude stays in place if `source` doesn't parse.
+pub fn set_prelude(source: Option<&str>) -> Result<(), String> {
+    let program = match source {
+        Some(source) => Some(Rc::new(parse(source)?)),
+        None => None,
+    };
+
+    PRELUDE.with(|prelude| prelude.replace(program));
+    Ok(())
+}
+
+pub fn reset_prelude() {
+    set_prelude(Some(DEFAULT_PRELUDE)).expect("default prelude should parse")
+}
+
+/// The parsed prelude, `None` when it is disabled.
+pub fn program() -> Option<Rc<Program>> {
+    PRELUDE.with(|prelude| prelude.borrow().clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{reset_prelude, set_prelude};
+    use crate::eval::eval;
+    use crate::lexer::Lexer;
+    use crate::object::ObjectTrait;
+    use crate::parser::Parser;
+
+    fn test_eval(input: &str) -> String {
+        let mut l = Lexer::new(input.to_string());
+        let mut p = Parser::new(&mut l);
+        eval(p.parse_program().unwrap()).inspect()
+    }
+
+    #[test]
+    fn test_default_prelude() {
+        struct Test {
+            input: String,
+            expected: String,
+        }
+
+        let tests = [
+            Test {
+                input: "map([1, 2, 3], fn(x) { x * 2 })".to_string(),
+                expected: "[2, 4, 6]".to_string(),
+            },
+            Test {
+                input: "filter([1, 2, 3, 4], fn(x) { x > 2 })".to_string(),
+                expected: "[3, 4]".to_string(),
+            },
+            Test {
+                input: "reduce([1, 2, 3], 10, fn(acc, x) { acc - x })".to_string(),
+                expected: "4".to_string(),
+            },
+            Test {
+                input: "let inc = fn(x) { x + 1 }; compose(inc, fn(x) { x * 3 })(2)".to_string(),
+                expected: "7".to_string(),
+            },
+            Test {
+                input: "identity(\"monkey\")".to_string(),
+                expected: "monkey".to_string(),
+            },
+            Test {
+                input: "[sum(range(0, 5)), reverse([1, 2, 3]), contains([1, 2], 2)]".to_string(),
+                expected: "[10, [3, 2, 1], true]".to_string(),
+            },
+            Test {
+                input: "let map = fn(x) { x }; map(5)".to_string(),
+                expected: "5".to_string(),
+            },
+        ];
+
+        reset_prelude();
+        for test in tests {
+            let actual = test_eval(&test.input);
+            if actual != test.expected {
+                panic!("{}: Expected {}, Got {}", test.input, test.expected, actual)
+            }
+        }
+    }
+
+    #[test]
+    fn test_custom_prelude() {
+        set_prelude(Some("let answer = 42;")).unwrap();
+        let actual = test_eval("[answer, len(\"ab\")]");
+        if actual != "[42, 2]" {
+            panic!("Expected [42, 2], Got {}", actual);
+        }
+
+        if set_prelude(Some("let answer 1;")).is_ok() {
+            panic!("Expected a broken prelude to be rejected");
+        }
+        let actual = test_eval("answer");
+        if actual != "42" {
+            panic!("Expected the previous prelude to stay. Got {}", actual);
+        }
+
+        set_prelude(None).unwrap();
+        let actual = test_eval("map");
+        if actual != "Error: identifier not found: map at line 1, column 1" {
+            panic!("Expected the prelude to be disabled. Got {}", actual);
+        }
+
+        reset_prelude();
+        let actual = test_eval("identity(1)");
+        if actual != "1" {
+            panic!("Expected the default prelude. Got {}", actual);
+        }
+    }
+}
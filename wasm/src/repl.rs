@@ -1,53 +1,521 @@
 use crate::{
-    eval::eval,
+    ast::{Node, Program},
+    complete::{completions, signature, word_start},
+    eval::{eval_in, new_environment, Limits, MAX_DEPTH},
     lexer::Lexer,
-    object::ObjectTrait,
+    object::{Environment, Object, ObjectTrait},
+    optimize::optimize,
     parser::Parser,
-    token::{Token, TokenType},
+    token::{Span, Token, TokenType},
 };
 use lazy_static::lazy_static;
-use std::io::{self, BufRead, Write};
+use rustyline::{
+    completion::{Completer, Pair},
+    error::ReadlineError,
+    highlight::Highlighter,
+    hint::{Hint, Hinter},
+    history::DefaultHistory,
+    validate::Validator,
+    Context, Editor, Helper,
+};
+use std::{cell::RefCell, env, fs, path::PathBuf, rc::Rc, time::Instant};
 lazy_static! {
     static ref EOF_TOKEN: Token = Token {
         r#type: TokenType::EOF,
         literal: "".to_string(),
+        span: Span::default(),
     };
 }
-pub fn start() {
+
+const PROMPT: &str = ">> ";
+const CONTINUATION_PROMPT: &str = ".. ";
+const HISTORY_FILE: &str = ".monkey_history";
+
+/// Runs the read-eval-print loop on stdin. With `dump_optimized` set, the
+/// optimized form of every input is printed before its result.
+pub fn start(dump_optimized: bool) {
+    let mut session = match Session::new(dump_optimized) {
+        Ok(session) => session,
+        Err(err) => {
+            eprintln!("{}", err);
+            return;
+        }
+    };
+
+    let mut editor = match Editor::new() {
+        Ok(editor) => editor,
+        Err(err) => {
+            eprintln!("cannot start the line editor: {}", err);
+            return;
+        }
+    };
+    editor.set_helper(Some(MonkeyHelper {
+        env: Rc::clone(session.env()),
+    }));
+
+    let history = history_path();
+    if let Some(path) = &history {
+        // There is no history file before the first session.
+        let _ = editor.load_history(path);
+    }
+
     println!("Hello! This is the Monkey programming language!",);
-    println!("Feel free to type commands");
-    print!(">> ");
-
-    io::stdout().flush().unwrap();
-
-    for line in io::stdin().lock().lines() {
-        if let Ok(line) = line {
-            let mut l = Lexer::new(line);
-            let mut p = Parser::new(&mut l);
-            let program = p.parse_program();
-
-            let program = match program {
-                Some(p) => p,
-                None => panic!("Can't parse program"),
-            };
-
-            let errors = p.errors();
-            if errors.len() > 0 {
-                print_parser_errors(&errors);
-            } else {
-                println!("{}", eval(program).inspect());
+    println!("Feel free to type commands, or :help for the REPL commands");
+
+    while let Some(input) = read_input(&mut editor) {
+        if input.trim().is_empty() {
+            continue;
+        }
+        let _ = editor.add_history_entry(input.as_str());
+
+        print!("{}", session.handle(&input));
+    }
+
+    if let Some(path) = &history {
+        if let Err(err) = editor.save_history(path) {
+            eprintln!("cannot save history to {}: {}", path.display(), err);
+        }
+    }
+}
+
+const COMMANDS: [&str; 7] = [
+    ":tokens", ":ast", ":type", ":env", ":load", ":time", ":help",
+];
+
+const HELP: &str = ":tokens <code>  print the tokens of <code>
+:ast <code>     print the syntax tree of <code>
+:type <expr>    evaluate <expr> and print the type of its value
+:env            list the bindings defined in this session
+:load <file>    evaluate a file in this session
+:time <expr>    evaluate <expr> and print how long it took
+:help           print this help
+";
+
+/// The bindings of one REPL run. Every input is evaluated in the same
+/// environment, so `let`s stay visible to later inputs.
+pub struct Session {
+    env: Rc<RefCell<Environment>>,
+    /// Slots below this one hold the prelude.
+    prelude_size: usize,
+    dump_optimized: bool,
+}
+
+impl Session {
+    pub fn new(dump_optimized: bool) -> Result<Session, String> {
+        let env = new_environment()?;
+        let prelude_size = env.borrow().frame_size();
+
+        Ok(Session {
+            env,
+            prelude_size,
+            dump_optimized,
+        })
+    }
+
+    pub fn env(&self) -> &Rc<RefCell<Environment>> {
+        &self.env
+    }
+
+    /// Runs one input, either code or a `:` command, and returns what should
+    /// be printed for it.
+    pub fn handle(&mut self, input: &str) -> String {
+        let input = input.trim();
+        let (command, arg) = match input.split_once(char::is_whitespace) {
+            Some((command, arg)) => (command, arg.trim()),
+            None => (input, ""),
+        };
+
+        match command {
+            ":tokens" => tokens(arg),
+            ":ast" => match parse(arg) {
+                Ok(program) => program.tree(),
+                Err(errors) => errors,
+            },
+            ":type" => match self.eval(arg) {
+                Ok((Object::ERROR(err), _)) => format!("{}\n", err.inspect()),
+                Ok((value, warnings)) => format!("{}{:?}\n", warnings, value.r#type()),
+                Err(errors) => errors,
+            },
+            ":env" => self.bindings(),
+            ":load" => match fs::read_to_string(arg) {
+                Ok(source) => self.eval_and_inspect(&source),
+                Err(err) => format!("cannot read {}: {}\n", arg, err),
+            },
+            ":time" => {
+                let started_at = Instant::now();
+                let output = self.eval_and_inspect(arg);
+                format!("{}took {:?}\n", output, started_at.elapsed())
+            }
+            ":help" => HELP.to_string(),
+            _ if command.starts_with(':') => {
+                format!("unknown command {}, try :help\n", command)
+            }
+            _ => self.eval_and_inspect(input),
+        }
+    }
+
+    /// Evaluates `source` and returns its value with the warnings to print
+    /// before it: names that aren't bound yet, which a later input may bind.
+    fn eval(&mut self, source: &str) -> Result<(Object, String), String> {
+        let program = optimize(parse(source)?);
+        if self.dump_optimized {
+            println!("{}", program.string());
+        }
+
+        let limits = Limits {
+            max_depth: Some(MAX_DEPTH),
+            ..Limits::default()
+        };
+        let (value, warnings) = eval_in(program, &self.env, limits);
+        let warnings = warnings
+            .iter()
+            .map(|w| format!("warning: {}\n", w))
+            .collect();
+        Ok((value, warnings))
+    }
+
+    fn eval_and_inspect(&mut self, source: &str) -> String {
+        match self.eval(source) {
+            // The error already names what wasn't found.
+            Ok((value @ Object::ERROR(_), _)) => format!("{}\n", value.inspect()),
+            Ok((value, warnings)) => format!("{}{}\n", warnings, value.inspect()),
+            Err(errors) => errors,
+        }
+    }
+
+    fn bindings(&self) -> String {
+        let env = self.env.borrow();
+        let mut names: Vec<(&String, usize)> = env
+            .names()
+            .filter(|&(_, index)| index >= self.prelude_size)
+            .collect();
+        names.sort_by_key(|&(_, index)| index);
+
+        let mut out = String::new();
+        for (name, index) in names {
+            if let Some(value) = env.get_at(0, index) {
+                out.push_str(&format!("{}: {:?}\n", name, value.r#type()));
+            }
+        }
+        out
+    }
+}
+
+fn parse(source: &str) -> Result<Program, String> {
+    let mut l = Lexer::new(source.to_string());
+    let mut p = Parser::new(&mut l);
+    let program = p.parse_program();
+
+    let program = match program {
+        Some(p) => p,
+        None => panic!("Can't parse program"),
+    };
+
+    let errors = p.errors();
+    if !errors.is_empty() {
+        return Err(parser_errors(&errors));
+    }
+
+    Ok(program)
+}
+
+fn tokens(source: &str) -> String {
+    let mut l = Lexer::new(source.to_string());
+    let mut out = String::new();
+
+    loop {
+        let tok = l.next_token();
+        if tok.r#type == TokenType::EOF {
+            return out;
+        }
+        out.push_str(&format!(
+            "{}:{}\t{:?}\t{}\n",
+            tok.span.line, tok.span.column, tok.r#type, tok.literal
+        ));
+    }
+}
+
+/// Completes bindings of the session, builtins, keywords and REPL commands,
+/// and hints the parameters of the function being called.
+struct MonkeyHelper {
+    env: Rc<RefCell<Environment>>,
+}
+
+impl Completer for MonkeyHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let start = word_start(line, pos);
+
+        if start == 1 && line.starts_with(':') {
+            let commands = COMMANDS
+                .iter()
+                .filter(|command| command[1..].starts_with(&line[1..pos]))
+                .map(|command| Pair {
+                    display: command.to_string(),
+                    replacement: command[1..].to_string(),
+                })
+                .collect();
+            return Ok((start, commands));
+        }
+
+        let candidates = completions(&self.env, &line[start..pos])
+            .into_iter()
+            .map(|c| Pair {
+                display: c.label(),
+                replacement: c.name,
+            })
+            .collect();
+        Ok((start, candidates))
+    }
+}
+
+/// A hint that is only displayed, never inserted.
+struct ParamsHint(String);
+
+impl Hint for ParamsHint {
+    fn display(&self) -> &str {
+        &self.0
+    }
+
+    fn completion(&self) -> Option<&str> {
+        None
+    }
+}
+
+impl Hinter for MonkeyHelper {
+    type Hint = ParamsHint;
+
+    fn hint(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> Option<ParamsHint> {
+        if pos < line.len() {
+            return None;
+        }
+        signature(&self.env, line, pos).map(|params| ParamsHint(format!("  {}", params)))
+    }
+}
+
+impl Highlighter for MonkeyHelper {}
+
+impl Validator for MonkeyHelper {}
+
+impl Helper for MonkeyHelper {}
+
+fn history_path() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(|home| PathBuf::from(home).join(HISTORY_FILE))
+}
+
+/// Reads lines until they form a complete input. Returns `None` at the end
+/// of input. Ctrl-C drops the lines read so far, an empty continuation line
+/// submits them as they are.
+fn read_input(editor: &mut Editor<MonkeyHelper, DefaultHistory>) -> Option<String> {
+    let mut input = String::new();
+
+    loop {
+        let prompt = if input.is_empty() {
+            PROMPT
+        } else {
+            CONTINUATION_PROMPT
+        };
+
+        match editor.readline(prompt) {
+            Ok(line) => {
+                if !input.is_empty() {
+                    // A blank line gives up on the input, unless it is part of a
+                    // multi-line string.
+                    if line.trim().is_empty() && !in_literal(&input) {
+                        return Some(input);
+                    }
+                    input.push('\n');
+                }
+                input.push_str(&line);
+
+                if !is_incomplete(&input) {
+                    return Some(input);
+                }
+            }
+            Err(ReadlineError::Interrupted) => input.clear(),
+            Err(ReadlineError::Eof) => return None,
+            Err(err) => {
+                eprintln!("cannot read input: {}", err);
+                return None;
             }
         }
+    }
+}
 
-        print!(">> ");
-        io::stdout().flush().unwrap();
+/// Whether `input` continues on the next line: a bracket or string is still
+/// open, or the last token is an operator waiting for its right operand.
+pub fn is_incomplete(input: &str) -> bool {
+    let mut l = Lexer::new(input.to_string());
+    let mut depth = 0;
+    let mut last = TokenType::EOF;
+
+    loop {
+        let tok = l.next_token();
+        match tok.r#type {
+            TokenType::EOF => break,
+            TokenType::LPAREN | TokenType::LBRACE | TokenType::LBRACKET => depth += 1,
+            TokenType::RPAREN | TokenType::RBRACE | TokenType::RBRACKET => depth -= 1,
+            _ => {}
+        }
+        last = tok.r#type;
     }
+
+    depth > 0
+        || l.unterminated()
+        || matches!(
+            last,
+            TokenType::ASSIGN
+                | TokenType::PLUS
+                | TokenType::MINUS
+                | TokenType::ASTERISK
+                | TokenType::SLASH
+                | TokenType::BANG
+                | TokenType::LT
+                | TokenType::GT
+                | TokenType::EQ
+                | TokenType::NOTEQ
+                | TokenType::AND
+                | TokenType::OR
+                | TokenType::COALESCE
+                | TokenType::PIPE
+                | TokenType::COMPOSE
+                | TokenType::DOT
+                | TokenType::DOTDOT
+                | TokenType::DOTDOTEQ
+                | TokenType::COMMA
+                | TokenType::COLON
+        )
 }
 
-fn print_parser_errors(errors: &[String]) {
-    println!("Woops! We ran into some monkey business here!");
-    println!("  parser errors:");
+/// Whether `input` ends inside a string or template.
+fn in_literal(input: &str) -> bool {
+    let mut l = Lexer::new(input.to_string());
+    while l.next_token().r#type != TokenType::EOF {}
+    l.unterminated()
+}
+
+fn parser_errors(errors: &[String]) -> String {
+    let mut out = "Woops! We ran into some monkey business here!\n  parser errors:\n".to_string();
     for err in errors {
-        println!("\t{}", err);
+        out.push_str(&format!("\t{}\n", err));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_incomplete, Session};
+
+    #[test]
+    fn test_is_incomplete() {
+        let tests = [
+            ("let x = 1;", false),
+            ("let f = fn(x) {", true),
+            ("let f = fn(x) {\n  x + 1\n};", false),
+            ("add(1,", true),
+            ("[1, 2", true),
+            ("{\"a\": 1", true),
+            ("let x = 1 +", true),
+            ("let x =", true),
+            ("x == ", true),
+            ("a &&", true),
+            ("a ||", true),
+            ("a ??", true),
+            ("a ?? b", false),
+            ("xs |>", true),
+            ("xs |> map(f)", false),
+            ("let h = f >>", true),
+            ("person.", true),
+            ("1..", true),
+            ("1..=", true),
+            ("1..=10", false),
+            ("\"unterminated", true),
+            ("\"done\"", false),
+            ("let s = `say \"hi`;", false),
+            ("let s = `line one", true),
+            ("let s = `a ${", true),
+            ("let s = `a ${ {\"k\": 1}[\"k\"] } b", true),
+            ("let s = `a ${x} b`", false),
+            ("let s = \"a`b\"", false),
+            ("}", false),
+            ("", false),
+        ];
+
+        for (input, expected) in tests {
+            if is_incomplete(input) != expected {
+                panic!("is_incomplete({:?}) should be {}", input, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_meta_commands() {
+        let file = std::env::temp_dir().join(format!("monkey-repl-{}.monkey", std::process::id()));
+        std::fs::write(&file, "let loaded = fn(x) { x };").unwrap();
+
+        let tests = [
+            ("let x = 5;".to_string(), "null\n".to_string()),
+            ("x * 2".to_string(), "10\n".to_string()),
+            (":type x".to_string(), "INTEGER\n".to_string()),
+            (":type \"a\" + \"b\"".to_string(), "STRING\n".to_string()),
+            (
+                ":type y".to_string(),
+                "Error: identifier not found: y at line 1, column 1\n".to_string(),
+            ),
+            (
+                ":tokens let a".to_string(),
+                "1:1\tLET\tlet\n1:5\tIDENT\ta\n".to_string(),
+            ),
+            (
+                ":ast -a".to_string(),
+                "Program\n  Expression\n    Prefix -\n      Identifier a\n".to_string(),
+            ),
+            (format!(":load {}", file.display()), "null\n".to_string()),
+            (
+                ":env".to_string(),
+                "x: INTEGER\nloaded: FUNCTION\n".to_string(),
+            ),
+            (
+                "loaded(map([1], fn(y) { y + x }))".to_string(),
+                "[6]\n".to_string(),
+            ),
+            (
+                "let f = fn() { g() };".to_string(),
+                "warning: identifier not found: g at line 1, column 16\nnull\n".to_string(),
+            ),
+            (
+                "f()".to_string(),
+                "Error: identifier not found: g at line 1, column 16\n".to_string(),
+            ),
+            ("let g = fn() { 1 };".to_string(), "null\n".to_string()),
+            ("f() + g()".to_string(), "2\n".to_string()),
+            (
+                ":nope".to_string(),
+                "unknown command :nope, try :help\n".to_string(),
+            ),
+        ];
+
+        let mut session = Session::new(false).unwrap();
+        for (input, expected) in tests {
+            let actual = session.handle(&input);
+            if actual != expected {
+                panic!("{}: Expected {:?}, Got {:?}", input, expected, actual);
+            }
+        }
+
+        let timed = session.handle(":time x + 1");
+        if !timed.starts_with("6\ntook ") {
+            panic!("unexpected :time output {:?}", timed);
+        }
+
+        std::fs::remove_file(&file).unwrap();
     }
 }
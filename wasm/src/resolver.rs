@@ -0,0 +1,470 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
+
+use crate::{
+    ast::{
+        BlockStatement, ElseBranch, ExportStatement, Identifier, IfExpression, MatchExpression,
+        Pattern, Program, Slot, Statement, EXPRESSION,
+    },
+    object::{Environment, BUILTINS},
+};
+
+/// The bindings of one runtime frame: the program's environment, the
+/// environment of a function call or of a `match` arm. Blocks don't open a
+/// frame.
+struct Scope {
+    names: HashMap<String, usize>,
+    size: usize,
+    /// `let` bindings of the frame whose statement hasn't been resolved yet.
+    /// Until it runs, the name still refers to a binding outside the frame.
+    pending: HashSet<String>,
+    /// Whether this is the frame of a function call. A function body only
+    /// runs once the frames around it are set up, so it sees all of their
+    /// bindings, pending or not.
+    function: bool,
+}
+
+impl Scope {
+    fn new(function: bool) -> Self {
+        Scope {
+            names: HashMap::new(),
+            size: 0,
+            pending: HashSet::new(),
+            function,
+        }
+    }
+
+    fn declare(&mut self, name: &str) -> usize {
+        if let Some(&index) = self.names.get(name) {
+            return index;
+        }
+
+        let index = self.size;
+        self.names.insert(name.to_string(), index);
+        self.size += 1;
+        index
+    }
+}
+
+/// Binds every identifier in `program` to the slot it will be evaluated from
+/// in `env`, reserving frame space in `env` for the program's own bindings.
+/// Returns an error for every identifier that is neither bound nor a builtin.
+pub fn resolve(program: &mut Program, env: &Rc<RefCell<Environment>>) -> Vec<String> {
+    let mut resolver = Resolver {
+        scopes: scopes_of(env),
+        errors: vec![],
+    };
+
+    resolver.declare_statements(&mut program.statements);
+    resolver.resolve_statements(&mut program.statements);
+
+    let size = resolver.scopes.last().map_or(0, |scope| scope.size);
+    env.borrow_mut().reserve(size);
+
+    resolver.errors
+}
+
+/// One scope per environment in the chain, outermost first.
+fn scopes_of(env: &Rc<RefCell<Environment>>) -> Vec<Scope> {
+    let env = env.borrow();
+
+    let mut scopes = match env.outer() {
+        Some(outer) => scopes_of(outer),
+        None => vec![],
+    };
+
+    scopes.push(Scope {
+        names: env
+            .names()
+            .map(|(name, index)| (name.clone(), index))
+            .collect(),
+        size: env.frame_size(),
+        pending: HashSet::new(),
+        function: false,
+    });
+
+    scopes
+}
+
+struct Resolver {
+    scopes: Vec<Scope>,
+    errors: Vec<String>,
+}
+
+impl Resolver {
+    fn current(&mut self) -> &mut Scope {
+        self.scopes.last_mut().expect("resolver has no scope")
+    }
+
+    /// `let` bindings are visible in their whole frame, so functions can
+    /// refer to bindings declared after them. Anything else sees them once
+    /// their statement is resolved, see `Scope::pending`.
+    fn declare_statements(&mut self, stmts: &mut [Statement]) {
+        for stmt in stmts.iter_mut() {
+            match stmt {
+                Statement::LETSTATEMENT(s)
+                | Statement::EXPORTSTATEMENT(ExportStatement { stmt: s, .. }) => {
+                    for name in s.pattern.bindings() {
+                        let scope = self.current();
+                        if !scope.names.contains_key(&name.value) {
+                            scope.pending.insert(name.value.clone());
+                        }
+                    }
+                    self.declare_pattern(&mut s.pattern);
+                    self.declare_expression(&mut s.value);
+                }
+                Statement::RETURNSTATEMENT(s) => self.declare_expression(&mut s.return_value),
+                Statement::EXPRESSIONSTATEMENT(s) => self.declare_expression(&mut s.expression),
+                Statement::IMPORTSTATEMENT(s) => {
+                    for name in s.alias.iter_mut().chain(s.names.iter_mut()) {
+                        self.declare_binding(name);
+                    }
+                }
+            }
+        }
+    }
+
+    fn declare_binding(&mut self, name: &mut Identifier) {
+        let index = self.current().declare(&name.value);
+        name.slot = Some(Slot { depth: 0, index });
+    }
+
+    fn declare_if(&mut self, e: &mut IfExpression) {
+        self.declare_expression(&mut e.condition);
+        self.declare_statements(&mut e.consequence.statements);
+        match &mut e.alternative {
+            Some(ElseBranch::BLOCK(alt)) => self.declare_statements(&mut alt.statements),
+            Some(ElseBranch::IF(alt)) => self.declare_if(alt),
+            None => {}
+        }
+    }
+
+    fn declare_expression(&mut self, exp: &mut EXPRESSION) {
+        match exp {
+            EXPRESSION::IF(e) => self.declare_if(e),
+            EXPRESSION::MATCH(e) => self.declare_expression(&mut e.value),
+            EXPRESSION::PREFIX(e) => self.declare_expression(&mut e.right),
+            EXPRESSION::INFIX(e) => {
+                self.declare_expression(&mut e.left);
+                self.declare_expression(&mut e.right);
+            }
+            EXPRESSION::CALL(e) => {
+                self.declare_expression(&mut e.function);
+                for arg in e.args.iter_mut() {
+                    self.declare_expression(arg);
+                }
+            }
+            EXPRESSION::DOT(e) => self.declare_expression(&mut e.left),
+            EXPRESSION::METHOD(e) => {
+                self.declare_expression(&mut e.receiver);
+                for arg in e.args.iter_mut() {
+                    self.declare_expression(arg);
+                }
+            }
+            EXPRESSION::ArrayLiteral(e) => {
+                for item in e.items.iter_mut() {
+                    self.declare_expression(item);
+                }
+            }
+            EXPRESSION::TEMPLATE(e) => {
+                for exp in e.expressions.iter_mut() {
+                    self.declare_expression(exp);
+                }
+            }
+            EXPRESSION::IndexExpression(e) => {
+                self.declare_expression(&mut e.left);
+                self.declare_expression(&mut e.index);
+            }
+            EXPRESSION::SLICE(e) => {
+                self.declare_expression(&mut e.left);
+                for bound in e.start.iter_mut().chain(e.end.iter_mut()) {
+                    self.declare_expression(bound);
+                }
+            }
+            EXPRESSION::HashLiteral(e) => {
+                for (k, v) in e.pairs.iter_mut() {
+                    self.declare_expression(k);
+                    self.declare_expression(v);
+                }
+            }
+            EXPRESSION::IDENTIFIER(_)
+            | EXPRESSION::INTEGER(_)
+            | EXPRESSION::BOOLEAN(_)
+            | EXPRESSION::NULL(_)
+            | EXPRESSION::FN(_)
+            | EXPRESSION::StringLiteral(_) => {}
+        }
+    }
+
+    fn resolve_statements(&mut self, stmts: &mut [Statement]) {
+        for stmt in stmts.iter_mut() {
+            match stmt {
+                Statement::LETSTATEMENT(s)
+                | Statement::EXPORTSTATEMENT(ExportStatement { stmt: s, .. }) => {
+                    self.resolve_expression(&mut s.value);
+                    for name in s.pattern.bindings() {
+                        self.current().pending.remove(&name.value);
+                    }
+                }
+                Statement::RETURNSTATEMENT(s) => self.resolve_expression(&mut s.return_value),
+                Statement::EXPRESSIONSTATEMENT(s) => self.resolve_expression(&mut s.expression),
+                Statement::IMPORTSTATEMENT(_) => {}
+            }
+        }
+    }
+
+    fn resolve_block(&mut self, block: &mut BlockStatement) {
+        self.resolve_statements(&mut block.statements);
+    }
+
+    fn resolve_if(&mut self, e: &mut IfExpression) {
+        self.resolve_expression(&mut e.condition);
+        self.resolve_block(&mut e.consequence);
+        match &mut e.alternative {
+            Some(ElseBranch::BLOCK(alt)) => self.resolve_block(alt),
+            Some(ElseBranch::IF(alt)) => self.resolve_if(alt),
+            None => {}
+        }
+    }
+
+    fn resolve_expression(&mut self, exp: &mut EXPRESSION) {
+        match exp {
+            EXPRESSION::IDENTIFIER(ident) => self.resolve_identifier(ident),
+            EXPRESSION::INTEGER(_)
+            | EXPRESSION::BOOLEAN(_)
+            | EXPRESSION::NULL(_)
+            | EXPRESSION::StringLiteral(_) => {}
+            EXPRESSION::PREFIX(e) => self.resolve_expression(&mut e.right),
+            EXPRESSION::INFIX(e) => {
+                self.resolve_expression(&mut e.left);
+                self.resolve_expression(&mut e.right);
+            }
+            EXPRESSION::IF(e) => self.resolve_if(e),
+            EXPRESSION::MATCH(e) => self.resolve_match(e),
+            EXPRESSION::FN(e) => {
+                self.scopes.push(Scope::new(true));
+
+                for param in e.parameters.iter_mut() {
+                    self.declare_pattern(param);
+                }
+                self.declare_statements(&mut e.body.statements);
+                self.resolve_block(&mut e.body);
+
+                self.scopes.pop();
+            }
+            EXPRESSION::CALL(e) => {
+                self.resolve_expression(&mut e.function);
+                for arg in e.args.iter_mut() {
+                    self.resolve_expression(arg);
+                }
+            }
+            EXPRESSION::DOT(e) => self.resolve_expression(&mut e.left),
+            EXPRESSION::METHOD(e) => {
+                self.resolve_expression(&mut e.receiver);
+                self.resolve_method(&mut e.method);
+                for arg in e.args.iter_mut() {
+                    self.resolve_expression(arg);
+                }
+            }
+            EXPRESSION::ArrayLiteral(e) => {
+                for item in e.items.iter_mut() {
+                    self.resolve_expression(item);
+                }
+            }
+            EXPRESSION::TEMPLATE(e) => {
+                for exp in e.expressions.iter_mut() {
+                    self.resolve_expression(exp);
+                }
+            }
+            EXPRESSION::IndexExpression(e) => {
+                self.resolve_expression(&mut e.left);
+                self.resolve_expression(&mut e.index);
+            }
+            EXPRESSION::SLICE(e) => {
+                self.resolve_expression(&mut e.left);
+                for bound in e.start.iter_mut().chain(e.end.iter_mut()) {
+                    self.resolve_expression(bound);
+                }
+            }
+            EXPRESSION::HashLiteral(e) => {
+                for (k, v) in e.pairs.iter_mut() {
+                    self.resolve_expression(k);
+                    self.resolve_expression(v);
+                }
+            }
+        }
+    }
+
+    fn resolve_match(&mut self, e: &mut MatchExpression) {
+        self.resolve_expression(&mut e.value);
+
+        for arm in e.arms.iter_mut() {
+            self.scopes.push(Scope::new(false));
+
+            self.declare_pattern(&mut arm.pattern);
+            if let Some(guard) = &mut arm.guard {
+                self.declare_expression(guard);
+                self.resolve_expression(guard);
+            }
+            self.declare_expression(&mut arm.body);
+            self.resolve_expression(&mut arm.body);
+
+            self.scopes.pop();
+        }
+    }
+
+    fn declare_pattern(&mut self, pattern: &mut Pattern) {
+        match pattern {
+            Pattern::BINDING(name) => self.declare_binding(name),
+            Pattern::ARRAY(p) => {
+                for item in p.items.iter_mut() {
+                    self.declare_pattern(item);
+                }
+                if let Some(rest) = &mut p.rest {
+                    self.declare_pattern(rest);
+                }
+            }
+            Pattern::HASH(p) => {
+                for (_, item) in p.pairs.iter_mut() {
+                    self.declare_pattern(item);
+                }
+            }
+            Pattern::WILDCARD(_) | Pattern::LITERAL(_) => {}
+        }
+    }
+
+    /// Like `resolve_identifier`, but an unbound name may still be a field of
+    /// a hash receiver, so it is left for the evaluator to report.
+    fn resolve_method(&mut self, ident: &mut Identifier) {
+        let errors = self.errors.len();
+        self.resolve_identifier(ident);
+        self.errors.truncate(errors);
+    }
+
+    fn resolve_identifier(&mut self, ident: &mut Identifier) {
+        let mut in_function = false;
+        for (depth, scope) in self.scopes.iter().rev().enumerate() {
+            if let Some(&index) = scope.names.get(&ident.value) {
+                if in_function || !scope.pending.contains(&ident.value) {
+                    ident.slot = Some(Slot { depth, index });
+                    return;
+                }
+            }
+            in_function |= scope.function;
+        }
+
+        ident.slot = None;
+
+        if !BUILTINS.contains_key(ident.value.as_str()) {
+            self.errors.push(format!(
+                "identifier not found: {} at line {}, column {}",
+                ident.value, ident.token.span.line, ident.token.span.column
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::resolve;
+    use crate::ast::{Slot, Statement, EXPRESSION};
+    use crate::gc;
+    use crate::lexer::Lexer;
+    use crate::object::Environment;
+    use crate::parser::Parser;
+
+    #[test]
+    fn test_resolve_slots() {
+        let input = "let a = 1;
+                     let f = fn(x, y) { let z = x; fn() { a + y + z } };"
+            .to_string();
+
+        let mut l = Lexer::new(input);
+        let mut p = Parser::new(&mut l);
+        let mut program = p.parse_program().unwrap();
+        let env = gc::track(Environment::new());
+
+        let errors = resolve(&mut program, &env);
+        if !errors.is_empty() {
+            panic!("unexpected resolver errors: {:?}", errors);
+        }
+
+        let f = match &program.statements[1] {
+            Statement::LETSTATEMENT(s) => match &s.value {
+                EXPRESSION::FN(f) => f,
+                other => panic!("expected fn literal. Got {:?}", other),
+            },
+            other => panic!("expected let statement. Got {:?}", other),
+        };
+
+        let inner = match &f.body.statements[1] {
+            Statement::EXPRESSIONSTATEMENT(s) => match &s.expression {
+                EXPRESSION::FN(inner) => inner,
+                other => panic!("expected fn literal. Got {:?}", other),
+            },
+            other => panic!("expected expression statement. Got {:?}", other),
+        };
+
+        // a + y + z parses as ((a + y) + z)
+        let (a_plus_y, z) = match &inner.body.statements[0] {
+            Statement::EXPRESSIONSTATEMENT(s) => match &s.expression {
+                EXPRESSION::INFIX(e) => (&*e.left, &*e.right),
+                other => panic!("expected infix expression. Got {:?}", other),
+            },
+            other => panic!("expected expression statement. Got {:?}", other),
+        };
+        let (a, y) = match a_plus_y {
+            EXPRESSION::INFIX(e) => (&*e.left, &*e.right),
+            other => panic!("expected infix expression. Got {:?}", other),
+        };
+
+        let tests = [
+            (a, Slot { depth: 2, index: 0 }),
+            (y, Slot { depth: 1, index: 1 }),
+            (z, Slot { depth: 1, index: 2 }),
+        ];
+
+        for (exp, expected) in tests {
+            match exp {
+                EXPRESSION::IDENTIFIER(ident) => {
+                    if ident.slot != Some(expected) {
+                        panic!(
+                            "{} resolved to {:?}, want {:?}",
+                            ident.value, ident.slot, expected
+                        );
+                    }
+                }
+                other => panic!("expected identifier. Got {:?}", other),
+            }
+        }
+
+        if env.borrow().frame_size() != 2 {
+            panic!("program frame not reserved");
+        }
+    }
+
+    #[test]
+    fn test_resolve_errors() {
+        let input = "let f = fn() { g() };
+                     len(f) + h;"
+            .to_string();
+
+        let mut l = Lexer::new(input);
+        let mut p = Parser::new(&mut l);
+        let mut program = p.parse_program().unwrap();
+        let env = gc::track(Environment::new());
+
+        let errors = resolve(&mut program, &env);
+        let expected = vec![
+            "identifier not found: g at line 1, column 16".to_string(),
+            "identifier not found: h at line 2, column 31".to_string(),
+        ];
+
+        if errors != expected {
+            panic!("Expected {:?}. Got {:?}", expected, errors);
+        }
+    }
+}
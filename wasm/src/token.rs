@@ -1,8 +1,9 @@
 use std::collections::HashMap;
 
 use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
 
-#[derive(Eq, Hash, Debug, PartialEq, Clone, Copy)]
+#[derive(Eq, Hash, Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub enum TokenType {
     LET,
     FUNCTION,
@@ -35,10 +36,32 @@ pub enum TokenType {
     NOTEQ,
     STRING,
     COLON,
+    IMPORT,
+    EXPORT,
+    NULL,
+    AND,
+    OR,
+    COALESCE,
+    MATCH,
+    ARROW,
+    ELLIPSIS,
+    DOT,
+    PIPE,
+    COMPOSE,
+    DOTDOT,
+    DOTDOTEQ,
+    /// A backtick string without `${}`.
+    TEMPLATE,
+    /// The text of a backtick string up to its first `${`.
+    TEMPLATEHEAD,
+    /// The text between a `}` and the next `${` of a backtick string.
+    TEMPLATEMIDDLE,
+    /// The text from the last `}` to the end of a backtick string.
+    TEMPLATETAIL,
 }
 
 lazy_static! {
-    static ref KEYWORDS: HashMap<&'static str, TokenType> = {
+    pub static ref KEYWORDS: HashMap<&'static str, TokenType> = {
         let mut keywords = HashMap::new();
         keywords.insert("fn", TokenType::FUNCTION);
         keywords.insert("let", TokenType::LET);
@@ -47,21 +70,44 @@ lazy_static! {
         keywords.insert("if", TokenType::IF);
         keywords.insert("else", TokenType::ELSE);
         keywords.insert("return", TokenType::RETURN);
+        keywords.insert("import", TokenType::IMPORT);
+        keywords.insert("export", TokenType::EXPORT);
+        keywords.insert("null", TokenType::NULL);
+        keywords.insert("match", TokenType::MATCH);
         keywords
     };
 }
 
+/// Location of a token in the source. `start` and `end` are byte offsets,
+/// `line` and `column` are 1-based and point at `start`.
+#[derive(Debug, PartialEq, Clone, Copy, Eq, Default, Serialize, Deserialize)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// A `//` comment, running to the end of the line. The parser never sees
+/// comments, the lexer keeps them for tools like the formatter.
 #[derive(Debug, PartialEq, Clone, Eq)]
+pub struct Comment {
+    pub text: String,
+    pub span: Span,
+}
+
+#[derive(Debug, PartialEq, Clone, Eq, Serialize, Deserialize)]
 pub struct Token {
     pub r#type: TokenType,
     pub literal: String,
+    pub span: Span,
 }
 
 impl Token {
     pub fn lookup_ident(input: &str) -> TokenType {
         let mut token_type: TokenType = TokenType::IDENT;
         if let Some(r#type) = KEYWORDS.get(input) {
-            token_type = r#type.clone();
+            token_type = *r#type;
         }
         token_type
     }